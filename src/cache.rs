@@ -1,7 +1,11 @@
+pub mod annotated;
 pub mod input;
 pub mod join;
 pub mod meta_lines;
 pub mod misses;
 pub mod output;
+pub mod region_index;
 pub mod regions;
 pub(crate) mod run;
+pub mod stream;
+pub mod variant_records;
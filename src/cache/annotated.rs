@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+use std::io::{Write, BufWriter};
+use std::sync::Arc;
+use fs_err::File;
+use crate::util::error::Error;
+use crate::cache::input::InputVariant;
+use crate::cache::region_index::RegionIndex;
+use crate::tabix::tsv::ColSelection;
+
+const REGIONS_KEY: &str = "Regions";
+const VCF_VERSION_LINE: &str = "##fileformat=VCFv4.2";
+const VCF_HEADER_LINE: &str = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO";
+const I_FIELD_INFO: usize = 7;
+const INFO_ID_PREFIX: &str = "##INFO=<ID=";
+
+#[derive(Clone, Copy)]
+pub(crate) enum AnnotateMode {
+    Info,
+    Columns,
+}
+
+// i_col_opt is the column in the data file, or None for the names of overlapping regions
+struct AnnotatedCol {
+    i_col_opt: Option<usize>,
+    key: String,
+}
+
+// Hits for the alleles of one input line, collected until the next line comes along
+struct PendingLine {
+    i_line: usize,
+    line: Arc<String>,
+    values_by_allele: Vec<Option<Vec<String>>>,
+}
+
+pub(crate) struct AnnotatedOutput {
+    write: Box<dyn Write>,
+    mode: AnnotateMode,
+    cols: Vec<AnnotatedCol>,
+    region_index_opt: Option<RegionIndex>,
+    pending_opt: Option<PendingLine>,
+}
+
+impl AnnotateMode {
+    pub(crate) fn parse(mode: &str) -> Result<AnnotateMode, Error> {
+        match mode {
+            "info" => { Ok(AnnotateMode::Info) }
+            "columns" => { Ok(AnnotateMode::Columns) }
+            _ => {
+                Err(Error::from(format!("Unknown annotate mode '{}', need 'info' or 'columns'.",
+                                        mode)))
+            }
+        }
+    }
+}
+
+// INFO keys need to match [A-Za-z_][0-9A-Za-z_.]*
+fn to_key(col_name: &str) -> String {
+    let key: String =
+        col_name.chars().map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' { c } else { '_' }
+        }).collect();
+    if key.starts_with(|c: char| { c.is_ascii_alphabetic() || c == '_' }) {
+        key
+    } else {
+        format!("_{}", key)
+    }
+}
+
+// percent-encodes characters that have a meaning in INFO values
+fn encode_value(value: &str) -> String {
+    if value.is_empty() {
+        return String::from(".");
+    }
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => { encoded.push_str("%25") }
+            ';' => { encoded.push_str("%3B") }
+            '=' => { encoded.push_str("%3D") }
+            ',' => { encoded.push_str("%2C") }
+            '&' => { encoded.push_str("%26") }
+            ' ' => { encoded.push_str("%20") }
+            _ => { encoded.push(c) }
+        }
+    }
+    encoded
+}
+
+// IDs of the INFO keys the input header already defines
+fn info_ids<'a>(meta_lines: &[&'a String]) -> HashSet<&'a str> {
+    meta_lines.iter().filter_map(|line| { line.strip_prefix(INFO_ID_PREFIX) }).map(|rest| {
+        rest.split([',', '>']).next().unwrap_or(rest)
+    }).collect()
+}
+
+fn count_alleles(line: &str) -> usize {
+    line.split('\t').nth(4).map_or(1, |alt_alleles| { alt_alleles.split(',').count() })
+}
+
+impl PendingLine {
+    fn new(i_line: usize, line: &Arc<String>) -> PendingLine {
+        let n_alleles = count_alleles(line);
+        PendingLine { i_line, line: line.clone(), values_by_allele: vec![None; n_alleles] }
+    }
+}
+
+// annotations must not clash with each other or with the input, or the output is no valid VCF
+fn check_keys(cols: &[AnnotatedCol], meta_lines: &[&String], header_line: &str,
+              mode: AnnotateMode) -> Result<(), Error> {
+    let mut keys: HashSet<&str> = HashSet::new();
+    for col in cols {
+        if !keys.insert(col.key.as_str()) {
+            return Err(Error::from(format!("Annotation key {} is given more than once.",
+                                           col.key)));
+        }
+    }
+    match mode {
+        AnnotateMode::Info => {
+            if let Some(id) = info_ids(meta_lines).into_iter().find(|id| { keys.contains(id) }) {
+                return Err(Error::from(format!("Input already defines INFO key {}, rename the \
+                column with --columns.", id)));
+            }
+        }
+        AnnotateMode::Columns => {
+            if header_line.split('\t').count() > I_FIELD_INFO + 1 {
+                return Err(Error::from("Cannot append columns after FORMAT or sample columns, \
+                use --annotate info instead."));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl AnnotatedOutput {
+    pub(crate) fn new(mut write: Box<dyn Write>, input_header_lines: &[String],
+                      col_selection: ColSelection, mode: AnnotateMode,
+                      region_index_opt: Option<RegionIndex>) -> Result<AnnotatedOutput, Error> {
+        let mut cols: Vec<AnnotatedCol> =
+            col_selection.cols.iter().map(|(i_col, col_name)| {
+                AnnotatedCol { i_col_opt: Some(*i_col), key: to_key(col_name) }
+            }).collect();
+        if region_index_opt.is_some() {
+            cols.push(AnnotatedCol { i_col_opt: None, key: String::from(REGIONS_KEY) });
+        }
+        let meta_lines: Vec<&String> =
+            input_header_lines.iter().filter(|line| { line.starts_with("##") }).collect();
+        let header_line =
+            input_header_lines.iter().find(|line| { !line.starts_with("##") })
+                .map_or(VCF_HEADER_LINE, |line| { line.as_str() });
+        check_keys(&cols, &meta_lines, header_line, mode)?;
+        if !meta_lines.first().is_some_and(|line| { line.starts_with("##fileformat=") }) {
+            writeln!(write, "{}", VCF_VERSION_LINE)?;
+        }
+        for meta_line in meta_lines {
+            writeln!(write, "{}", meta_line)?;
+        }
+        match mode {
+            AnnotateMode::Info => {
+                for col in &cols {
+                    let description =
+                        match col.i_col_opt {
+                            Some(_) => {
+                                format!("{} from the data file, '&'-separated for multiple hits",
+                                        col.key)
+                            }
+                            None => { String::from("Names of overlapping regions, '&'-separated") }
+                        };
+                    writeln!(write, "##INFO=<ID={},Number=A,Type=String,Description=\"{}\">",
+                             col.key, description)?;
+                }
+                writeln!(write, "{}", header_line)?;
+            }
+            AnnotateMode::Columns => {
+                let keys: Vec<&str> = cols.iter().map(|col| { col.key.as_str() }).collect();
+                writeln!(write, "{}\t{}", header_line, keys.join("\t"))?;
+            }
+        }
+        Ok(AnnotatedOutput { write, mode, cols, region_index_opt, pending_opt: None })
+    }
+    pub(crate) fn from_file(out_file: String, input_header_lines: &[String],
+                            col_selection: ColSelection, mode: AnnotateMode,
+                            region_index_opt: Option<RegionIndex>)
+                            -> Result<AnnotatedOutput, Error> {
+        let write: Box<dyn Write> = Box::new(BufWriter::new(File::create(out_file)?));
+        AnnotatedOutput::new(write, input_header_lines, col_selection, mode, region_index_opt)
+    }
+    fn values_for(&self, input_variant: &InputVariant, lines: &[String]) -> Vec<String> {
+        let hits: Vec<Vec<&str>> =
+            lines.iter().map(|line| {
+                line.trim_end_matches(&['\n', '\r'][..]).split('\t').collect()
+            }).collect();
+        self.cols.iter().map(|col| {
+            let values: Vec<String> =
+                match col.i_col_opt {
+                    Some(i_col) => {
+                        hits.iter().map(|fields| {
+                            encode_value(fields.get(i_col).cloned().unwrap_or(""))
+                        }).collect()
+                    }
+                    None => {
+                        self.region_index_opt.iter().flat_map(|region_index| {
+                            region_index.overlapping_names(&input_variant.variant)
+                        }).map(encode_value).collect()
+                    }
+                };
+            if values.is_empty() { String::from(".") } else { values.join("&") }
+        }).collect()
+    }
+    fn pending_for(&mut self, i_line: usize, line: &Arc<String>)
+                   -> Result<&mut PendingLine, Error> {
+        if self.pending_opt.as_ref().is_some_and(|pending| { pending.i_line != i_line }) {
+            self.write_pending()?;
+        }
+        Ok(self.pending_opt.get_or_insert_with(|| { PendingLine::new(i_line, line) }))
+    }
+    pub(crate) fn add_result(&mut self, input_variant: &InputVariant, lines: &[String])
+                             -> Result<(), Error> {
+        let values = self.values_for(input_variant, lines);
+        let pending = self.pending_for(input_variant.i_line, &input_variant.line)?;
+        if let Some(slot) = pending.values_by_allele.get_mut(input_variant.i_allele - 1) {
+            *slot = Some(values);
+        }
+        Ok(())
+    }
+    // for input lines without variants that were looked up, which are written as they are
+    pub(crate) fn add_line(&mut self, i_line: usize, line: &Arc<String>) -> Result<(), Error> {
+        self.pending_for(i_line, line)?;
+        Ok(())
+    }
+    fn write_pending(&mut self) -> Result<(), Error> {
+        let pending = match self.pending_opt.take() {
+            None => { return Ok(()) }
+            Some(pending) => { pending }
+        };
+        let per_col: Vec<String> =
+            (0..self.cols.len()).map(|i_col| {
+                let values: Vec<&str> =
+                    pending.values_by_allele.iter().map(|values_opt| {
+                        values_opt.as_ref().map_or(".", |values| { values[i_col].as_str() })
+                    }).collect();
+                values.join(",")
+            }).collect();
+        let line = pending.line.trim_end_matches(&['\n', '\r'][..]);
+        match self.mode {
+            AnnotateMode::Info => {
+                let entries: Vec<String> =
+                    self.cols.iter().zip(per_col.iter()).filter(|(_, value)| {
+                        value.split(',').any(|value| { value != "." })
+                    }).map(|(col, value)| { format!("{}={}", col.key, value) }).collect();
+                if entries.is_empty() {
+                    writeln!(self.write, "{}", line)?;
+                    return Ok(());
+                }
+                let mut fields: Vec<&str> = line.split('\t').collect();
+                while fields.len() <= I_FIELD_INFO {
+                    fields.push(".");
+                }
+                let info =
+                    match fields[I_FIELD_INFO] {
+                        "." | "" => { entries.join(";") }
+                        info => {
+                            if let Some(key) = info.split(';').filter_map(|entry| {
+                                entry.split('=').next()
+                            }).find(|key| { self.cols.iter().any(|col| { col.key == *key }) }) {
+                                return Err(Error::from(format!(
+                                    "INFO key {} of input line {} is also an annotation key.",
+                                    key, pending.i_line)));
+                            }
+                            format!("{};{}", info, entries.join(";"))
+                        }
+                    };
+                fields[I_FIELD_INFO] = &info;
+                writeln!(self.write, "{}", fields.join("\t"))?;
+            }
+            AnnotateMode::Columns => {
+                writeln!(self.write, "{}\t{}", line, per_col.join("\t"))?;
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn finish(&mut self) -> Result<(), Error> {
+        self.write_pending()?;
+        self.write.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::cache::annotated::{to_key, AnnotateMode, AnnotatedOutput};
+    use crate::cache::input::InputVariant;
+    use crate::genomics::variant::Variant;
+    use crate::tabix::tsv::ColSelection;
+    use crate::util::error::Error;
+
+    const DATA_HEADER: &str = "#chrom\tpos\tref\talt\tscore\tnote";
+    const INPUT_LINE: &str = "1\t100\t.\tA\tG,T\t.\t.\tDP=3";
+    const UNUSED_LINE: &str = "2\t5\t.\tC\t.\t.\t.\t.";
+
+    fn input_variant(line: &Arc<String>, alt_allele: &str, i_allele: usize) -> InputVariant {
+        let variant =
+            Variant::new(String::from("1"), 100, String::from("A"), String::from(alt_allele));
+        InputVariant::new(variant, 2, i_allele, line.clone())
+    }
+
+    fn annotate(mode: AnnotateMode, file: &str) -> Result<String, Error> {
+        let header_lines =
+            vec!(String::from("##fileformat=VCFv4.2"),
+                 String::from("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO"));
+        let columns = vec!(String::from("score"), String::from("note:Note Text"));
+        let col_selection = ColSelection::parse(DATA_HEADER, &columns, &[])?;
+        let mut annotated =
+            AnnotatedOutput::from_file(String::from(file), &header_lines, col_selection, mode,
+                                       None)?;
+        let line = Arc::new(String::from(INPUT_LINE));
+        let hits =
+            vec!(String::from("1\t100\tA\tG\t0.5\ta;b=c,d\n"),
+                 String::from("1\t100\tA\tG\t0.7\t\n"));
+        annotated.add_result(&input_variant(&line, "G", 1), &hits)?;
+        annotated.add_result(&input_variant(&line, "T", 2), &[])?;
+        annotated.add_line(3, &Arc::new(String::from(UNUSED_LINE)))?;
+        annotated.finish()?;
+        Ok(std::fs::read_to_string(file)?)
+    }
+
+    #[test]
+    fn keys_for_column_names() {
+        assert_eq!(to_key("score"), "score");
+        assert_eq!(to_key("Note Text"), "Note_Text");
+        assert_eq!(to_key("1kg.af"), "_1kg.af");
+    }
+
+    #[test]
+    fn annotate_info() -> Result<(), Error> {
+        let expected =
+            "##fileformat=VCFv4.2\n\
+            ##INFO=<ID=score,Number=A,Type=String,Description=\"score from the data file, \
+            '&'-separated for multiple hits\">\n\
+            ##INFO=<ID=Note_Text,Number=A,Type=String,Description=\"Note_Text from the data \
+            file, '&'-separated for multiple hits\">\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            1\t100\t.\tA\tG,T\t.\t.\tDP=3;score=0.5&0.7,.;Note_Text=a%3Bb%3Dc%2Cd&.,.\n\
+            2\t5\t.\tC\t.\t.\t.\t.\n";
+        assert_eq!(annotate(AnnotateMode::Info, "tmp/annotated_info.vcf")?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn annotate_columns() -> Result<(), Error> {
+        let expected =
+            "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tscore\tNote_Text\n\
+            1\t100\t.\tA\tG,T\t.\t.\tDP=3\t0.5&0.7,.\ta%3Bb%3Dc%2Cd&.,.\n\
+            2\t5\t.\tC\t.\t.\t.\t.\t.\t.\n";
+        assert_eq!(annotate(AnnotateMode::Columns, "tmp/annotated_columns.vcf")?, expected);
+        Ok(())
+    }
+
+    fn open_annotated(header_lines: &[&str], columns: &[&str], mode: AnnotateMode)
+                      -> Result<AnnotatedOutput, Error> {
+        let header_lines: Vec<String> =
+            header_lines.iter().map(|line| { line.to_string() }).collect();
+        let columns: Vec<String> = columns.iter().map(|column| { column.to_string() }).collect();
+        let col_selection = ColSelection::parse(DATA_HEADER, &columns, &[])?;
+        AnnotatedOutput::new(Box::new(Vec::new()), &header_lines, col_selection, mode, None)
+    }
+
+    #[test]
+    fn reject_clashing_annotations() -> Result<(), Error> {
+        let info_line = "##INFO=<ID=score,Number=1,Type=Float,Description=\"Score\">";
+        let sites_header = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO";
+        let samples_header = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1";
+        assert!(open_annotated(&[info_line, sites_header], &["score"], AnnotateMode::Info)
+            .is_err());
+        assert!(open_annotated(&[info_line, sites_header], &["score:data_score"],
+                               AnnotateMode::Info).is_ok());
+        assert!(open_annotated(&[sites_header], &["score:a b", "note:a_b"], AnnotateMode::Info)
+            .is_err());
+        assert!(open_annotated(&[samples_header], &["score"], AnnotateMode::Info).is_ok());
+        assert!(open_annotated(&[samples_header], &["score"], AnnotateMode::Columns).is_err());
+        let mut annotated = open_annotated(&[sites_header], &["score"], AnnotateMode::Info)?;
+        let line = Arc::new(String::from("1\t100\t.\tA\tG\t.\t.\tscore=1"));
+        let hits = vec!(String::from("1\t100\tA\tG\t0.5\tx\n"));
+        annotated.add_result(&input_variant(&line, "G", 1), &hits)?;
+        assert!(annotated.finish().is_err());
+        Ok(())
+    }
+}
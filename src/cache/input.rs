@@ -1,33 +1,196 @@
 use std::io::{Read, BufReader, BufRead};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
 use crate::genomics::variant::Variant;
 use crate::util::error::Error;
 use fs_err::File;
+use flate2::bufread::MultiGzDecoder;
 use crate::genomics::variant;
-use crate::util::error;
+use crate::util::error_policy::ErrorPolicy;
+use crate::genomics::chrom_aliases::ChromAliases;
+
+const STDIN_FILE: &str = "-";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
 pub(crate) struct Input {
-    reader: BufReader<Box<dyn Read>>
+    reader: BufReader<Box<dyn Read>>,
+    pub(crate) header_lines: Vec<String>,
+    chrom_aliases: ChromAliases,
+    n_errors: Rc<Cell<usize>>,
+}
+
+pub(crate) struct InputVariant {
+    pub(crate) variant: Variant,
+    pub(crate) i_line: usize,
+    pub(crate) i_allele: usize,
+    pub(crate) line: Arc<String>,
+}
+
+// an input line yields one record per ALT allele to look up, or one unused record if it has
+// none, or was skipped as malformed, so that annotated output can still write it
+pub(crate) enum InputRecord {
+    Variant(InputVariant),
+    Unused(usize, Arc<String>),
+}
+
+impl InputRecord {
+    pub(crate) fn i_line(&self) -> usize {
+        match self {
+            InputRecord::Variant(input_variant) => { input_variant.i_line }
+            InputRecord::Unused(i_line, _) => { *i_line }
+        }
+    }
+}
+
+impl InputVariant {
+    pub(crate) fn new(variant: Variant, i_line: usize, i_allele: usize, line: Arc<String>)
+                      -> InputVariant {
+        InputVariant { variant, i_line, i_allele, line }
+    }
 }
 
 impl Input {
-    pub(crate) fn from_file(file: &str) -> Result<Input, Error> {
-        let inner: Box<dyn Read> = Box::new(File::open(file)?);
-        let reader = BufReader::new(inner);
-        Ok(Input { reader })
-    }
-    pub(crate) fn variants<'a>(self) -> impl Iterator<Item=(Variant, String)> + 'a {
-        self.reader.lines().filter_map(|line_res|{
-             match line_res {
-                Ok(line) => Some(line),
-                Err(io_error) => {
-                    println!("Error: {}", Error::IO(io_error));
-                    None
+    pub(crate) fn from_file(file: &str, chrom_aliases: ChromAliases) -> Result<Input, Error> {
+        let raw: Box<dyn Read> =
+            if file == STDIN_FILE {
+                Box::new(std::io::stdin())
+            } else {
+                Box::new(File::open(file)?)
+            };
+        Input::from_read(raw, chrom_aliases)
+    }
+    // plain or gzipped VCF, from a file or stdin
+    fn from_read(raw: Box<dyn Read>, chrom_aliases: ChromAliases) -> Result<Input, Error> {
+        let mut raw_reader = BufReader::new(raw);
+        let is_gzipped = raw_reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+        let inner: Box<dyn Read> =
+            if is_gzipped {
+                Box::new(MultiGzDecoder::new(raw_reader))
+            } else {
+                Box::new(raw_reader)
+            };
+        let mut reader = BufReader::new(inner);
+        let mut header_lines = Vec::<String>::new();
+        while reader.fill_buf()?.first() == Some(&b'#') {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            header_lines.push(String::from(line.trim_end_matches(&['\n', '\r'][..])));
+        }
+        let n_errors = Rc::new(Cell::new(0));
+        Ok(Input { reader, header_lines, chrom_aliases, n_errors })
+    }
+    // number of lines that could not be read or parsed, so far
+    pub(crate) fn error_counter(&self) -> Rc<Cell<usize>> { self.n_errors.clone() }
+    pub(crate) fn records<'a>(self, error_policy: Arc<ErrorPolicy>)
+                              -> impl Iterator<Item=Result<InputRecord, Error>> + 'a {
+        let chrom_aliases = self.chrom_aliases;
+        let n_header_lines = self.header_lines.len();
+        let n_errors = self.n_errors;
+        self.reader.lines().enumerate().filter_map(move |(i, line_res)| {
+            let i_line = n_header_lines + i + 1;
+            let (line, variants_res) =
+                match line_res {
+                    Ok(line) if line.starts_with('#') => { return None }
+                    Ok(line) => {
+                        let variants_res = variant::parse_vcf_line(&line);
+                        (line, variants_res)
+                    }
+                    Err(io_error) => { return Some(Err(Error::from(io_error))) }
+                };
+            match variants_res {
+                Ok(variants) => { Some(Ok((i_line, variants, Arc::new(line)))) }
+                Err(error) => {
+                    n_errors.set(n_errors.get() + 1);
+                    let source = format!("input line {}", i_line);
+                    match error_policy.handle(&source, &line, error) {
+                        Ok(()) => { Some(Ok((i_line, Vec::new(), Arc::new(line)))) }
+                        Err(error) => { Some(Err(error)) }
+                    }
                 }
             }
         })
-            .filter(|line| { ! line.starts_with('#')})
-            .map(variant::parse_vcf_line)
-            .filter_map(error::handle_result)
+            .flat_map(move |parsed_res| {
+                let records: Vec<Result<InputRecord, Error>> =
+                    match parsed_res {
+                        Err(error) => { vec!(Err(error)) }
+                        Ok((i_line, variants, line)) if variants.is_empty() => {
+                            vec!(Ok(InputRecord::Unused(i_line, line)))
+                        }
+                        Ok((i_line, variants, line)) => {
+                            variants.into_iter().map(|(i_allele, mut variant)| {
+                                variant.chrom = chrom_aliases.canonicalize(variant.chrom);
+                                let input_variant =
+                                    InputVariant::new(variant, i_line, i_allele, line.clone());
+                                Ok(InputRecord::Variant(input_variant))
+                            }).collect()
+                        }
+                    };
+                records
+            })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+    use std::sync::Arc;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use fs_err::File;
+    use crate::cache::input::{Input, InputRecord};
+    use crate::genomics::chrom_aliases::ChromAliases;
+    use crate::util::error::Error;
+    use crate::util::error_policy::ErrorPolicy;
+
+    const VCF: &str = "##fileformat=VCFv4.2\n\
+    #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+    1\t100\t.\tA\tG\t.\t.\t.\n\
+    2\t200\t.\tC\tT,CA\t.\t.\t.\n\
+    3\t300\t.\tG\t.\t.\t.\t.\n";
+
+    fn assert_variants(input: Input) -> Result<(), Error> {
+        assert_eq!(input.header_lines.len(), 2);
+        let records =
+            input.records(Arc::new(ErrorPolicy::strict()))
+                .map(|record_res| {
+                    record_res.map(|record| {
+                        match record {
+                            InputRecord::Variant(input_variant) => {
+                                (input_variant.variant.to_string(), input_variant.i_line,
+                                 input_variant.i_allele)
+                            }
+                            InputRecord::Unused(i_line, _) => { (String::from("-"), i_line, 0) }
+                        }
+                    })
+                })
+                .collect::<Result<Vec<(String, usize, usize)>, Error>>()?;
+        assert_eq!(records, vec!((String::from("1:100_A/G"), 3, 1),
+                                 (String::from("2:200_C/T"), 4, 1),
+                                 (String::from("2:200_C/CA"), 4, 2),
+                                 (String::from("-"), 5, 0)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_plain_vcf() -> Result<(), Error> {
+        let file = "tmp/input_plain.vcf";
+        File::create(file)?.write_all(VCF.as_bytes())?;
+        assert_variants(Input::from_file(file, ChromAliases::new())?)
+    }
+
+    #[test]
+    fn read_gzipped_vcf() -> Result<(), Error> {
+        let file = "tmp/input_gzipped.vcf.gz";
+        let mut encoder = GzEncoder::new(File::create(file)?, Compression::default());
+        encoder.write_all(VCF.as_bytes())?;
+        encoder.finish()?;
+        assert_variants(Input::from_file(file, ChromAliases::new())?)
+    }
+
+    #[test]
+    fn read_vcf_from_stream() -> Result<(), Error> {
+        let raw: Box<dyn Read> = Box::new(Cursor::new(VCF.as_bytes().to_vec()));
+        assert_variants(Input::from_read(raw, ChromAliases::new())?)
+    }
+}
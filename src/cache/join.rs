@@ -1,116 +1,922 @@
 use crate::util::error::Error;
 use bgzip::BGZFReader;
 use std::io::{Read, Seek, BufRead};
-use crate::cache::input::Input;
-use bgzip::tabix::Tabix;
+use std::thread;
+use std::sync::Arc;
+use crate::cache::input::{Input, InputRecord, InputVariant};
+use std::collections::{HashSet, VecDeque};
+use std::cmp;
 use crate::cache::output::Output;
+use crate::cache::annotated::AnnotatedOutput;
 use crate::cache::misses::MissesFile;
-use crate::genomics::variant::{ICols, Variant};
+use crate::genomics::reference::Reference;
+use crate::genomics::chrom_aliases::ChromAliases;
+use crate::genomics::variant::{ICols, Normalizer, Variant};
 use crate::genomics::variant;
-use crate::tabix::tabix_index;
+use crate::tabix::data_index::{DataIndex, SequenceIndex};
 use crate::tabix::tsv::IAlleleCols;
-use crate::cache::regions::Regions;
+use crate::cache::regions::{FilterReason, RegionsFilter};
+use crate::cache::variant_records::VariantRecordsFile;
+use crate::util::error_policy::ErrorPolicy;
+
+const RECORDS_PER_BATCH: usize = 1 << 16;
 
 struct SequenceMeta {
     name: String,
     i_opt: Option<usize>,
 }
 
+struct InputOrder {
+    i_seq_last_opt: Option<usize>,
+    pos_last: u32,
+    i_seqs_seen: HashSet<usize>,
+    is_sorted: bool,
+}
+
+struct Sweep {
+    i_seq: usize,
+    pos_begin: u32,
+    data: VecDeque<DataLine>,
+    vpos_next: u64,
+    is_exhausted: bool,
+}
+
+struct DataLine {
+    pos: u32,
+    variant: Variant,
+    line: String,
+}
+
+// input records in input order, of which only admitted variants are looked up
+enum Task {
+    LookUp(InputVariant),
+    Filtered(InputVariant),
+    Unused(usize, Arc<String>),
+}
+
+enum LookupResult {
+    Lines(Vec<String>),
+    RefMismatch(String),
+    UnknownChrom,
+}
+
+pub(crate) enum HitsOutput {
+    Lines(Output),
+    Annotated(AnnotatedOutput),
+}
+
+pub(crate) struct Writers {
+    output: HitsOutput,
+    misses_file: MissesFile,
+    ref_mismatches_file_opt: Option<VariantRecordsFile>,
+    filtered_file_opt: Option<VariantRecordsFile>,
+}
+
+// input_lines and parse_errors count lines, the others count variants, one per ALT allele
+pub(crate) struct JoinStats {
+    pub(crate) n_input_lines: usize,
+    pub(crate) n_inputs: usize,
+    pub(crate) n_parse_errors: usize,
+    pub(crate) n_outside_regions: usize,
+    pub(crate) n_in_excluded_regions: usize,
+    pub(crate) n_variants: usize,
+    pub(crate) n_hits: usize,
+    pub(crate) n_multiple_hits: usize,
+    pub(crate) n_misses: usize,
+    pub(crate) n_unknown_chroms: usize,
+    pub(crate) n_ref_mismatches: usize,
+    i_line_last: usize,
+}
+
+struct PosRange {
+    begin: u32,
+    end: u32,
+}
+
+impl PosRange {
+    fn contains(&self, pos: u32) -> bool { self.begin <= pos && pos <= self.end }
+}
+
+pub(crate) struct Worker<R: Read + Seek> {
+    bgzf: BGZFReader<R>,
+    data_file: String,
+    normalize: bool,
+    reference_opt: Option<Reference>,
+    chrom_aliases: ChromAliases,
+    error_policy: Arc<ErrorPolicy>,
+    meta: SequenceMeta,
+    order: InputOrder,
+    sweep_opt: Option<Sweep>,
+}
 
 impl SequenceMeta {
     fn new() -> SequenceMeta {
         SequenceMeta { name: String::from(""), i_opt: None }
     }
-    fn update_from(&mut self, name: &str, names: &[Vec<u8>]) {
+    fn update_from(&mut self, name: &str, data_index: &DataIndex, chrom_aliases: &ChromAliases) {
         if self.name != name {
             self.name = name.to_string();
-            self.i_opt = names.iter().position(|name_i_bytes| {
-                let name_i_raw = String::from_utf8(name_i_bytes.clone())
-                    .unwrap_or_else(|_| String::from(""));
-                let name_i = name_i_raw.trim_matches(char::from(0));
-                name == name_i
-            });
+            self.i_opt = data_index.i_sequence(name, chrom_aliases);
         }
     }
 }
 
-pub(crate) fn join_input_with_data<R>(input: Input, mut bgzf: BGZFReader<R>, tabix: Tabix,
-                                      regions_opt: Option<Regions>, mut output: Output,
-                                      mut misses_file: MissesFile, i_allele_cols: IAlleleCols)
-                                      -> Result<(), Error>
-    where R: Read + Seek {
-    let mut meta = SequenceMeta::new();
-    let i_cols =
-        ICols::new((tabix.column_for_sequence - 1) as usize,
-                   (tabix.column_for_begin - 1) as usize,
-                   i_allele_cols.i_col_ref, i_allele_cols.i_col_alt);
-    let pred_opt = regions_opt.map(|regions| {
-        move |(variant, _): &(Variant, String)| { regions.overlap(variant) }
-    });
-    let variants = match pred_opt {
-        None => { Box::new(input.variants()) as Box<dyn Iterator<Item=(Variant, String)>> }
-        Some(pred) => {
-            Box::new(input.variants().filter(pred))
-                as Box<dyn Iterator<Item=(Variant, String)>>
+impl InputOrder {
+    fn new() -> InputOrder {
+        InputOrder {
+            i_seq_last_opt: None,
+            pos_last: 0,
+            i_seqs_seen: HashSet::new(),
+            is_sorted: true,
         }
-    };
-    for (variant, _) in variants {
-        meta.update_from(&variant.chrom, &tabix.names);
-        match meta.i_opt {
-            None => {
-                misses_file.write_variant(&variant)?;
-            }
-            Some(i_seq) => {
-                let sequence =
-                    tabix.sequences.get(i_seq).ok_or_else(|| {
-                        Error::from(format!("Index {} out of range for sequences.", i_seq))
-                    })?;
-                let i_interval = tabix_index::pos_to_i_interval(variant.pos);
-                match sequence.intervals.get(i_interval as usize) {
-                    None => { misses_file.write_variant(&variant)?; }
-                    Some(vpos_interval) => {
-                        let bins = tabix_index::variant_to_bins(&variant);
-                        let mut vposes: Vec<u64> =
-                            bins.iter().flat_map(|k| sequence.bins.get(k))
-                                .flat_map(|tabix_bin| { &tabix_bin.chunks })
-                                .filter_map(|chunk| {
-                                    if chunk.end <= *vpos_interval {
-                                        None
-                                    } else if chunk.begin <= *vpos_interval {
-                                        Some(*vpos_interval)
-                                    } else {
-                                        Some(chunk.begin)
-                                    }
-                                }).collect();
-                        vposes.sort_unstable();
-                        vposes.dedup();
-                        let mut found_variant = false;
-                        for vpos in vposes {
-                            bgzf.bgzf_seek(vpos)?;
-                            loop {
-                                let mut line_buf = String::new();
-                                let n_bytes_read = bgzf.read_line(&mut line_buf)?;
-                                if n_bytes_read == 0 {
-                                    break;
-                                }
-                                let (data_variant, line) =
-                                    variant::parse_tsv_line(line_buf, &i_cols)?;
-                                if variant == data_variant {
-                                    found_variant = true;
-                                    output.write_line(line)?;
-                                } else if (variant.chrom != data_variant.chrom) ||
-                                    (variant.pos < data_variant.pos) {
-                                    break;
-                                }
-                            }
-                        }
-                        if !found_variant {
-                            misses_file.write_variant(&variant)?;
-                        }
+    }
+    fn is_still_sorted(&mut self, i_seq: usize, pos: u32) -> bool {
+        if self.is_sorted {
+            match self.i_seq_last_opt {
+                Some(i_seq_last) if i_seq_last == i_seq => {
+                    if pos < self.pos_last { self.is_sorted = false }
+                }
+                _ => {
+                    if !self.i_seqs_seen.insert(i_seq) { self.is_sorted = false }
+                }
+            }
+            self.i_seq_last_opt = Some(i_seq);
+            self.pos_last = pos;
+        }
+        self.is_sorted
+    }
+}
+
+impl Writers {
+    pub(crate) fn new(output: HitsOutput, misses_file: MissesFile,
+                      ref_mismatches_file_opt: Option<VariantRecordsFile>,
+                      filtered_file_opt: Option<VariantRecordsFile>) -> Writers {
+        Writers { output, misses_file, ref_mismatches_file_opt, filtered_file_opt }
+    }
+    // records not looked up still go to annotated output, so that it has every input line
+    fn pass_through(&mut self, task: &Task) -> Result<(), Error> {
+        if let HitsOutput::Annotated(annotated) = &mut self.output {
+            match task {
+                Task::LookUp(_) => {}
+                Task::Filtered(input_variant) => { annotated.add_result(input_variant, &[])?; }
+                Task::Unused(i_line, line) => { annotated.add_line(*i_line, line)?; }
+            }
+        }
+        Ok(())
+    }
+    fn finish(&mut self) -> Result<(), Error> {
+        match &mut self.output {
+            HitsOutput::Lines(output) => { output.flush()?; }
+            HitsOutput::Annotated(annotated) => { annotated.finish()?; }
+        }
+        self.misses_file.flush()?;
+        if let Some(ref_mismatches_file) = &mut self.ref_mismatches_file_opt {
+            ref_mismatches_file.flush()?;
+        }
+        if let Some(filtered_file) = &mut self.filtered_file_opt {
+            filtered_file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// the line number is unknown after a seek, so data lines are located by chromosome and position
+fn data_line_source(data_file: &str, line: &str, i_cols: &ICols) -> String {
+    let line = line.trim_end_matches(&['\n', '\r'][..]);
+    let fields: Vec<&str> = line.split('\t').collect();
+    match (fields.get(i_cols.i_col_chrom), fields.get(i_cols.i_col_pos)) {
+        (Some(chrom), Some(pos)) => { format!("data file {} at {}:{}", data_file, chrom, pos) }
+        _ => { format!("data file {} line '{}'", data_file, line) }
+    }
+}
+
+impl JoinStats {
+    pub(crate) fn new() -> JoinStats {
+        JoinStats {
+            n_input_lines: 0,
+            n_inputs: 0,
+            n_parse_errors: 0,
+            n_outside_regions: 0,
+            n_in_excluded_regions: 0,
+            n_variants: 0,
+            n_hits: 0,
+            n_multiple_hits: 0,
+            n_misses: 0,
+            n_unknown_chroms: 0,
+            n_ref_mismatches: 0,
+            i_line_last: 0,
+        }
+    }
+    pub(crate) fn counts(&self) -> Vec<(&'static str, usize)> {
+        vec!(
+            ("input_lines", self.n_input_lines),
+            ("input_variants", self.n_inputs),
+            ("parse_errors", self.n_parse_errors),
+            ("outside_regions", self.n_outside_regions),
+            ("in_excluded_regions", self.n_in_excluded_regions),
+            ("looked_up", self.n_variants),
+            ("hits", self.n_hits),
+            ("multiple_hits", self.n_multiple_hits),
+            ("misses", self.n_misses),
+            ("unknown_chroms", self.n_unknown_chroms),
+            ("ref_mismatches", self.n_ref_mismatches),
+        )
+    }
+}
+
+impl Sweep {
+    fn new(i_seq: usize, vpos: u64) -> Sweep {
+        Sweep { i_seq, pos_begin: 0, data: VecDeque::new(), vpos_next: vpos, is_exhausted: false }
+    }
+}
+
+impl<R: Read + Seek> Worker<R> {
+    // the reference, if any, is used both to left-align and to check REF alleles
+    pub(crate) fn new(bgzf: BGZFReader<R>, data_file: String, normalize: bool,
+                      reference_opt: Option<Reference>, chrom_aliases: ChromAliases,
+                      error_policy: Arc<ErrorPolicy>) -> Worker<R> {
+        let meta = SequenceMeta::new();
+        let order = InputOrder::new();
+        Worker {
+            bgzf, data_file, normalize, reference_opt, chrom_aliases, error_policy, meta, order,
+            sweep_opt: None,
+        }
+    }
+    fn check_and_look_up(&mut self, variant: &Variant, data_index: &DataIndex, i_cols: &ICols)
+                         -> Result<LookupResult, Error> {
+        self.meta.update_from(&variant.chrom, data_index, &self.chrom_aliases);
+        if self.meta.i_opt.is_none() {
+            return Ok(LookupResult::UnknownChrom);
+        }
+        if let Some(reference) = &mut self.reference_opt {
+            if let Some(expected_ref) = reference.find_ref_mismatch(variant)? {
+                return Ok(LookupResult::RefMismatch(expected_ref));
+            }
+        }
+        Ok(LookupResult::Lines(self.look_up(variant, data_index, i_cols)?))
+    }
+    fn read_data_line(&mut self, i_cols: &ICols) -> Result<Option<DataLine>, Error> {
+        loop {
+            let vpos = self.bgzf.bgzf_pos();
+            let mut line = String::new();
+            let n_bytes_read = self.bgzf.read_line(&mut line)?;
+            if n_bytes_read == 0 {
+                return Ok(None);
+            }
+            match variant::parse_tsv_line(&line, i_cols) {
+                Ok(mut variant) => {
+                    variant.chrom = self.chrom_aliases.canonicalize(variant.chrom);
+                    let pos = variant.pos;
+                    let variant =
+                        if self.normalize {
+                            Normalizer::new(self.reference_opt.as_mut()).normalize(&variant)?
+                        } else {
+                            variant
+                        };
+                    return Ok(Some(DataLine { pos, variant, line }));
+                }
+                Err(error) => {
+                    let source = data_line_source(&self.data_file, &line, i_cols);
+                    self.error_policy.handle_once(&vpos.to_string(), &source, &line, error)?;
+                }
+            }
+        }
+    }
+    fn look_up(&mut self, variant: &Variant, data_index: &DataIndex, i_cols: &ICols)
+               -> Result<Vec<String>, Error> {
+        self.meta.update_from(&variant.chrom, data_index, &self.chrom_aliases);
+        let i_seq = match self.meta.i_opt {
+            None => { return Ok(Vec::new()) }
+            Some(i_seq) => { i_seq }
+        };
+        let sequence =
+            data_index.sequences.get(i_seq).ok_or_else(|| {
+                Error::from(format!("Index {} out of range for sequences.", i_seq))
+            })?;
+        let normalized_opt =
+            if self.normalize {
+                let mut normalizer = Normalizer::new(self.reference_opt.as_mut());
+                let normalized = normalizer.normalize(variant)?;
+                let pos_shifted = normalizer.right_shifted_pos(&normalized)?;
+                Some((normalized, pos_shifted))
+            } else {
+                None
+            };
+        let (target, range) = match &normalized_opt {
+            None => { (variant, PosRange { begin: variant.pos, end: variant.pos }) }
+            Some((normalized, pos_shifted)) => {
+                let begin = cmp::min(variant.pos, normalized.pos);
+                let end = cmp::max(cmp::max(variant.end(), normalized.end()) - 1, *pos_shifted);
+                (normalized, PosRange { begin, end })
+            }
+        };
+        let is_sorted = self.order.is_still_sorted(i_seq, variant.pos);
+        match data_index.min_vpos(sequence, range.begin) {
+            None => { Ok(Vec::new()) }
+            Some(vpos_min) => {
+                if is_sorted {
+                    self.look_up_by_sweeping(target, i_seq, vpos_min, &range, i_cols)
+                } else {
+                    self.look_up_by_seeking(target, data_index, sequence, &range, i_cols)
+                }
+            }
+        }
+    }
+    fn look_up_by_seeking(&mut self, variant: &Variant, data_index: &DataIndex,
+                          sequence: &SequenceIndex, range: &PosRange, i_cols: &ICols)
+                          -> Result<Vec<String>, Error> {
+        self.sweep_opt = None;
+        let mut lines = Vec::<String>::new();
+        let vposes = data_index.seek_vposes(sequence, range.begin, range.end);
+        let mut vpos_last_found_opt: Option<u64> = None;
+        for vpos in vposes {
+            self.bgzf.bgzf_seek(vpos)?;
+            loop {
+                let vpos_line = self.bgzf.bgzf_pos();
+                let data_line = match self.read_data_line(i_cols)? {
+                    None => { break }
+                    Some(data_line) => { data_line }
+                };
+                if *variant == data_line.variant && range.contains(data_line.pos) {
+                    if vpos_last_found_opt.is_none_or(|vpos_last| vpos_line > vpos_last) {
+                        lines.push(data_line.line);
+                        vpos_last_found_opt = Some(vpos_line);
+                    }
+                } else if (variant.chrom != data_line.variant.chrom) ||
+                    (range.end < data_line.pos) {
+                    break;
+                }
+            }
+        }
+        Ok(lines)
+    }
+    fn look_up_by_sweeping(&mut self, variant: &Variant, i_seq: usize, vpos_interval: u64,
+                           range: &PosRange, i_cols: &ICols) -> Result<Vec<String>, Error> {
+        let mut sweep = match self.sweep_opt.take() {
+            Some(sweep) if sweep.i_seq == i_seq && sweep.pos_begin <= range.begin &&
+                sweep.vpos_next >= vpos_interval => { sweep }
+            _ => {
+                self.bgzf.bgzf_seek(vpos_interval)?;
+                Sweep::new(i_seq, vpos_interval)
+            }
+        };
+        sweep.pos_begin = range.begin;
+        while sweep.data.front().is_some_and(|data_line| {
+            data_line.variant.chrom == variant.chrom && data_line.pos < range.begin
+        }) {
+            sweep.data.pop_front();
+        }
+        while !sweep.is_exhausted && sweep.data.back().is_none_or(|data_line| {
+            data_line.variant.chrom == variant.chrom && data_line.pos <= range.end
+        }) {
+            match self.read_data_line(i_cols)? {
+                None => { sweep.is_exhausted = true }
+                Some(data_line) => {
+                    if data_line.variant.chrom != variant.chrom ||
+                        data_line.pos >= range.begin {
+                        sweep.data.push_back(data_line);
                     }
                 }
             }
         }
+        sweep.vpos_next = self.bgzf.bgzf_pos();
+        let lines: Vec<String> =
+            sweep.data.iter().filter(|data_line| {
+                data_line.variant == *variant && range.contains(data_line.pos)
+            })
+                .map(|data_line| data_line.line.clone()).collect();
+        self.sweep_opt = Some(sweep);
+        Ok(lines)
+    }
+    // results for the tasks to look up, in order
+    fn look_up_all(&mut self, tasks: &[Task], data_index: &DataIndex, i_cols: &ICols)
+                   -> Result<Vec<LookupResult>, Error> {
+        let mut results = Vec::<LookupResult>::with_capacity(tasks.len());
+        for task in tasks {
+            if let Task::LookUp(input_variant) = task {
+                results.push(self.check_and_look_up(&input_variant.variant, data_index, i_cols)?);
+            }
+        }
+        Ok(results)
+    }
+}
+
+fn write_result(input_variant: &InputVariant, result: LookupResult, writers: &mut Writers,
+                stats: &mut JoinStats) -> Result<(), Error> {
+    stats.n_variants += 1;
+    let lines = match result {
+        LookupResult::RefMismatch(expected_ref) => {
+            stats.n_ref_mismatches += 1;
+            match &mut writers.ref_mismatches_file_opt {
+                Some(ref_mismatches_file) => {
+                    ref_mismatches_file.write_variant(input_variant, &expected_ref)?;
+                }
+                None => {
+                    stats.n_misses += 1;
+                    writers.misses_file.write_variant(input_variant)?;
+                }
+            }
+            Vec::new()
+        }
+        LookupResult::UnknownChrom => {
+            stats.n_unknown_chroms += 1;
+            stats.n_misses += 1;
+            writers.misses_file.write_variant(input_variant)?;
+            Vec::new()
+        }
+        LookupResult::Lines(lines) => {
+            if lines.is_empty() {
+                stats.n_misses += 1;
+                writers.misses_file.write_variant(input_variant)?;
+            } else {
+                stats.n_hits += 1;
+                if lines.len() > 1 {
+                    stats.n_multiple_hits += 1;
+                }
+            }
+            lines
+        }
+    };
+    match &mut writers.output {
+        HitsOutput::Lines(output) => {
+            for line in lines {
+                output.write_line(line, input_variant)?;
+            }
+        }
+        HitsOutput::Annotated(annotated) => { annotated.add_result(input_variant, &lines)?; }
+    }
+    Ok(())
+}
+
+// counts the input variant and checks it against the regions filters
+fn admit_input(record: InputRecord, filter: &RegionsFilter, writers: &mut Writers,
+               stats: &mut JoinStats) -> Result<Task, Error> {
+    if record.i_line() != stats.i_line_last {
+        stats.n_input_lines += 1;
+        stats.i_line_last = record.i_line();
+    }
+    let input_variant = match record {
+        InputRecord::Unused(i_line, line) => { return Ok(Task::Unused(i_line, line)) }
+        InputRecord::Variant(input_variant) => { input_variant }
+    };
+    stats.n_inputs += 1;
+    let reason = match filter.check(&input_variant.variant) {
+        None => { return Ok(Task::LookUp(input_variant)) }
+        Some(reason) => { reason }
+    };
+    match reason {
+        FilterReason::OutsideRegions => { stats.n_outside_regions += 1 }
+        FilterReason::InExcludedRegions => { stats.n_in_excluded_regions += 1 }
+    }
+    if let Some(filtered_file) = &mut writers.filtered_file_opt {
+        filtered_file.write_variant(&input_variant, reason.as_str())?;
+    }
+    Ok(Task::Filtered(input_variant))
+}
+
+fn write_results(tasks: &[Task], results: Vec<LookupResult>, writers: &mut Writers,
+                 stats: &mut JoinStats) -> Result<(), Error> {
+    let mut results = results.into_iter();
+    for task in tasks {
+        match task {
+            Task::LookUp(input_variant) => {
+                let result =
+                    results.next().ok_or_else(|| {
+                        Error::from(format!("No lookup result for {}.", input_variant.variant))
+                    })?;
+                write_result(input_variant, result, writers, stats)?;
+            }
+            _ => { writers.pass_through(task)?; }
+        }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+pub(crate) fn join_input_with_data<R>(input: Input, mut workers: Vec<Worker<R>>,
+                                      data_index: DataIndex,
+                                      filter: RegionsFilter, mut writers: Writers,
+                                      i_allele_cols: IAlleleCols, error_policy: Arc<ErrorPolicy>)
+                                      -> Result<JoinStats, Error>
+    where R: Read + Seek + Send {
+    if workers.is_empty() {
+        return Err(Error::from("Need at least one worker for the data file."));
+    }
+    let i_cols =
+        ICols::new((data_index.format.col_seq - 1) as usize,
+                   (data_index.format.col_begin - 1) as usize,
+                   i_allele_cols.i_col_ref, i_allele_cols.i_col_alt);
+    let n_parse_errors = input.error_counter();
+    let mut records = input.records(error_policy);
+    let mut stats = JoinStats::new();
+    if workers.len() == 1 {
+        let worker = &mut workers[0];
+        for record in records {
+            match admit_input(record?, &filter, &mut writers, &mut stats)? {
+                Task::LookUp(input_variant) => {
+                    let result =
+                        worker.check_and_look_up(&input_variant.variant, &data_index, &i_cols)?;
+                    write_result(&input_variant, result, &mut writers, &mut stats)?;
+                }
+                task => { writers.pass_through(&task)?; }
+            }
+        }
+    } else {
+        loop {
+            let mut batch = Vec::<Task>::with_capacity(RECORDS_PER_BATCH);
+            for record in records.by_ref() {
+                batch.push(admit_input(record?, &filter, &mut writers, &mut stats)?);
+                if batch.len() == RECORDS_PER_BATCH {
+                    break;
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+            let chunk_size = batch.len().div_ceil(workers.len());
+            let data_index_ref = &data_index;
+            let i_cols_ref = &i_cols;
+            let results: Vec<Result<Vec<LookupResult>, Error>> = thread::scope(|scope| {
+                let children: Vec<_> =
+                    workers.iter_mut().zip(batch.chunks(chunk_size)).map(|(worker, chunk)| {
+                        scope.spawn(move || {
+                            worker.look_up_all(chunk, data_index_ref, i_cols_ref)
+                        })
+                    }).collect();
+                children.into_iter().map(|child| { child.join()? }).collect()
+            });
+            for (chunk, result) in batch.chunks(chunk_size).zip(results) {
+                write_results(chunk, result?, &mut writers, &mut stats)?;
+            }
+        }
+    }
+    writers.finish()?;
+    stats.n_parse_errors = n_parse_errors.get();
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufWriter, Write};
+    use std::sync::Arc;
+    use bgzip::BGZFReader;
+    use fs_err::File;
+    use crate::cache::input::Input;
+    use crate::cache::join::{join_input_with_data, HitsOutput, InputOrder, JoinStats, PosRange,
+                             Worker, Writers};
+    use crate::cache::misses::MissesFile;
+    use crate::cache::annotated::{AnnotateMode, AnnotatedOutput};
+    use crate::cache::output::Output;
+    use crate::cache::regions::{Regions, RegionsFilter, RegionsFormat};
+    use crate::config::BgzipConfig;
+    use crate::genomics::chrom_aliases::ChromAliases;
+    use crate::genomics::reference::Reference;
+    use crate::genomics::variant::{ICols, Variant};
+    use crate::tabix::data_index::DataIndex;
+    use crate::tabix::index_builder::IndexFormat;
+    use crate::tabix::tabix_index::IndexType;
+    use crate::tabix::tsv::{ColSelection, IAlleleCols};
+    use crate::tools;
+    use crate::util::error::Error;
+    use crate::util::error_policy::{ErrorMode, ErrorPolicy};
+
+    const DATA_HEADER: &str = "#chrom\tpos\tref\talt\tscore";
+    const INPUT_HEADER: &str = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO";
+
+    struct Joined {
+        stats: JoinStats,
+        output: String,
+        misses: String,
+    }
+
+    fn write_lines(file: &str, header: &str, lines: &[String]) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(file)?);
+        writeln!(writer, "{}", header)?;
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // bgzipped data file with a .tbi index next to it
+    fn write_data_file(name: &str, lines: &[String]) -> Result<String, Error> {
+        let plain_file = format!("tmp/{}.tsv", name);
+        write_lines(&plain_file, DATA_HEADER, lines)?;
+        let data_file = format!("{}.gz", plain_file);
+        let config = BgzipConfig {
+            input_file: plain_file,
+            output_file: data_file.clone(),
+            threads: 1,
+            index_file_opt: Some(format!("{}.tbi", data_file)),
+            index_format: IndexFormat::for_preset(None)?,
+            index_type: IndexType::Tbi,
+        };
+        tools::bgzip::compress(&config)?;
+        Ok(data_file)
+    }
+
+    fn new_workers(data_file: &str, n_workers: usize) -> Result<Vec<Worker<File>>, Error> {
+        workers_with_policy(data_file, n_workers, &Arc::new(ErrorPolicy::strict()))
+    }
+
+    fn workers_with_policy(data_file: &str, n_workers: usize, error_policy: &Arc<ErrorPolicy>)
+                           -> Result<Vec<Worker<File>>, Error> {
+        let mut workers = Vec::<Worker<File>>::with_capacity(n_workers);
+        for _ in 0..n_workers {
+            let bgzf = BGZFReader::new(File::open(data_file)?);
+            workers.push(Worker::new(bgzf, data_file.to_string(), false, None,
+                                     ChromAliases::new(), error_policy.clone()));
+        }
+        Ok(workers)
+    }
+
+    // sequence 1 is all C except for A T at 100, and A GGGGG T at 120
+    fn write_reference_file(file: &str) -> Result<(), Error> {
+        let mut bases = [b'C'; 200];
+        bases[99..101].copy_from_slice(b"AT");
+        bases[119..126].copy_from_slice(b"AGGGGGT");
+        let mut writer = BufWriter::new(File::create(file)?);
+        writer.write_all(b">1\n")?;
+        for line in bases.chunks(60) {
+            writer.write_all(line)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        File::create(format!("{}.fai", file))?.write_all(b"1\t200\t3\t60\t61\n")?;
+        Ok(())
+    }
+
+    fn worker_with_reference(data_file: &str, reference_file: &str, normalize: bool)
+                             -> Result<Worker<File>, Error> {
+        let bgzf = BGZFReader::new(File::open(data_file)?);
+        let reference = Reference::from_file(reference_file, ChromAliases::new())?;
+        Ok(Worker::new(bgzf, data_file.to_string(), normalize, Some(reference),
+                       ChromAliases::new(), Arc::new(ErrorPolicy::strict())))
+    }
+
+    fn join(name: &str, data_file: &str, input_lines: &[String], workers: Vec<Worker<File>>)
+            -> Result<Joined, Error> {
+        let input_file = format!("tmp/{}.vcf", name);
+        write_lines(&input_file, INPUT_HEADER, input_lines)?;
+        let output_file = format!("tmp/{}.out.tsv", name);
+        let misses_file = format!("tmp/{}.misses.vcf", name);
+        let input = Input::from_file(&input_file, ChromAliases::new())?;
+        let data_index = DataIndex::from_file(&format!("{}.tbi", data_file))?;
+        let output =
+            Output::from_file(output_file.clone(), DATA_HEADER, &[], false, None, None)?;
+        let misses = MissesFile::from_file(misses_file.clone(), &[], false)?;
+        let writers = Writers::new(HitsOutput::Lines(output), misses, None, None);
+        let i_allele_cols = IAlleleCols::parse(DATA_HEADER, "ref", "alt")?;
+        let stats =
+            join_input_with_data(input, workers, data_index, RegionsFilter::new(None, None),
+                                 writers, i_allele_cols, Arc::new(ErrorPolicy::strict()))?;
+        let output = std::fs::read_to_string(output_file)?;
+        let misses = std::fs::read_to_string(misses_file)?;
+        Ok(Joined { stats, output, misses })
+    }
+
+    fn data_line(chrom: &str, pos: u32, ref_allele: &str, alt_allele: &str) -> String {
+        format!("{}\t{}\t{}\t{}\t{}:{}", chrom, pos, ref_allele, alt_allele, chrom, pos)
+    }
+
+    fn input_line(chrom: &str, pos: u32, ref_allele: &str, alt_allele: &str) -> String {
+        format!("{}\t{}\t.\t{}\t{}\t.\t.\t.", chrom, pos, ref_allele, alt_allele)
+    }
+
+    #[test]
+    fn same_output_for_one_and_many_workers() -> Result<(), Error> {
+        let mut data_lines = Vec::<String>::new();
+        for chrom in ["1", "2"] {
+            for pos in (100..40_000).step_by(10) {
+                data_lines.push(data_line(chrom, pos, "A", "G"));
+            }
+        }
+        let data_file = write_data_file("join_workers_data", &data_lines)?;
+        let mut input_lines = Vec::<String>::new();
+        for chrom in ["1", "2", "3"] {
+            for pos in (50..41_000).step_by(70) {
+                input_lines.push(input_line(chrom, pos, "A", "G,T"));
+            }
+        }
+        let single = join("join_workers_1", &data_file, &input_lines, new_workers(&data_file, 1)?)?;
+        let multi = join("join_workers_4", &data_file, &input_lines, new_workers(&data_file, 4)?)?;
+        assert!(single.stats.n_hits > 0 && single.stats.n_misses > 0);
+        assert_eq!(single.stats.counts(), multi.stats.counts());
+        assert_eq!(single.output, multi.output);
+        assert_eq!(single.misses, multi.misses);
+        Ok(())
+    }
+
+    #[test]
+    fn input_order_tracks_sorting() {
+        let mut order = InputOrder::new();
+        assert!(order.is_still_sorted(0, 100));
+        assert!(order.is_still_sorted(0, 100));
+        assert!(order.is_still_sorted(0, 200));
+        assert!(order.is_still_sorted(1, 50));
+        assert!(!order.is_still_sorted(0, 300));
+        assert!(!order.is_still_sorted(1, 60));
+        let mut order = InputOrder::new();
+        assert!(order.is_still_sorted(0, 200));
+        assert!(!order.is_still_sorted(0, 100));
+        assert!(!order.is_still_sorted(1, 100));
+    }
+
+    #[test]
+    fn sweep_and_seek_find_same_lines() -> Result<(), Error> {
+        let mut data_lines = Vec::<String>::new();
+        for chrom in ["1", "2"] {
+            for pos in (100..2_000).step_by(5) {
+                data_lines.push(data_line(chrom, pos, "A", "G"));
+                if pos % 3 == 0 {
+                    data_lines.push(data_line(chrom, pos, "A", "G"));
+                    data_lines.push(data_line(chrom, pos, "AC", "A"));
+                }
+            }
+        }
+        let data_file = write_data_file("join_sweep_data", &data_lines)?;
+        let data_index = DataIndex::from_file(&format!("{}.tbi", data_file))?;
+        let i_cols = ICols::new(0, 1, 2, 3);
+        let mut sweeper = new_workers(&data_file, 1)?.remove(0);
+        let mut seeker = new_workers(&data_file, 1)?.remove(0);
+        let mut n_hits: usize = 0;
+        for chrom in ["1", "2"] {
+            for pos in (90..2_100).step_by(7) {
+                for (ref_allele, alt_allele) in [("A", "G"), ("AC", "A")] {
+                    let variant =
+                        Variant::new(chrom.to_string(), pos, ref_allele.to_string(),
+                                     alt_allele.to_string());
+                    let swept = sweeper.look_up(&variant, &data_index, &i_cols)?;
+                    let i_seq = data_index.i_sequence(chrom, &ChromAliases::new()).unwrap();
+                    let range = PosRange { begin: pos, end: pos };
+                    let sought =
+                        seeker.look_up_by_seeking(&variant, &data_index,
+                                                  &data_index.sequences[i_seq], &range,
+                                                  &i_cols)?;
+                    assert_eq!(swept, sought, "Sweep and seek differ for {}", variant);
+                    n_hits += swept.len();
+                }
+            }
+        }
+        assert!(sweeper.order.is_sorted);
+        assert!(n_hits > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn unsorted_input_finds_same_hits_as_sorted() -> Result<(), Error> {
+        let mut data_lines = Vec::<String>::new();
+        for chrom in ["1", "2"] {
+            for pos in (100..2_000).step_by(10) {
+                data_lines.push(data_line(chrom, pos, "A", "G"));
+            }
+        }
+        let data_file = write_data_file("join_order_data", &data_lines)?;
+        let mut sorted_lines = Vec::<String>::new();
+        for chrom in ["1", "2"] {
+            for pos in (50..2_050).step_by(30) {
+                sorted_lines.push(input_line(chrom, pos, "A", "G"));
+            }
+        }
+        let mut unsorted_lines = sorted_lines.clone();
+        unsorted_lines.reverse();
+        let sorted =
+            join("join_order_sorted", &data_file, &sorted_lines, new_workers(&data_file, 1)?)?;
+        let unsorted =
+            join("join_order_unsorted", &data_file, &unsorted_lines,
+                 new_workers(&data_file, 1)?)?;
+        assert!(sorted.stats.n_hits > 0);
+        assert_eq!(sorted.stats.counts(), unsorted.stats.counts());
+        let mut sorted_hits: Vec<&str> = sorted.output.lines().collect();
+        let mut unsorted_hits: Vec<&str> = unsorted.output.lines().collect();
+        sorted_hits.sort();
+        unsorted_hits.sort();
+        assert_eq!(sorted_hits, unsorted_hits);
+        Ok(())
+    }
+
+    #[test]
+    fn normalized_lookup_matches_shifted_indels() -> Result<(), Error> {
+        let reference_file = "tmp/join_normalize_reference.fa";
+        write_reference_file(reference_file)?;
+        let data_lines =
+            vec!(data_line("1", 101, "T", "GT"), data_line("1", 125, "G", "GG"));
+        let data_file = write_data_file("join_normalize_data", &data_lines)?;
+        let input_lines =
+            vec!(input_line("1", 100, "AT", "AGT"), input_line("1", 120, "A", "AG"),
+                 input_line("1", 150, "C", "CA"));
+        let workers = vec!(worker_with_reference(&data_file, reference_file, true)?);
+        let joined = join("join_normalize", &data_file, &input_lines, workers)?;
+        assert_eq!(joined.stats.n_hits, 2);
+        assert_eq!(joined.stats.n_misses, 1);
+        let hits: Vec<&str> = joined.output.lines().skip(1).collect();
+        assert_eq!(hits, vec!(data_lines[0].as_str(), data_lines[1].as_str()));
+        Ok(())
+    }
+
+    #[test]
+    fn ref_mismatches_go_to_misses_by_default() -> Result<(), Error> {
+        let reference_file = "tmp/join_ref_mismatch_reference.fa";
+        write_reference_file(reference_file)?;
+        let data_lines = vec!(data_line("1", 100, "A", "G"), data_line("1", 101, "T", "C"));
+        let data_file = write_data_file("join_ref_mismatch_data", &data_lines)?;
+        let input_lines = vec!(input_line("1", 100, "A", "G"), input_line("1", 101, "G", "C"));
+        let workers = vec!(worker_with_reference(&data_file, reference_file, false)?);
+        let joined = join("join_ref_mismatch", &data_file, &input_lines, workers)?;
+        assert_eq!(joined.stats.n_hits, 1);
+        assert_eq!(joined.stats.n_ref_mismatches, 1);
+        assert_eq!(joined.stats.n_misses, 1);
+        assert_eq!(joined.misses, "#CHROM\tPOS\tID\tREF\tALT\n1\t101\t1:101_G/C\tG\tC\n");
+        Ok(())
+    }
+
+    fn join_annotated(name: &str, data_file: &str, input_lines: &[String], n_workers: usize)
+                      -> Result<(JoinStats, String), Error> {
+        let input_file = format!("tmp/{}.vcf", name);
+        write_lines(&input_file, INPUT_HEADER, input_lines)?;
+        let regions_file = format!("tmp/{}.bed", name);
+        write_lines(&regions_file, "#chrom\tstart\tend", &[String::from("1\t0\t1000")])?;
+        let regions =
+            Regions::load(&regions_file, Some(RegionsFormat::Bed), &ChromAliases::new(),
+                          &ErrorPolicy::strict())?;
+        let output_file = format!("tmp/{}.out.vcf", name);
+        let misses_file = format!("tmp/{}.misses.vcf", name);
+        let input = Input::from_file(&input_file, ChromAliases::new())?;
+        let data_index = DataIndex::from_file(&format!("{}.tbi", data_file))?;
+        let col_selection = ColSelection::parse(DATA_HEADER, &[String::from("score")], &[])?;
+        let annotated =
+            AnnotatedOutput::from_file(output_file.clone(), &input.header_lines, col_selection,
+                                       AnnotateMode::Info, None)?;
+        let misses = MissesFile::from_file(misses_file, &[], false)?;
+        let writers = Writers::new(HitsOutput::Annotated(annotated), misses, None, None);
+        let i_allele_cols = IAlleleCols::parse(DATA_HEADER, "ref", "alt")?;
+        let error_policy = Arc::new(ErrorPolicy::new(ErrorMode::parse("lenient", None)?, &None)?);
+        let stats =
+            join_input_with_data(input, new_workers(data_file, n_workers)?, data_index,
+                                 RegionsFilter::new(Some(regions), None), writers,
+                                 i_allele_cols, error_policy)?;
+        Ok((stats, std::fs::read_to_string(output_file)?))
+    }
+
+    #[test]
+    fn annotated_output_keeps_every_input_line() -> Result<(), Error> {
+        let data_lines = vec!(data_line("1", 100, "A", "G"), data_line("1", 2000, "A", "G"));
+        let data_file = write_data_file("join_annotated_data", &data_lines)?;
+        let input_lines =
+            vec!(input_line("1", 100, "A", "G,T"), String::from("1\tbad"),
+                 input_line("1", 2000, "A", "G"), input_line("1", 3000, "A", "*"));
+        let expected_lines =
+            vec!(String::from("1\t100\t.\tA\tG,T\t.\t.\tscore=1:100,."),
+                 String::from("1\tbad"), input_line("1", 2000, "A", "G"),
+                 input_line("1", 3000, "A", "*"));
+        let expected_counts =
+            vec!(("input_lines", 4), ("input_variants", 3), ("parse_errors", 1),
+                 ("outside_regions", 1), ("in_excluded_regions", 0), ("looked_up", 2),
+                 ("hits", 1), ("multiple_hits", 0), ("misses", 1), ("unknown_chroms", 0),
+                 ("ref_mismatches", 0));
+        for n_workers in [1, 3] {
+            let (stats, output) =
+                join_annotated(&format!("join_annotated_{}", n_workers), &data_file,
+                               &input_lines, n_workers)?;
+            let lines: Vec<&str> =
+                output.lines().filter(|line| { !line.starts_with('#') }).collect();
+            assert_eq!(lines, expected_lines);
+            assert_eq!(stats.counts(), expected_counts);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_data_line_counts_once() -> Result<(), Error> {
+        let data_lines =
+            vec!(data_line("1", 100, "A", "G"), String::from("1\t150"),
+                 data_line("1", 200, "A", "G"));
+        let data_file = write_data_file("join_malformed_data", &data_lines)?;
+        let mut input_lines = Vec::<String>::new();
+        for pos in (100..=200).step_by(10).chain((100..=200).rev().step_by(10)) {
+            input_lines.push(input_line("1", pos, "A", "G"));
+        }
+        let error_policy =
+            Arc::new(ErrorPolicy::new(ErrorMode::parse("lenient", Some(1))?, &None)?);
+        let workers = workers_with_policy(&data_file, 3, &error_policy)?;
+        let joined = join("join_malformed", &data_file, &input_lines, workers)?;
+        assert_eq!(error_policy.n_errors(), 1);
+        assert_eq!(joined.stats.n_hits, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn strict_error_names_data_file_and_position() -> Result<(), Error> {
+        let data_lines = vec!(data_line("1", 100, "A", "G"), String::from("1\t150"));
+        let data_file = write_data_file("join_strict_data", &data_lines)?;
+        let input_lines = vec!(input_line("1", 150, "A", "G"));
+        let workers = new_workers(&data_file, 1)?;
+        let message =
+            match join("join_strict", &data_file, &input_lines, workers) {
+                Ok(_) => { return Err(Error::from("Malformed data line was not reported.")) }
+                Err(error) => { error.to_string() }
+            };
+        assert!(message.starts_with(&format!("data file {} at 1:150: ", data_file)), "{}", message);
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
 use std::io::Write;
 use crate::util::error::Error;
 
+pub(crate) const INPUT_LINE_KEY: &str = "INPUT_LINE";
+pub(crate) const ALT_INDEX_KEY: &str = "ALT_INDEX";
+const INPUT_LINE_COL: &str = "InputLine";
+const ALT_INDEX_COL: &str = "AltIndex";
+
 pub(crate) fn chromosome_lines(chromosomes: &[String]) -> Vec<String> {
     let mut lines = Vec::<String>::new();
     for chromosome in chromosomes {
@@ -9,6 +14,21 @@ pub(crate) fn chromosome_lines(chromosomes: &[String]) -> Vec<String> {
     lines
 }
 
+pub(crate) fn trace_info_lines() -> Vec<String> {
+    vec!(
+        format!("##INFO=<ID={},Number=1,Type=Integer,\
+        Description=\"Line number of the originating record in the input file\">",
+                INPUT_LINE_KEY),
+        format!("##INFO=<ID={},Number=1,Type=Integer,\
+        Description=\"Index of the allele in the ALT field of the originating record\">",
+                ALT_INDEX_KEY)
+    )
+}
+
+pub(crate) fn trace_header_line(header_line: &str) -> String {
+    format!("{}\t{}\t{}", header_line, INPUT_LINE_COL, ALT_INDEX_COL)
+}
+
 pub(crate) fn write_meta_lines(write: &mut impl Write, meta_lines: &[String])
                                -> Result<(), Error> {
     for meta_line in meta_lines {
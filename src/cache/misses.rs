@@ -1,38 +1,55 @@
 use std::io::{Write, BufWriter};
 use crate::util::error::Error;
 use fs_err::File;
-use crate::genomics::variant::Variant;
+use crate::cache::input::InputVariant;
 use crate::cache::meta_lines;
 
 pub(crate) struct MissesFile {
-    write: BufWriter<Box<dyn Write>>,
+    write: Box<dyn Write>,
+    is_traced: bool,
 }
 
 impl MissesFile {
-    fn write_header(write: &mut BufWriter<Box<dyn Write>>) -> Result<(), Error> {
-        let header = "#CHROM\tPOS\tID\tREF\tALT\n";
-        write.write_all(header.as_bytes())?;
+    fn write_header(write: &mut Box<dyn Write>, is_traced: bool)
+                    -> Result<(), Error> {
+        if is_traced {
+            meta_lines::write_meta_lines(write, &meta_lines::trace_info_lines())?;
+            let header = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+            write.write_all(header.as_bytes())?;
+        } else {
+            let header = "#CHROM\tPOS\tID\tREF\tALT\n";
+            write.write_all(header.as_bytes())?;
+        }
         Ok(())
     }
-    pub(crate) fn from_file(out_file: String, meta_lines: &[String]) -> Result<MissesFile, Error> {
-        let mut write: BufWriter<Box<dyn Write>> =
-            BufWriter::new(Box::new(File::create(out_file)?));
+    pub(crate) fn new(mut write: Box<dyn Write>, meta_lines: &[String], is_traced: bool)
+                      -> Result<MissesFile, Error> {
         meta_lines::write_meta_lines(&mut write, meta_lines)?;
-        MissesFile::write_header(&mut write)?;
-        Ok(MissesFile { write })
+        MissesFile::write_header(&mut write, is_traced)?;
+        Ok(MissesFile { write, is_traced })
     }
-    pub(crate) fn from_stdout(meta_lines: &[String]) -> Result<MissesFile, Error> {
-        let mut write: BufWriter<Box<dyn Write>> =
-            BufWriter::new(Box::new(std::io::stdout()));
-        meta_lines::write_meta_lines(&mut write, meta_lines)?;
-        MissesFile::write_header(&mut write)?;
-        Ok(MissesFile { write })
+    pub(crate) fn from_file(out_file: String, meta_lines: &[String], is_traced: bool)
+                            -> Result<MissesFile, Error> {
+        let write: Box<dyn Write> = Box::new(BufWriter::new(File::create(out_file)?));
+        MissesFile::new(write, meta_lines, is_traced)
     }
-    pub(crate) fn write_variant(&mut self, variant: &Variant) -> Result<(), Error>{
+    pub(crate) fn write_variant(&mut self, input_variant: &InputVariant) -> Result<(), Error>{
+        let variant = &input_variant.variant;
         let line =
-            format!("{}\t{}\t{}\t{}\t{}\n", variant.chrom, variant.pos, variant.canonical_id(),
-                    variant.ref_allele, variant.alt_allele);
+            if self.is_traced {
+                format!("{}\t{}\t{}\t{}\t{}\t.\t.\t{}={};{}={}\n", variant.chrom, variant.pos,
+                        variant.canonical_id(), variant.ref_allele, variant.alt_allele,
+                        meta_lines::INPUT_LINE_KEY, input_variant.i_line,
+                        meta_lines::ALT_INDEX_KEY, input_variant.i_allele)
+            } else {
+                format!("{}\t{}\t{}\t{}\t{}\n", variant.chrom, variant.pos,
+                        variant.canonical_id(), variant.ref_allele, variant.alt_allele)
+            };
         self.write.write_all(line.as_bytes())?;
         Ok(())
     }
-}
\ No newline at end of file
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.write.flush()?;
+        Ok(())
+    }
+}
@@ -1,35 +1,74 @@
 use fs_err::File;
 use crate::util::error::Error;
 use std::io::{Write, BufWriter};
+use crate::cache::input::InputVariant;
 use crate::cache::meta_lines;
+use crate::cache::region_index::RegionIndex;
+use crate::tabix::tsv::ColSelection;
+
+const REGIONS_COL: &str = "Regions";
 
 pub(crate) struct Output {
     write: Box<dyn Write>,
+    is_traced: bool,
+    col_selection_opt: Option<ColSelection>,
+    region_index_opt: Option<RegionIndex>,
 }
 
-fn write_header_line(write: &mut Box<dyn Write>, header_line: &str) -> Result<(), Error> {
+fn write_header_line(write: &mut Box<dyn Write>, header_line: &str, is_traced: bool,
+                     is_annotated: bool) -> Result<(), Error> {
+    let mut header_line = header_line.to_string();
+    if is_annotated {
+        header_line = format!("{}\t{}", header_line, REGIONS_COL);
+    }
+    if is_traced {
+        header_line = meta_lines::trace_header_line(&header_line);
+    }
     write.write_all(format!("{}\n", header_line).as_bytes())?;
     Ok(())
 }
 
 impl Output {
-    pub(crate) fn from_file(out_file: String, header_line: &str, meta_lines: &[String])
-                            -> Result<Output, Error> {
-        let mut write: Box<dyn Write> =
-            Box::new(BufWriter::new(File::create(out_file)?));
+    pub(crate) fn new(mut write: Box<dyn Write>, header_line: &str, meta_lines: &[String],
+                      is_traced: bool, col_selection_opt: Option<ColSelection>,
+                      region_index_opt: Option<RegionIndex>) -> Result<Output, Error> {
         meta_lines::write_meta_lines(&mut write, meta_lines)?;
-        write_header_line(&mut write, header_line)?;
-        Ok(Output { write })
+        let header_line = match &col_selection_opt {
+            None => { header_line.to_string() }
+            Some(col_selection) => { col_selection.header_line() }
+        };
+        write_header_line(&mut write, &header_line, is_traced, region_index_opt.is_some())?;
+        Ok(Output { write, is_traced, col_selection_opt, region_index_opt })
     }
-    pub(crate) fn from_stdout(header_line: &str, meta_lines: &[String])
-                              -> Result<Output, Error> {
-        let mut write: Box<dyn Write> = Box::new(std::io::stdout());
-        meta_lines::write_meta_lines(&mut write, meta_lines)?;
-        write_header_line(&mut write, header_line)?;
-        Ok(Output { write })
+    pub(crate) fn from_file(out_file: String, header_line: &str, meta_lines: &[String],
+                            is_traced: bool, col_selection_opt: Option<ColSelection>,
+                            region_index_opt: Option<RegionIndex>) -> Result<Output, Error> {
+        let write: Box<dyn Write> = Box::new(BufWriter::new(File::create(out_file)?));
+        Output::new(write, header_line, meta_lines, is_traced, col_selection_opt, region_index_opt)
     }
-    pub(crate) fn write_line(&mut self, line: String) -> Result<(), Error> {
-        self.write.write_all(line.as_bytes())?;
+    pub(crate) fn write_line(&mut self, line: String, input_variant: &InputVariant)
+                             -> Result<(), Error> {
+        if !self.is_traced && self.col_selection_opt.is_none() && self.region_index_opt.is_none() {
+            self.write.write_all(line.as_bytes())?;
+            return Ok(());
+        }
+        let mut line = match &self.col_selection_opt {
+            None => { line.trim_end_matches(&['\n', '\r'][..]).to_string() }
+            Some(col_selection) => { col_selection.select_line(&line) }
+        };
+        if let Some(region_index) = &self.region_index_opt {
+            let names = region_index.overlapping_names(&input_variant.variant);
+            let names = if names.is_empty() { String::from(".") } else { names.join(",") };
+            line = format!("{}\t{}", line, names);
+        }
+        if self.is_traced {
+            line = format!("{}\t{}\t{}", line, input_variant.i_line, input_variant.i_allele);
+        }
+        self.write.write_all(format!("{}\n", line).as_bytes())?;
         Ok(())
     }
-}
\ No newline at end of file
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.write.flush()?;
+        Ok(())
+    }
+}
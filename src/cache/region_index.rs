@@ -0,0 +1,169 @@
+use std::cmp;
+use std::collections::HashMap;
+use crate::util::error::Error;
+use crate::cache::regions;
+use crate::cache::regions::RegionsFormat;
+use crate::genomics::chrom_aliases::ChromAliases;
+use crate::genomics::variant::Variant;
+use crate::util::error_policy::ErrorPolicy;
+
+// Levels at or below this are scanned linearly instead of descending further
+const MIN_TREE_LEVEL: u32 = 3;
+
+// begin is 1-based, end is exclusive; max_end is the largest end in the subtree
+struct Entry {
+    begin: u32,
+    end: u32,
+    max_end: u32,
+    i_name: usize,
+}
+
+// Sorted entries with an implicit augmented interval tree, as in cgranges
+struct ChromIndex {
+    entries: Vec<Entry>,
+    max_level: u32,
+}
+
+pub(crate) struct RegionIndex {
+    by_chrom: HashMap<String, ChromIndex>,
+    names: Vec<String>,
+}
+
+impl ChromIndex {
+    fn new(mut entries: Vec<Entry>) -> ChromIndex {
+        entries.sort_by_key(|entry| { (entry.begin, entry.end) });
+        let max_level = ChromIndex::index(&mut entries);
+        ChromIndex { entries, max_level }
+    }
+    // ported from cr_index_prepare in https://github.com/lh3/cgranges
+    fn index(entries: &mut [Entry]) -> u32 {
+        let n = entries.len();
+        if n == 0 {
+            return 0;
+        }
+        let mut i_last: usize = 0;
+        let mut end_last: u32 = 0;
+        for i in (0..n).step_by(2) {
+            i_last = i;
+            entries[i].max_end = entries[i].end;
+            end_last = entries[i].end;
+        }
+        let mut level: u32 = 1;
+        while (1usize << level) <= n {
+            let x = 1usize << (level - 1);
+            for i in (((x << 1) - 1)..n).step_by(x << 2) {
+                let end_left = entries[i - x].max_end;
+                let end_right = if i + x < n { entries[i + x].max_end } else { end_last };
+                entries[i].max_end = cmp::max(entries[i].end, cmp::max(end_left, end_right));
+            }
+            i_last = if (i_last >> level) & 1 != 0 { i_last - x } else { i_last + x };
+            if i_last < n {
+                end_last = cmp::max(end_last, entries[i_last].max_end);
+            }
+            level += 1;
+        }
+        level - 1
+    }
+    // ported from cr_overlap in https://github.com/lh3/cgranges
+    fn overlapping(&self, begin: u32, end: u32) -> Vec<usize> {
+        let entries = &self.entries;
+        let n = entries.len();
+        let mut i_entries = Vec::<usize>::new();
+        if n == 0 {
+            return i_entries;
+        }
+        let mut stack: Vec<(usize, u32, bool)> =
+            vec!(((1usize << self.max_level) - 1, self.max_level, false));
+        while let Some((x, level, is_left_done)) = stack.pop() {
+            if level <= MIN_TREE_LEVEL {
+                let i_begin = (x >> level) << level;
+                let i_end = cmp::min(i_begin + (1usize << (level + 1)) - 1, n);
+                for (i, entry) in entries.iter().enumerate().take(i_end).skip(i_begin) {
+                    if entry.begin >= end {
+                        break;
+                    }
+                    if begin < entry.end {
+                        i_entries.push(i);
+                    }
+                }
+            } else if !is_left_done {
+                stack.push((x, level, true));
+                let y = x - (1usize << (level - 1));
+                if y >= n || entries[y].max_end > begin {
+                    stack.push((y, level - 1, false));
+                }
+            } else if x < n && entries[x].begin < end {
+                if begin < entries[x].end {
+                    i_entries.push(x);
+                }
+                stack.push((x + (1usize << (level - 1)), level - 1, false));
+            }
+        }
+        i_entries.sort_unstable();
+        i_entries
+    }
+}
+
+impl RegionIndex {
+    pub(crate) fn load(file: &str, format_opt: Option<RegionsFormat>,
+                       chrom_aliases: &ChromAliases, error_policy: &ErrorPolicy)
+                       -> Result<RegionIndex, Error> {
+        let mut entries_by_chrom = HashMap::<String, Vec<Entry>>::new();
+        let mut names = Vec::<String>::new();
+        for region in regions::read_regions(file, format_opt, error_policy)? {
+            let (begin, end) = (region.begin(), region.end());
+            let i_name = names.len();
+            names.push(region.name());
+            let chrom = chrom_aliases.canonicalize(region.chrom);
+            entries_by_chrom.entry(chrom).or_default()
+                .push(Entry { begin, end, max_end: end, i_name });
+        }
+        let by_chrom =
+            entries_by_chrom.into_iter().map(|(chrom, entries)| {
+                (chrom, ChromIndex::new(entries))
+            }).collect();
+        Ok(RegionIndex { by_chrom, names })
+    }
+    // names of regions overlapping the variant, ordered by region begin
+    pub(crate) fn overlapping_names(&self, variant: &Variant) -> Vec<&str> {
+        match self.by_chrom.get(variant.chrom.as_str()) {
+            None => { Vec::new() }
+            Some(chrom_index) => {
+                chrom_index.overlapping(variant.pos, variant.end()).into_iter().map(|i| {
+                    self.names[chrom_index.entries[i].i_name].as_str()
+                }).collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::region_index::{ChromIndex, Entry};
+
+    #[test]
+    fn overlapping_matches_brute_force() {
+        let mut seed: u64 = 17;
+        let mut next = |modulus: u64| -> u32 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) % modulus) as u32
+        };
+        for n in [0usize, 1, 2, 7, 8, 9, 100, 1000] {
+            let entries: Vec<Entry> = (0..n).map(|i_name| {
+                let begin = next(100_000) + 1;
+                let end = begin + next(if i_name % 10 == 0 { 20_000 } else { 500 }) + 1;
+                Entry { begin, end, max_end: end, i_name }
+            }).collect();
+            let chrom_index = ChromIndex::new(entries);
+            for _ in 0..200 {
+                let begin = next(110_000) + 1;
+                let end = begin + next(1_000) + 1;
+                let expected: Vec<usize> =
+                    chrom_index.entries.iter().enumerate().filter(|(_, entry)| {
+                        entry.begin < end && begin < entry.end
+                    }).map(|(i, _)| { i }).collect();
+                assert_eq!(chrom_index.overlapping(begin, end), expected);
+            }
+        }
+    }
+}
@@ -1,8 +1,16 @@
+use std::cmp;
 use std::collections::{HashMap, BTreeSet};
 use crate::util::error::Error;
 use fs_err::File;
 use std::io::{BufReader, BufRead};
+use flate2::bufread::MultiGzDecoder;
 use crate::genomics::variant::Variant;
+use crate::genomics::chrom_aliases;
+use crate::genomics::chrom_aliases::ChromAliases;
+use crate::util::error_policy::ErrorPolicy;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const N_LINES_TO_DETECT_FORMAT: usize = 1000;
 
 #[derive(Eq, Ord, PartialOrd, PartialEq, Clone, Copy)]
 struct Interval {
@@ -10,15 +18,35 @@ struct Interval {
     end: u32,
 }
 
-struct Region {
-    chrom: String,
+// begin is 1-based, end is exclusive
+pub(crate) struct Region {
+    pub(crate) chrom: String,
     interval: Interval,
+    pub(crate) name_opt: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum RegionsFormat {
+    Bed,
+    IdFirst,
+    Vcf,
 }
 
 pub(crate) struct Regions {
     by_chrom: HashMap<String, Vec<Interval>>,
 }
 
+#[derive(Clone, Copy)]
+pub(crate) enum FilterReason {
+    OutsideRegions,
+    InExcludedRegions,
+}
+
+pub(crate) struct RegionsFilter {
+    include_opt: Option<Regions>,
+    exclude_opt: Option<Regions>,
+}
+
 struct RegionsBuffer {
     by_chrom: HashMap<String, BTreeSet<Interval>>,
 }
@@ -40,10 +68,184 @@ impl Interval {
 }
 
 impl Region {
-    pub fn new(chrom: String, begin: u32, end: u32) -> Region {
-        let interval = Interval { begin, end };
-        Region { chrom, interval }
+    pub fn new(chrom: String, begin: u32, end: u32, name_opt: Option<String>) -> Region {
+        let interval = Interval { begin, end: cmp::max(end, begin + 1) };
+        Region { chrom, interval, name_opt }
+    }
+    pub(crate) fn begin(&self) -> u32 { self.interval.begin }
+    pub(crate) fn end(&self) -> u32 { self.interval.end }
+    // the name, or chrom:begin-end, 1-based and inclusive, if there is none
+    pub(crate) fn name(&self) -> String {
+        match &self.name_opt {
+            Some(name) => { name.clone() }
+            None => { format!("{}:{}-{}", self.chrom, self.begin(), self.end() - 1) }
+        }
+    }
+}
+
+fn get_field<'a>(fields: &[&'a str], i_field: usize) -> Result<&'a str, Error> {
+    fields.get(i_field).cloned().ok_or_else(|| {
+        Error::from(format!("Need at least {} columns.", i_field + 1))
+    })
+}
+
+fn get_name(fields: &[&str], i_field: usize) -> Option<String> {
+    fields.get(i_field).filter(|name| { !name.is_empty() && **name != "." })
+        .map(|name| { name.to_string() })
+}
+
+fn get_pos(fields: &[&str], i_field: usize) -> Result<u32, Error> {
+    Ok(get_field(fields, i_field)?.parse::<u32>()?)
+}
+
+// begin and end of BED are columns 1 and 2, of id-first columns 2 and 3
+fn has_interval_at(fields: &[&str], i_begin: usize) -> bool {
+    match (fields.get(i_begin), fields.get(i_begin + 1)) {
+        (Some(begin), Some(end)) => {
+            matches!((begin.parse::<u32>(), end.parse::<u32>()),
+                     (Ok(begin), Ok(end)) if begin <= end)
+        }
+        _ => { false }
+    }
+}
+
+impl RegionsFormat {
+    pub(crate) fn parse(format: &str) -> Result<RegionsFormat, Error> {
+        match format {
+            "bed" => { Ok(RegionsFormat::Bed) }
+            "id-first" => { Ok(RegionsFormat::IdFirst) }
+            "vcf" => { Ok(RegionsFormat::Vcf) }
+            _ => {
+                Err(Error::from(format!("Unknown regions format '{}', need 'bed', 'id-first' \
+                or 'vcf'.", format)))
+            }
+        }
+    }
+    // A format fits if every line has begin <= end in its columns, ignoring lines fitting no
+    // format, which are malformed either way. If both fit, as for BED with a numeric name, it is
+    // BED only if column 0 looks like a chromosome and column 1 does not. Otherwise, it is
+    // id-first, which was the only format before BED was supported.
+    fn detect(lines: &[(usize, String)], file: &str) -> Result<RegionsFormat, Error> {
+        let mut is_bed = true;
+        let mut is_id_first = true;
+        let mut is_bed_chrom = true;
+        let mut is_id_first_chrom = true;
+        let mut n_fitting: usize = 0;
+        for (_, line) in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let fits_bed = has_interval_at(&fields, 1);
+            let fits_id_first = fields.len() >= 4 && has_interval_at(&fields, 2);
+            if fits_bed || fits_id_first {
+                n_fitting += 1;
+                is_bed &= fits_bed;
+                is_id_first &= fits_id_first;
+                is_bed_chrom &= chrom_aliases::looks_like_chrom(fields[0]);
+                is_id_first_chrom &= fits_id_first && chrom_aliases::looks_like_chrom(fields[1]);
+            }
+        }
+        match (is_bed, is_id_first) {
+            _ if n_fitting == 0 => {
+                Err(Error::from(format!("Cannot detect format of regions file {}, lines \
+                match neither BED nor id-first.", file)))
+            }
+            (true, false) => { Ok(RegionsFormat::Bed) }
+            (false, true) => { Ok(RegionsFormat::IdFirst) }
+            (true, true) if is_bed_chrom && !is_id_first_chrom => { Ok(RegionsFormat::Bed) }
+            (true, true) => { Ok(RegionsFormat::IdFirst) }
+            (false, false) => {
+                Err(Error::from(format!("Regions file {} has lines only BED fits and lines \
+                only id-first fits. Please specify the regions format.", file)))
+            }
+        }
+    }
+    fn parse_region(&self, fields: &[&str]) -> Result<Region, Error> {
+        match self {
+            RegionsFormat::Bed => {
+                let chrom = get_field(fields, 0)?;
+                let begin = get_pos(fields, 1)? + 1;
+                let end = get_pos(fields, 2)? + 1;
+                Ok(Region::new(chrom.to_string(), begin, end, get_name(fields, 3)))
+            }
+            RegionsFormat::IdFirst => {
+                let chrom = get_field(fields, 1)?;
+                let begin = get_pos(fields, 2)?;
+                let end = get_pos(fields, 3)?;
+                Ok(Region::new(chrom.to_string(), begin, end, get_name(fields, 0)))
+            }
+            RegionsFormat::Vcf => {
+                let chrom = get_field(fields, 0)?;
+                let begin = get_pos(fields, 1)?;
+                let end = begin + get_field(fields, 3)?.len() as u32;
+                Ok(Region::new(chrom.to_string(), begin, end, get_name(fields, 2)))
+            }
+        }
+    }
+}
+
+fn is_vcf_header(line: &str) -> bool {
+    line.starts_with("##fileformat=VCF") || line.starts_with("#CHROM\t")
+}
+
+fn is_comment(line: &str) -> bool {
+    line.is_empty() || line.starts_with('#') || line.starts_with("track") ||
+        line.starts_with("browser")
+}
+
+fn add_region(regions: &mut Vec<Region>, format: RegionsFormat, file: &str, i_line: usize,
+              line: &str, error_policy: &ErrorPolicy) -> Result<(), Error> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match format.parse_region(&fields) {
+        Ok(region) => { regions.push(region) }
+        Err(error) => {
+            let source = format!("line {} of regions file {}", i_line + 1, file);
+            error_policy.handle(&source, line, error)?;
+        }
+    }
+    Ok(())
+}
+
+// BED (0-based, end exclusive), id-first (id, chrom, begin, end, 1-based, end exclusive)
+// or VCF (each record covering its REF allele), optionally gzip-compressed. Unless given, the
+// format is detected from the VCF header or else from the first data lines.
+pub(crate) fn read_regions(file: &str, format_opt: Option<RegionsFormat>,
+                           error_policy: &ErrorPolicy) -> Result<Vec<Region>, Error> {
+    let mut raw_reader = BufReader::new(File::open(file)?);
+    let is_gzipped = raw_reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    let reader: Box<dyn BufRead> =
+        if is_gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(raw_reader)))
+        } else {
+            Box::new(raw_reader)
+        };
+    let mut format_opt = format_opt;
+    // data lines are held back until the format is known
+    let mut pending_lines = Vec::<(usize, String)>::new();
+    let mut regions = Vec::<Region>::new();
+    for (i_line, line_result) in reader.lines().enumerate() {
+        let line = line_result?;
+        if format_opt.is_none() && is_vcf_header(&line) {
+            format_opt = Some(RegionsFormat::Vcf);
+        }
+        if is_comment(&line) {
+            continue;
+        }
+        pending_lines.push((i_line, line));
+        if format_opt.is_none() && pending_lines.len() >= N_LINES_TO_DETECT_FORMAT {
+            format_opt = Some(RegionsFormat::detect(&pending_lines, file)?);
+        }
+        if let Some(format) = format_opt {
+            for (i_line, line) in pending_lines.drain(..) {
+                add_region(&mut regions, format, file, i_line, &line, error_policy)?;
+            }
+        }
+    }
+    if !pending_lines.is_empty() {
+        let format = RegionsFormat::detect(&pending_lines, file)?;
+        for (i_line, line) in pending_lines.drain(..) {
+            add_region(&mut regions, format, file, i_line, &line, error_policy)?;
+        }
     }
+    Ok(regions)
 }
 
 impl RegionsBuffer {
@@ -93,17 +295,12 @@ impl RegionsBuffer {
 }
 
 impl Regions {
-    pub(crate) fn load(file: &str) -> Result<Regions, Error> {
-        let reader = BufReader::new(File::open(file)?);
+    pub(crate) fn load(file: &str, format_opt: Option<RegionsFormat>,
+                       chrom_aliases: &ChromAliases, error_policy: &ErrorPolicy)
+                       -> Result<Regions, Error> {
         let mut regions_buffer = RegionsBuffer::new();
-        for line_result in reader.lines() {
-            let line = line_result?;
-            let mut parts = line.split('\t');
-            let _id = parts.next().ok_or("Need at least four columns.")?;
-            let chrom = parts.next().ok_or("chrom column missing.")?;
-            let begin = parts.next().ok_or("chrom column missing.")?.parse::<u32>()?;
-            let end = parts.next().ok_or("end column missing.")?.parse::<u32>()?;
-            let region = Region::new(chrom.to_string(), begin, end);
+        for mut region in read_regions(file, format_opt, error_policy)? {
+            region.chrom = chrom_aliases.canonicalize(region.chrom);
             regions_buffer.add(region);
         }
         Ok(regions_buffer.as_regions())
@@ -118,6 +315,35 @@ impl Regions {
     }
 }
 
+impl FilterReason {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            FilterReason::OutsideRegions => { "outside_regions" }
+            FilterReason::InExcludedRegions => { "in_excluded_regions" }
+        }
+    }
+}
+
+impl RegionsFilter {
+    pub(crate) fn new(include_opt: Option<Regions>, exclude_opt: Option<Regions>)
+                      -> RegionsFilter {
+        RegionsFilter { include_opt, exclude_opt }
+    }
+    pub(crate) fn is_active(&self) -> bool {
+        self.include_opt.is_some() || self.exclude_opt.is_some()
+    }
+    // None if the variant passes both the include and the exclude regions
+    pub(crate) fn check(&self, variant: &Variant) -> Option<FilterReason> {
+        if self.include_opt.as_ref().is_some_and(|include| { !include.overlap(variant) }) {
+            Some(FilterReason::OutsideRegions)
+        } else if self.exclude_opt.as_ref().is_some_and(|exclude| { exclude.overlap(variant) }) {
+            Some(FilterReason::InExcludedRegions)
+        } else {
+            None
+        }
+    }
+}
+
 fn overlaps_intervals(interval: &Interval, intervals: &[Interval]) -> bool {
     if intervals.is_empty() {
         false
@@ -152,8 +378,10 @@ fn overlaps_intervals(interval: &Interval, intervals: &[Interval]) -> bool {
 #[cfg(test)]
 mod tests {
     use crate::util::error::Error;
-    use crate::cache::regions::Regions;
+    use crate::util::error_policy::ErrorPolicy;
+    use crate::cache::regions::{FilterReason, Regions, RegionsFilter, RegionsFormat};
     use crate::genomics::variant::Variant;
+    use crate::genomics::chrom_aliases::ChromAliases;
     use std::io::{BufWriter, Write};
     use fs_err::File;
 
@@ -193,7 +421,8 @@ mod tests {
     fn load_and_test_regions() -> Result<(), Error> {
         let regions_file_path = "tmp/regions.tsv";
         write_regions_file(&regions_file_path)?;
-        let regions = Regions::load(regions_file_path)?;
+        let regions =
+            Regions::load(regions_file_path, None, &ChromAliases::new(), &ErrorPolicy::strict())?;
         assert_eq!(regions.by_chrom.len(), 3);
         assert_eq!(regions.by_chrom.get("1").unwrap().len(), 1);
         assert_eq!(regions.by_chrom.get("2").unwrap().len(), 1);
@@ -226,5 +455,88 @@ mod tests {
                             &new_variant("X", 50, "A", "T"));
         Ok(())
     }
-}
 
+    #[test]
+    fn load_bed_and_vcf_regions() -> Result<(), Error> {
+        let bed_file_path = "tmp/regions.bed";
+        std::fs::write(bed_file_path, "track name=test\n1\t99\t200\tregion1\n")?;
+        let error_policy = ErrorPolicy::strict();
+        let regions = Regions::load(bed_file_path, None, &ChromAliases::new(), &error_policy)?;
+        assert_not_included(&regions, &new_variant("1", 99, "A", "T"));
+        assert_included(&regions, &new_variant("1", 100, "A", "T"));
+        assert_included(&regions, &new_variant("1", 200, "A", "T"));
+        assert_not_included(&regions, &new_variant("1", 201, "A", "T"));
+        let vcf_file_path = "tmp/regions.vcf";
+        std::fs::write(vcf_file_path,
+                       "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\n\
+                        2\t100\trs1\tACG\tA\n")?;
+        let regions = Regions::load(vcf_file_path, None, &ChromAliases::new(), &error_policy)?;
+        assert_not_included(&regions, &new_variant("2", 99, "A", "T"));
+        assert_included(&regions, &new_variant("2", 102, "A", "T"));
+        assert_not_included(&regions, &new_variant("2", 103, "A", "T"));
+        Ok(())
+    }
+
+    #[test]
+    fn detect_bed_with_numeric_names() -> Result<(), Error> {
+        let chrom_aliases = ChromAliases::new();
+        let error_policy = ErrorPolicy::strict();
+        let bed_file_path = "tmp/regions_numeric_names.bed";
+        std::fs::write(bed_file_path, "1\t99\t200\t5\n1\t299\t400\t17\nchr2\t999\t2000\t3\n")?;
+        let regions = Regions::load(bed_file_path, None, &chrom_aliases, &error_policy)?;
+        assert_included(&regions, &new_variant("1", 100, "A", "T"));
+        assert_not_included(&regions, &new_variant("1", 250, "A", "T"));
+        assert_included(&regions, &new_variant("1", 400, "A", "T"));
+        assert_included(&regions, &new_variant("2", 1000, "A", "T"));
+        assert_not_included(&regions, &new_variant("5", 150, "A", "T"));
+        let bed_file_path = "tmp/regions_large_names.bed";
+        std::fs::write(bed_file_path, "chr1\t1000\t2000\t3000\n")?;
+        let regions = Regions::load(bed_file_path, None, &chrom_aliases, &error_policy)?;
+        assert_included(&regions, &new_variant("1", 1500, "A", "T"));
+        let bed_file_path = "tmp/regions_bed_forced.bed";
+        std::fs::write(bed_file_path, "5\t1\t100\t200\n")?;
+        let regions =
+            Regions::load(bed_file_path, Some(RegionsFormat::parse("bed")?), &chrom_aliases,
+                          &error_policy)?;
+        assert_included(&regions, &new_variant("5", 50, "A", "T"));
+        assert_not_included(&regions, &new_variant("1", 150, "A", "T"));
+        Ok(())
+    }
+
+    #[test]
+    fn detect_ambiguous_regions_as_id_first() -> Result<(), Error> {
+        let chrom_aliases = ChromAliases::new();
+        let error_policy = ErrorPolicy::strict();
+        let ambiguous_file_path = "tmp/regions_ambiguous.tsv";
+        std::fs::write(ambiguous_file_path, "5\t1\t100\t200\nchrX\t2\t300\t400\n")?;
+        let regions = Regions::load(ambiguous_file_path, None, &chrom_aliases, &error_policy)?;
+        assert_included(&regions, &new_variant("1", 100, "A", "T"));
+        assert_included(&regions, &new_variant("1", 199, "A", "T"));
+        assert_not_included(&regions, &new_variant("1", 200, "A", "T"));
+        assert_included(&regions, &new_variant("2", 350, "A", "T"));
+        assert_not_included(&regions, &new_variant("5", 150, "A", "T"));
+        assert_not_included(&regions, &new_variant("X", 350, "A", "T"));
+        Ok(())
+    }
+
+    #[test]
+    fn filter_by_include_and_exclude_regions() -> Result<(), Error> {
+        let include_file_path = "tmp/include.bed";
+        std::fs::write(include_file_path, "1\t0\t1000\n")?;
+        let exclude_file_path = "tmp/exclude.bed";
+        std::fs::write(exclude_file_path, "1\t499\t600\n")?;
+        let chrom_aliases = ChromAliases::new();
+        let error_policy = ErrorPolicy::strict();
+        let filter =
+            RegionsFilter::new(Some(Regions::load(include_file_path, None, &chrom_aliases,
+                                                  &error_policy)?),
+                               Some(Regions::load(exclude_file_path, None, &chrom_aliases,
+                                                  &error_policy)?));
+        assert!(filter.check(&new_variant("1", 100, "A", "T")).is_none());
+        assert!(matches!(filter.check(&new_variant("1", 550, "A", "T")),
+                         Some(FilterReason::InExcludedRegions)));
+        assert!(matches!(filter.check(&new_variant("2", 550, "A", "T")),
+                         Some(FilterReason::OutsideRegions)));
+        Ok(())
+    }
+}
@@ -1,51 +1,386 @@
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
 use fs_err::File;
 
 use bgzip::BGZFReader;
-use bgzip::tabix::Tabix;
 
 use crate::cache::{join, meta_lines};
 use crate::cache::input::Input;
+use crate::cache::join::{HitsOutput, JoinStats, Worker, Writers};
+use crate::cache::annotated::AnnotatedOutput;
 use crate::cache::misses::MissesFile;
 use crate::cache::output::Output;
-use crate::cache::regions::Regions;
-use crate::config::TabixConfig;
+use crate::cache::region_index::RegionIndex;
+use crate::cache::regions::{Regions, RegionsFilter, RegionsFormat};
+use crate::cache::variant_records::VariantRecordsFile;
+use crate::cache::stream::{Channel, SharedStream};
+use crate::config::{ChromsConfig, TabixConfig, TabixInputConfig, TabixOutputConfig};
+use crate::genomics::chrom_aliases::ChromAliases;
+use crate::genomics::reference::Reference;
+use crate::tabix::data_index::DataIndex;
+use crate::tabix::index_builder::IndexFormat;
 use crate::tabix::tsv;
-use crate::tabix::tsv::IAlleleCols;
+use crate::tabix::tsv::{ColSelection, IAlleleCols};
 use crate::util::error::Error;
+use crate::util::error_policy::ErrorPolicy;
 
-pub(crate) fn run_cache(tabix_config: TabixConfig) -> Result<(), Error> {
+const REF_MISMATCH_WARNING_PERCENTAGE: f64 = 5.0;
+
+pub(crate) fn run_cache(tabix_config: TabixConfig) -> Result<JoinStats, Error> {
     let input_config = &tabix_config.input_config;
-    let input = Input::from_file(&input_config.input_file)?;
-    let mut bgzf =
-        BGZFReader::new(File::open(&input_config.cache_file)?);
-    let tabix =
-        Tabix::from_reader(&mut File::open(&input_config.index_file)?)?;
-    let chroms: Vec<String> = tabix.names.iter().filter_map(|raw| {
-        String::from_utf8(raw[0..(raw.len() - 1)].to_owned()).ok()
+    let output_config = tabix_config.output_config;
+    check_output_files(&output_config)?;
+    let chrom_aliases = load_chrom_aliases(&tabix_config.chroms_config)?;
+    let errors_config = &tabix_config.errors_config;
+    let error_policy =
+        Arc::new(ErrorPolicy::new(errors_config.mode, &errors_config.rejects_file_opt)?);
+    let input_error_policy =
+        if errors_config.input_mode == errors_config.mode {
+            error_policy.clone()
+        } else {
+            Arc::new(ErrorPolicy::new(errors_config.input_mode, &None)?)
+        };
+    let input = Input::from_file(&input_config.input_file, chrom_aliases.clone())?;
+    let mut bgzfs = Vec::<BGZFReader<File>>::with_capacity(tabix_config.threads);
+    for _ in 0..tabix_config.threads {
+        bgzfs.push(BGZFReader::new(File::open(&input_config.cache_file)?));
+    }
+    let data_index = DataIndex::from_file(&input_config.index_file)?;
+    // hits keep the chromosome names of the data file, other outputs use the canonical ones
+    let canonical_chroms: Vec<String> = data_index.names.iter().map(|chrom| {
+        String::from(chrom_aliases.canonical(chrom))
     }).collect();
     let vcf_version_line = String::from("##fileformat=VCFv4.0");
-    let mut meta_lines = vec!(vcf_version_line);
-    meta_lines.append(&mut meta_lines::chromosome_lines(chroms.as_slice()));
-    let header_line = tsv::get_header_line(&mut bgzf)?;
-    let regions_opt = match &input_config.regions_file_opt {
+    let mut meta_lines = vec!(vcf_version_line.clone());
+    meta_lines.append(&mut meta_lines::chromosome_lines(canonical_chroms.as_slice()));
+    let mut hits_meta_lines = vec!(vcf_version_line);
+    hits_meta_lines.append(&mut meta_lines::chromosome_lines(data_index.names.as_slice()));
+    let header_line = tsv::get_header_line(&mut bgzfs[0])?;
+    let regions_format_opt = input_config.regions_format_opt;
+    let include_opt =
+        load_regions(&input_config.regions_file_opt, regions_format_opt, &chrom_aliases,
+                     &error_policy)?;
+    let exclude_opt =
+        load_regions(&input_config.exclude_regions_file_opt, regions_format_opt,
+                     &chrom_aliases, &error_policy)?;
+    let filter = RegionsFilter::new(include_opt, exclude_opt);
+    let region_index_opt = match &output_config.annotate_regions_file_opt {
         None => { None }
-        Some(regions_file) => { Some(Regions::load(regions_file)?) }
+        Some(annotate_regions_file) => {
+            Some(RegionIndex::load(annotate_regions_file, regions_format_opt, &chrom_aliases,
+                                   &error_policy)?)
+        }
     };
-    let output = match tabix_config.output_file_opt {
-        None => { Output::from_stdout(&header_line, &meta_lines)? }
-        Some(output_file) => {
-            Output::from_file(output_file, &header_line, &meta_lines)?
+    let i_allele_cols =
+        IAlleleCols::parse(&header_line, &input_config.col_ref, &input_config.col_alt)?;
+    let columns_config = &tabix_config.columns_config;
+    let col_selection_opt =
+        if columns_config.columns.is_empty() && columns_config.exclude_columns.is_empty() {
+            None
+        } else {
+            Some(ColSelection::parse(&header_line, &columns_config.columns,
+                                     &columns_config.exclude_columns)?)
+        };
+    let stream_opt = output_config.stream_format_opt.map(SharedStream::new);
+    let output_file_opt = non_stdout(output_config.output_file_opt);
+    let output = match (output_config.annotate_mode_opt, output_file_opt) {
+        (None, None) => {
+            HitsOutput::Lines(Output::new(stdout_write(&stream_opt, Channel::Hits), &header_line,
+                                          &hits_meta_lines, output_config.trace_input,
+                                          col_selection_opt, region_index_opt)?)
+        }
+        (None, Some(output_file)) => {
+            HitsOutput::Lines(Output::from_file(output_file, &header_line, &hits_meta_lines,
+                                                output_config.trace_input, col_selection_opt,
+                                                region_index_opt)?)
+        }
+        (Some(annotate_mode), output_file_opt) => {
+            let col_selection = match col_selection_opt {
+                Some(col_selection) if !columns_config.columns.is_empty() => { col_selection }
+                _ => {
+                    non_location_cols(&header_line, &data_index.format, &i_allele_cols,
+                                      &columns_config.exclude_columns)?
+                }
+            };
+            let annotated = match output_file_opt {
+                None => {
+                    AnnotatedOutput::new(stdout_write(&stream_opt, Channel::Hits),
+                                         &input.header_lines, col_selection, annotate_mode,
+                                         region_index_opt)?
+                }
+                Some(output_file) => {
+                    AnnotatedOutput::from_file(output_file, &input.header_lines, col_selection,
+                                               annotate_mode, region_index_opt)?
+                }
+            };
+            HitsOutput::Annotated(annotated)
         }
     };
-    let misses_file = match tabix_config.cache_misses_file_opt {
-        None => { MissesFile::from_stdout(&meta_lines)? }
+    let misses_file = match non_stdout(output_config.cache_misses_file_opt) {
+        None => {
+            MissesFile::new(stdout_write(&stream_opt, Channel::Misses), &meta_lines,
+                            output_config.trace_input)?
+        }
         Some(cache_misses_file) => {
-            MissesFile::from_file(cache_misses_file, &meta_lines)?
+            MissesFile::from_file(cache_misses_file, &meta_lines, output_config.trace_input)?
         }
     };
-    let i_allele_cols =
-        IAlleleCols::parse(&header_line, &input_config.col_ref, &input_config.col_alt)?;
-    join::join_input_with_data(input, bgzf, tabix, regions_opt, output, misses_file,
-                               i_allele_cols)?;
+    let ref_mismatches_file_opt = match output_config.ref_mismatches_file_opt {
+        None => { None }
+        Some(ref_mismatches_file) => {
+            Some(VariantRecordsFile::ref_mismatches(ref_mismatches_file, &meta_lines,
+                                                    output_config.trace_input)?)
+        }
+    };
+    let filtered_file_opt = match output_config.filtered_file_opt {
+        None => { None }
+        Some(filtered_file) => {
+            Some(VariantRecordsFile::filtered(filtered_file, &meta_lines,
+                                              output_config.trace_input)?)
+        }
+    };
+    let writers = Writers::new(output, misses_file, ref_mismatches_file_opt, filtered_file_opt);
+    let mut workers = Vec::<Worker<File>>::with_capacity(bgzfs.len());
+    for bgzf in bgzfs {
+        let reference_opt = open_reference(&input_config.reference_file_opt, &chrom_aliases)?;
+        workers.push(Worker::new(bgzf, input_config.cache_file.clone(), tabix_config.normalize,
+                                 reference_opt, chrom_aliases.clone(), error_policy.clone()));
+    }
+    let is_filtering = filter.is_active();
+    let stats =
+        join::join_input_with_data(input, workers, data_index, filter, writers, i_allele_cols,
+                                   input_error_policy)?;
+    error_policy.flush()?;
+    if let Some(rejects_file) = &errors_config.rejects_file_opt {
+        eprintln!("Wrote {} malformed lines to {}.", error_policy.n_errors(), rejects_file);
+    }
+    if is_filtering {
+        report_filtered(&stats, input_config);
+    }
+    if input_config.reference_file_opt.is_some() {
+        report_ref_mismatches(&stats);
+    }
+    if let Some(summary_file) = &output_config.summary_file_opt {
+        write_summary(summary_file, &stats)?;
+    }
+    Ok(stats)
+}
+
+pub(crate) fn load_chrom_aliases(chroms_config: &ChromsConfig) -> Result<ChromAliases, Error> {
+    let mut chrom_aliases = match &chroms_config.assembly_opt {
+        None => { ChromAliases::new() }
+        Some(assembly) => { assembly.chrom_aliases() }
+    };
+    if let Some(chrom_aliases_file) = &chroms_config.chrom_aliases_file_opt {
+        chrom_aliases.add_from_file(chrom_aliases_file)?;
+    }
+    Ok(chrom_aliases)
+}
+
+// all columns except chromosome, position and alleles, which are in the input already, and
+// except those excluded
+fn non_location_cols(header_line: &str, format: &IndexFormat, i_allele_cols: &IAlleleCols,
+                     exclude_columns: &[String]) -> Result<ColSelection, Error> {
+    let mut col_selection = ColSelection::parse(header_line, &[], exclude_columns)?;
+    col_selection.cols.retain(|(i_col, _)| {
+        *i_col != i_allele_cols.i_col_ref && *i_col != i_allele_cols.i_col_alt &&
+            [format.col_seq, format.col_begin, format.col_end].iter().all(|col| {
+                *col <= 0 || (*col - 1) as usize != *i_col
+            })
+    });
+    if col_selection.cols.is_empty() {
+        return Err(Error::from("No columns of data file left to annotate with."));
+    }
+    Ok(col_selection)
+}
+
+// the file descriptor behind names like -, /dev/stdout or /dev/fd/1, if any
+fn special_fd(file: &str) -> Option<&str> {
+    match file {
+        "-" | "/dev/stdout" => { Some("1") }
+        "/dev/stderr" => { Some("2") }
+        _ => { file.strip_prefix("/dev/fd/").or_else(|| { file.strip_prefix("/proc/self/fd/") }) }
+    }
+}
+
+fn is_stdout_file(file: &str) -> bool { special_fd(file) == Some("1") }
+
+// files given by different names, but written through the same file descriptor, are the same
+fn writer_key(file: &str) -> String {
+    match special_fd(file) {
+        None => { String::from(file) }
+        Some(fd) => { format!("/dev/fd/{}", fd) }
+    }
+}
+
+fn is_stdout(file_opt: &Option<String>) -> bool {
+    file_opt.as_deref().is_none_or(is_stdout_file)
+}
+
+fn non_stdout(file_opt: Option<String>) -> Option<String> {
+    file_opt.filter(|file| { !is_stdout_file(file) })
+}
+
+// separate writers on the same file or on stdout would garble each other's lines
+fn check_output_files(output_config: &TabixOutputConfig) -> Result<(), Error> {
+    if is_stdout(&output_config.output_file_opt) && is_stdout(&output_config.cache_misses_file_opt)
+        && output_config.stream_format_opt.is_none() {
+        return Err(Error::from("Output and cache misses would both be written to stdout. Give \
+        --output-file or --cache-misses-file, or use --single-stream to share stdout."));
+    }
+    let other_files =
+        [&output_config.ref_mismatches_file_opt, &output_config.filtered_file_opt,
+            &output_config.summary_file_opt];
+    if let Some(file) = other_files.into_iter().flatten().find(|file| { is_stdout_file(file) }) {
+        return Err(Error::from(format!("Cannot write to {}, only output and cache misses can \
+        go to stdout.", file)));
+    }
+    let files: Vec<&str> =
+        [&output_config.output_file_opt, &output_config.cache_misses_file_opt].into_iter()
+            .chain(other_files).flatten().map(|file| { file.as_str() })
+            .filter(|file| { !is_stdout_file(file) }).collect();
+    let keys: Vec<String> = files.iter().map(|file| { writer_key(file) }).collect();
+    for (i, key) in keys.iter().enumerate() {
+        if keys[..i].contains(key) {
+            return Err(Error::from(format!("File {} is given for more than one output.",
+                                           files[i])));
+        }
+    }
     Ok(())
 }
+
+// stdout, or its share of the single stream if one was asked for
+fn stdout_write(stream_opt: &Option<SharedStream>, channel: Channel) -> Box<dyn Write> {
+    match stream_opt {
+        None => { Box::new(BufWriter::new(std::io::stdout())) }
+        Some(stream) => { stream.channel(channel) }
+    }
+}
+
+fn load_regions(regions_file_opt: &Option<String>, format_opt: Option<RegionsFormat>,
+                chrom_aliases: &ChromAliases, error_policy: &ErrorPolicy)
+                -> Result<Option<Regions>, Error> {
+    match regions_file_opt {
+        None => { Ok(None) }
+        Some(regions_file) => {
+            Ok(Some(Regions::load(regions_file, format_opt, chrom_aliases, error_policy)?))
+        }
+    }
+}
+
+fn open_reference(reference_file_opt: &Option<String>, chrom_aliases: &ChromAliases)
+                  -> Result<Option<Reference>, Error> {
+    match reference_file_opt {
+        None => { Ok(None) }
+        Some(reference_file) => {
+            Ok(Some(Reference::from_file(reference_file, chrom_aliases.clone())?))
+        }
+    }
+}
+
+fn write_summary(summary_file: &str, stats: &JoinStats) -> Result<(), Error> {
+    let entries: Vec<String> =
+        stats.counts().iter().map(|(key, count)| { format!("  \"{}\": {}", key, count) })
+            .collect();
+    let mut write = BufWriter::new(File::create(summary_file)?);
+    writeln!(write, "{{\n{}\n}}", entries.join(",\n"))?;
+    write.flush()?;
+    Ok(())
+}
+
+fn report_filtered(stats: &JoinStats, input_config: &TabixInputConfig) {
+    if let Some(regions_file) = &input_config.regions_file_opt {
+        eprintln!("Filtered out {} variants outside regions of {}.", stats.n_outside_regions,
+                  regions_file);
+    }
+    if let Some(exclude_regions_file) = &input_config.exclude_regions_file_opt {
+        eprintln!("Filtered out {} variants in excluded regions of {}.",
+                  stats.n_in_excluded_regions, exclude_regions_file);
+    }
+}
+
+fn report_ref_mismatches(stats: &JoinStats) {
+    let percentage =
+        if stats.n_variants == 0 {
+            0.0
+        } else {
+            100.0 * (stats.n_ref_mismatches as f64) / (stats.n_variants as f64)
+        };
+    eprintln!("REF allele did not match reference for {} of {} variants ({:.2}%).",
+              stats.n_ref_mismatches, stats.n_variants, percentage);
+    if percentage > REF_MISMATCH_WARNING_PERCENTAGE {
+        eprintln!("Warning: this many mismatches suggest input and reference are on different \
+        assemblies.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::join::JoinStats;
+    use crate::cache::run::{check_output_files, non_location_cols, write_summary};
+    use crate::cache::stream::StreamFormat;
+    use crate::config::TabixOutputConfig;
+    use crate::tabix::index_builder::IndexFormat;
+    use crate::tabix::tsv::IAlleleCols;
+    use crate::util::error::Error;
+
+    fn col_names(exclude_columns: &[&str]) -> Result<Vec<String>, Error> {
+        let header_line = "#chrom\tpos\tref\talt\tgene\tscore";
+        let exclude_columns: Vec<String> =
+            exclude_columns.iter().map(|col| { String::from(*col) }).collect();
+        let col_selection =
+            non_location_cols(header_line, &IndexFormat::for_preset(None)?,
+                              &IAlleleCols::new(2, 3), &exclude_columns)?;
+        Ok(col_selection.cols.into_iter().map(|(_, name)| { name }).collect())
+    }
+
+    #[test]
+    fn annotate_with_non_location_cols() -> Result<(), Error> {
+        assert_eq!(col_names(&[])?, vec!("gene", "score"));
+        assert_eq!(col_names(&["gene"])?, vec!("score"));
+        assert!(col_names(&["gene", "score"]).is_err());
+        assert!(col_names(&["nope"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn write_counts_as_json() -> Result<(), Error> {
+        let mut stats = JoinStats::new();
+        stats.n_input_lines = 2;
+        stats.n_inputs = 3;
+        stats.n_variants = 3;
+        stats.n_hits = 2;
+        stats.n_misses = 1;
+        let summary_file = "tmp/summary.json";
+        write_summary(summary_file, &stats)?;
+        let expected =
+            "{\n  \"input_lines\": 2,\n  \"input_variants\": 3,\n  \"parse_errors\": 0,\n  \
+            \"outside_regions\": 0,\n  \"in_excluded_regions\": 0,\n  \"looked_up\": 3,\n  \
+            \"hits\": 2,\n  \"multiple_hits\": 0,\n  \"misses\": 1,\n  \
+            \"unknown_chroms\": 0,\n  \"ref_mismatches\": 0\n}\n";
+        assert_eq!(std::fs::read_to_string(summary_file)?, expected);
+        Ok(())
+    }
+
+    fn check_files(output_file: &str, misses_file: &str, summary_file_opt: Option<&str>,
+                   stream_format_opt: Option<StreamFormat>) -> bool {
+        let output_config =
+            TabixOutputConfig::new(Some(String::from(output_file)),
+                                   Some(String::from(misses_file)), None, None, None, None,
+                                   summary_file_opt.map(String::from), stream_format_opt, false);
+        check_output_files(&output_config).is_ok()
+    }
+
+    #[test]
+    fn reject_shared_output_files() {
+        assert!(check_files("hits.tsv", "-", None, None));
+        assert!(check_files("/dev/fd/1", "misses.vcf", None, None));
+        assert!(!check_files("/dev/fd/1", "/dev/stdout", None, None));
+        assert!(!check_files("-", "/proc/self/fd/1", None, None));
+        assert!(check_files("-", "/dev/fd/1", None, Some(StreamFormat::Tagged)));
+        assert!(!check_files("hits.tsv", "misses.vcf", Some("/dev/fd/1"), None));
+        assert!(!check_files("hits.tsv", "hits.tsv", None, None));
+        assert!(!check_files("/dev/stderr", "/dev/fd/2", None, None));
+        assert!(check_files("/dev/fd/3", "/dev/fd/4", None, None));
+    }
+}
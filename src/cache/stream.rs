@@ -0,0 +1,203 @@
+use std::cell::RefCell;
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+use crate::util::error::Error;
+
+#[derive(Clone, Copy)]
+pub(crate) enum StreamFormat {
+    Tagged,
+    JsonLines,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Channel {
+    Hits,
+    Misses,
+}
+
+// Hits and misses sharing stdout, written line by line so they never interleave mid-line
+pub(crate) struct SharedStream {
+    format: StreamFormat,
+    write: Rc<RefCell<Box<dyn Write>>>,
+}
+
+// Tagged lines are the channel tag, a tab and the line as it would be in its own file. JSON
+// lines are objects keyed by the columns of the channel's header line; meta lines are dropped.
+struct ChannelWrite {
+    format: StreamFormat,
+    channel: Channel,
+    write: Rc<RefCell<Box<dyn Write>>>,
+    pending: Vec<u8>,
+    keys: Vec<String>,
+}
+
+impl StreamFormat {
+    pub(crate) fn parse(format: &str) -> Result<StreamFormat, Error> {
+        match format {
+            "tagged" => { Ok(StreamFormat::Tagged) }
+            "jsonl" => { Ok(StreamFormat::JsonLines) }
+            _ => {
+                Err(Error::from(format!("Unknown stream format '{}', need 'tagged' or 'jsonl'.",
+                                        format)))
+            }
+        }
+    }
+}
+
+impl Channel {
+    fn tag(&self) -> &'static str {
+        match self {
+            Channel::Hits => { "HIT" }
+            Channel::Misses => { "MISS" }
+        }
+    }
+    fn json_type(&self) -> &'static str {
+        match self {
+            Channel::Hits => { "hit" }
+            Channel::Misses => { "miss" }
+        }
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => { json.push_str("\\\"") }
+            '\\' => { json.push_str("\\\\") }
+            '\t' => { json.push_str("\\t") }
+            '\n' => { json.push_str("\\n") }
+            '\r' => { json.push_str("\\r") }
+            c if (c as u32) < 0x20 => { json.push_str(&format!("\\u{:04x}", c as u32)) }
+            c => { json.push(c) }
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl SharedStream {
+    pub(crate) fn new(format: StreamFormat) -> SharedStream {
+        SharedStream::with_write(format, Box::new(BufWriter::new(std::io::stdout())))
+    }
+    fn with_write(format: StreamFormat, write: Box<dyn Write>) -> SharedStream {
+        SharedStream { format, write: Rc::new(RefCell::new(write)) }
+    }
+    pub(crate) fn channel(&self, channel: Channel) -> Box<dyn Write> {
+        Box::new(ChannelWrite {
+            format: self.format,
+            channel,
+            write: self.write.clone(),
+            pending: Vec::new(),
+            keys: Vec::new(),
+        })
+    }
+}
+
+impl ChannelWrite {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let mut write = self.write.borrow_mut();
+        match self.format {
+            StreamFormat::Tagged => { writeln!(write, "{}\t{}", self.channel.tag(), line) }
+            StreamFormat::JsonLines => {
+                if line.starts_with("##") {
+                    Ok(())
+                } else if let Some(header_line) = line.strip_prefix('#') {
+                    self.keys = header_line.split('\t').map(String::from).collect();
+                    Ok(())
+                } else {
+                    let mut entries =
+                        vec!(format!("\"type\":{}", json_string(self.channel.json_type())));
+                    for (i, value) in line.split('\t').enumerate() {
+                        let key = match self.keys.get(i) {
+                            Some(key) => { key.clone() }
+                            None => { format!("col{}", i + 1) }
+                        };
+                        entries.push(format!("{}:{}", json_string(&key), json_string(value)));
+                    }
+                    writeln!(write, "{{{}}}", entries.join(","))
+                }
+            }
+        }
+    }
+}
+
+impl Write for ChannelWrite {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(i_newline) = self.pending.iter().position(|byte| { *byte == b'\n' }) {
+            let bytes: Vec<u8> = self.pending.drain(..=i_newline).collect();
+            let line = String::from_utf8_lossy(&bytes);
+            self.write_line(line.trim_end_matches(&['\n', '\r'][..]))?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write.borrow_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+    use crate::cache::stream::{json_string, Channel, SharedStream, StreamFormat};
+
+    #[derive(Clone)]
+    struct Buffer {
+        bytes: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    // hits and misses written in turns, the hits line in two parts
+    fn write_stream(format: StreamFormat) -> std::io::Result<String> {
+        let buffer = Buffer { bytes: Rc::new(RefCell::new(Vec::new())) };
+        let stream = SharedStream::with_write(format, Box::new(buffer.clone()));
+        let mut hits = stream.channel(Channel::Hits);
+        let mut misses = stream.channel(Channel::Misses);
+        hits.write_all(b"##contig=<ID=1>\n#chrom\tpos\tgene\n")?;
+        misses.write_all(b"#CHROM\tPOS\n")?;
+        hits.write_all(b"1\t100")?;
+        misses.write_all(b"2\t200\n")?;
+        hits.write_all(b"\tA\"B\textra\n")?;
+        hits.flush()?;
+        misses.flush()?;
+        let bytes = buffer.bytes.borrow().clone();
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    #[test]
+    fn escape_json_strings() {
+        assert_eq!(json_string("rs1"), "\"rs1\"");
+        assert_eq!(json_string("a\"b\\c\td"), "\"a\\\"b\\\\c\\td\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+    }
+
+    #[test]
+    fn write_tagged_stream() -> std::io::Result<()> {
+        let expected =
+            "HIT\t##contig=<ID=1>\nHIT\t#chrom\tpos\tgene\nMISS\t#CHROM\tPOS\n\
+            MISS\t2\t200\nHIT\t1\t100\tA\"B\textra\n";
+        assert_eq!(write_stream(StreamFormat::Tagged)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn write_json_lines_stream() -> std::io::Result<()> {
+        let expected =
+            "{\"type\":\"miss\",\"CHROM\":\"2\",\"POS\":\"200\"}\n\
+            {\"type\":\"hit\",\"chrom\":\"1\",\"pos\":\"100\",\"gene\":\"A\\\"B\",\
+            \"col4\":\"extra\"}\n";
+        assert_eq!(write_stream(StreamFormat::JsonLines)?, expected);
+        Ok(())
+    }
+}
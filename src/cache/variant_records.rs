@@ -0,0 +1,88 @@
+use std::io::{Write, BufWriter};
+use crate::util::error::Error;
+use fs_err::File;
+use crate::cache::input::InputVariant;
+use crate::cache::meta_lines;
+
+const EXPECTED_REF_COL: &str = "EXPECTED_REF";
+const FILTER_COL: &str = "FILTER";
+
+// Variants set aside during the join, each with one more column saying why
+pub(crate) struct VariantRecordsFile {
+    write: BufWriter<File>,
+    is_traced: bool,
+}
+
+impl VariantRecordsFile {
+    fn from_file(out_file: String, meta_lines: &[String], last_col: &str, is_traced: bool)
+                 -> Result<VariantRecordsFile, Error> {
+        let mut write = BufWriter::new(File::create(out_file)?);
+        meta_lines::write_meta_lines(&mut write, meta_lines)?;
+        let header_line = format!("#CHROM\tPOS\tID\tREF\tALT\t{}", last_col);
+        if is_traced {
+            writeln!(write, "{}", meta_lines::trace_header_line(&header_line))?;
+        } else {
+            writeln!(write, "{}", header_line)?;
+        }
+        Ok(VariantRecordsFile { write, is_traced })
+    }
+    // variants whose REF does not match the reference, with the expected bases
+    pub(crate) fn ref_mismatches(out_file: String, meta_lines: &[String], is_traced: bool)
+                                 -> Result<VariantRecordsFile, Error> {
+        VariantRecordsFile::from_file(out_file, meta_lines, EXPECTED_REF_COL, is_traced)
+    }
+    // variants dropped by the regions filters, with the reason
+    pub(crate) fn filtered(out_file: String, meta_lines: &[String], is_traced: bool)
+                           -> Result<VariantRecordsFile, Error> {
+        VariantRecordsFile::from_file(out_file, meta_lines, FILTER_COL, is_traced)
+    }
+    pub(crate) fn write_variant(&mut self, input_variant: &InputVariant, last_value: &str)
+                                -> Result<(), Error> {
+        let variant = &input_variant.variant;
+        let line =
+            if self.is_traced {
+                format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n", variant.chrom, variant.pos,
+                        variant.canonical_id(), variant.ref_allele, variant.alt_allele,
+                        last_value, input_variant.i_line, input_variant.i_allele)
+            } else {
+                format!("{}\t{}\t{}\t{}\t{}\t{}\n", variant.chrom, variant.pos,
+                        variant.canonical_id(), variant.ref_allele, variant.alt_allele,
+                        last_value)
+            };
+        self.write.write_all(line.as_bytes())?;
+        Ok(())
+    }
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.write.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::cache::input::InputVariant;
+    use crate::cache::variant_records::VariantRecordsFile;
+    use crate::genomics::variant::Variant;
+    use crate::util::error::Error;
+
+    #[test]
+    fn write_records_with_last_column() -> Result<(), Error> {
+        let variant =
+            Variant::new(String::from("1"), 100, String::from("A"), String::from("G"));
+        let input_variant = InputVariant::new(variant, 3, 1, Arc::new(String::new()));
+        let file = String::from("tmp/variant_records_ref_mismatches.tsv");
+        let mut ref_mismatches = VariantRecordsFile::ref_mismatches(file.clone(), &[], false)?;
+        ref_mismatches.write_variant(&input_variant, "C")?;
+        ref_mismatches.flush()?;
+        assert_eq!(std::fs::read_to_string(&file)?,
+                   "#CHROM\tPOS\tID\tREF\tALT\tEXPECTED_REF\n1\t100\t1:100_A/G\tA\tG\tC\n");
+        let file = String::from("tmp/variant_records_filtered.tsv");
+        let mut filtered = VariantRecordsFile::filtered(file.clone(), &[], false)?;
+        filtered.write_variant(&input_variant, "outside_regions")?;
+        filtered.flush()?;
+        assert_eq!(std::fs::read_to_string(&file)?,
+                   "#CHROM\tPOS\tID\tREF\tALT\tFILTER\n1\t100\t1:100_A/G\tA\tG\toutside_regions\n");
+        Ok(())
+    }
+}
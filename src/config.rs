@@ -1,8 +1,20 @@
 use crate::util::error::Error;
 use clap::{App, SubCommand, Arg};
+use crate::genomics::assembly::Hg;
+use crate::tabix::index_builder::{IndexFormat, FLAG_ZERO_BASED};
+use crate::tabix::tabix_index::{Binning, IndexType, CSI_DEFAULT_DEPTH, CSI_DEFAULT_MIN_SHIFT};
+use std::path::Path;
+use std::thread;
+use crate::cache::annotated::AnnotateMode;
+use crate::cache::regions::RegionsFormat;
+use crate::cache::stream::StreamFormat;
+use crate::util::error_policy::ErrorMode;
 
 pub(crate) enum Config {
-    Tabix(TabixConfig),
+    Tabix(Box<TabixConfig>),
+    Index(IndexConfig),
+    Bgzip(BgzipConfig),
+    Query(QueryConfig),
     Script(ScriptConfig),
     VepTransformPipe,
     AdaptIdPipe(AdaptIdPipeConfig),
@@ -10,8 +22,12 @@ pub(crate) enum Config {
 
 pub(crate) struct TabixConfig {
     pub(crate) input_config: TabixInputConfig,
-    pub(crate) cache_misses_file_opt: Option<String>,
-    pub(crate) output_file_opt: Option<String>,
+    pub(crate) output_config: TabixOutputConfig,
+    pub(crate) chroms_config: ChromsConfig,
+    pub(crate) columns_config: ColumnsConfig,
+    pub(crate) errors_config: ErrorsConfig,
+    pub(crate) threads: usize,
+    pub(crate) normalize: bool,
 }
 
 pub(crate) struct TabixInputConfig {
@@ -19,12 +35,72 @@ pub(crate) struct TabixInputConfig {
     pub(crate) index_file: String,
     pub(crate) input_file: String,
     pub(crate) regions_file_opt: Option<String>,
+    pub(crate) exclude_regions_file_opt: Option<String>,
+    pub(crate) regions_format_opt: Option<RegionsFormat>,
+    pub(crate) reference_file_opt: Option<String>,
     pub(crate) col_ref: String,
     pub(crate) col_alt: String,
 }
 
+pub(crate) struct TabixOutputConfig {
+    pub(crate) output_file_opt: Option<String>,
+    pub(crate) cache_misses_file_opt: Option<String>,
+    pub(crate) ref_mismatches_file_opt: Option<String>,
+    pub(crate) filtered_file_opt: Option<String>,
+    pub(crate) annotate_regions_file_opt: Option<String>,
+    pub(crate) annotate_mode_opt: Option<AnnotateMode>,
+    pub(crate) summary_file_opt: Option<String>,
+    pub(crate) stream_format_opt: Option<StreamFormat>,
+    pub(crate) trace_input: bool,
+}
+
+pub(crate) struct ChromsConfig {
+    pub(crate) assembly_opt: Option<Hg>,
+    pub(crate) chrom_aliases_file_opt: Option<String>,
+}
+
+// columns are name or name:new_name, and all columns are written if both are empty
+pub(crate) struct ColumnsConfig {
+    pub(crate) columns: Vec<String>,
+    pub(crate) exclude_columns: Vec<String>,
+}
+
+// what to do with malformed lines in input, data and regions files
+pub(crate) struct ErrorsConfig {
+    pub(crate) mode: ErrorMode,
+    pub(crate) input_mode: ErrorMode,
+    pub(crate) rejects_file_opt: Option<String>,
+}
+
+pub(crate) struct IndexConfig {
+    pub(crate) data_file: String,
+    pub(crate) index_file: String,
+    pub(crate) index_format: IndexFormat,
+    pub(crate) index_type: IndexType,
+}
+
+pub(crate) struct BgzipConfig {
+    pub(crate) input_file: String,
+    pub(crate) output_file: String,
+    pub(crate) threads: usize,
+    pub(crate) index_file_opt: Option<String>,
+    pub(crate) index_format: IndexFormat,
+    pub(crate) index_type: IndexType,
+}
+
+pub(crate) struct QueryConfig {
+    pub(crate) data_file: String,
+    pub(crate) index_file: String,
+    pub(crate) regions: Vec<String>,
+    pub(crate) regions_file_opt: Option<String>,
+    pub(crate) regions_format_opt: Option<RegionsFormat>,
+    pub(crate) output_file_opt: Option<String>,
+    pub(crate) chroms_config: ChromsConfig,
+}
+
 pub(crate) struct ScriptConfig {
     pub(crate) script_file: String,
+    pub(crate) max_parallel: usize,
 }
 
 pub(crate) struct AdaptIdPipeConfig {
@@ -32,39 +108,158 @@ pub(crate) struct AdaptIdPipeConfig {
 }
 
 impl TabixConfig {
-    pub(crate) fn new(input_config: TabixInputConfig, cache_misses_file_opt: Option<String>,
-                      output_file_opt: Option<String>)
+    pub(crate) fn new(input_config: TabixInputConfig, output_config: TabixOutputConfig,
+                      chroms_config: ChromsConfig, columns_config: ColumnsConfig,
+                      errors_config: ErrorsConfig, threads: usize, normalize: bool)
                       -> TabixConfig {
         TabixConfig {
-            input_config,
-            cache_misses_file_opt,
-            output_file_opt,
+            input_config, output_config, chroms_config, columns_config, errors_config, threads,
+            normalize
         }
     }
 }
 
+// Picks the .tbi or .csi index next to the data file, whichever exists
+fn default_index_file(data_file: &str) -> String {
+    let tbi_file = data_file.to_string() + IndexType::Tbi.file_extension();
+    let csi_file = data_file.to_string() + IndexType::Csi(Binning::TBI).file_extension();
+    if !Path::new(&tbi_file).exists() && Path::new(&csi_file).exists() {
+        csi_file
+    } else {
+        tbi_file
+    }
+}
+
 impl TabixInputConfig {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(cache_file: String, index_file_opt: Option<String>, input_file: String,
-                      regions_file_opt: Option<String>, col_ref: String, col_alt: String)
+                      regions_file_opt: Option<String>, exclude_regions_file_opt: Option<String>,
+                      regions_format_opt: Option<RegionsFormat>,
+                      reference_file_opt: Option<String>, col_ref: String, col_alt: String)
                       -> TabixInputConfig {
         let index_file =
             match index_file_opt {
                 Some(index_file) => index_file,
-                None => cache_file.clone() + ".tbi"
+                None => default_index_file(&cache_file)
             };
         TabixInputConfig {
             cache_file,
             index_file,
             input_file,
             regions_file_opt,
+            exclude_regions_file_opt,
+            regions_format_opt,
+            reference_file_opt,
             col_ref,
             col_alt,
         }
     }
 }
 
+impl TabixOutputConfig {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(output_file_opt: Option<String>, cache_misses_file_opt: Option<String>,
+                      ref_mismatches_file_opt: Option<String>, filtered_file_opt: Option<String>,
+                      annotate_regions_file_opt: Option<String>,
+                      annotate_mode_opt: Option<AnnotateMode>, summary_file_opt: Option<String>,
+                      stream_format_opt: Option<StreamFormat>, trace_input: bool)
+                      -> TabixOutputConfig {
+        TabixOutputConfig {
+            output_file_opt,
+            cache_misses_file_opt,
+            ref_mismatches_file_opt,
+            filtered_file_opt,
+            annotate_regions_file_opt,
+            annotate_mode_opt,
+            summary_file_opt,
+            stream_format_opt,
+            trace_input,
+        }
+    }
+}
+
+impl ChromsConfig {
+    pub(crate) fn new(assembly_opt: Option<Hg>, chrom_aliases_file_opt: Option<String>)
+                      -> ChromsConfig {
+        ChromsConfig { assembly_opt, chrom_aliases_file_opt }
+    }
+}
+
+impl ColumnsConfig {
+    pub(crate) fn new(columns: Vec<String>, exclude_columns: Vec<String>) -> ColumnsConfig {
+        ColumnsConfig { columns, exclude_columns }
+    }
+}
+
+impl ErrorsConfig {
+    // without an explicit mode, a rejects file implies quarantine, else malformed input lines
+    // are skipped, as they always were, while malformed data and regions files are fatal
+    pub(crate) fn new(on_error_opt: Option<&str>, max_errors_opt: Option<usize>,
+                      rejects_file_opt: Option<String>) -> Result<ErrorsConfig, Error> {
+        let (mode, input_mode) =
+            match (on_error_opt, &rejects_file_opt) {
+                (Some(on_error), _) => {
+                    let mode = ErrorMode::parse(on_error, max_errors_opt)?;
+                    (mode, mode)
+                }
+                (None, Some(_)) => {
+                    let mode = ErrorMode::parse("quarantine", max_errors_opt)?;
+                    (mode, mode)
+                }
+                (None, None) => { (ErrorMode::Strict, ErrorMode::Lenient { max_errors_opt }) }
+            };
+        Ok(ErrorsConfig { mode, input_mode, rejects_file_opt })
+    }
+}
+
+impl IndexConfig {
+    fn new(data_file: String, index_file_opt: Option<String>, index_format: IndexFormat,
+           index_type: IndexType) -> IndexConfig {
+        let index_file =
+            match index_file_opt {
+                Some(index_file) => index_file,
+                None => data_file.clone() + index_type.file_extension()
+            };
+        IndexConfig { data_file, index_file, index_format, index_type }
+    }
+}
+
+impl BgzipConfig {
+    pub(crate) fn new(input_file: String, output_file: String, threads: usize,
+                      index_file_opt: Option<String>, index_format: IndexFormat,
+                      index_type: IndexType) -> BgzipConfig {
+        BgzipConfig {
+            input_file, output_file, threads, index_file_opt, index_format, index_type
+        }
+    }
+}
+
+impl QueryConfig {
+    pub(crate) fn new(data_file: String, index_file_opt: Option<String>, regions: Vec<String>,
+                      regions_file_opt: Option<String>, regions_format_opt: Option<RegionsFormat>,
+                      output_file_opt: Option<String>, chroms_config: ChromsConfig)
+                      -> QueryConfig {
+        let index_file =
+            match index_file_opt {
+                Some(index_file) => index_file,
+                None => default_index_file(&data_file)
+            };
+        QueryConfig {
+            data_file,
+            index_file,
+            regions,
+            regions_file_opt,
+            regions_format_opt,
+            output_file_opt,
+            chroms_config,
+        }
+    }
+}
+
 impl ScriptConfig {
-    fn new(script_file: String) -> ScriptConfig { ScriptConfig { script_file } }
+    fn new(script_file: String, max_parallel: usize) -> ScriptConfig {
+        ScriptConfig { script_file, max_parallel }
+    }
 }
 
 impl AdaptIdPipeConfig {
@@ -73,6 +268,9 @@ impl AdaptIdPipeConfig {
 
 mod names {
     pub(crate) const TABIX: &str = "tabix";
+    pub(crate) const INDEX: &str = "index";
+    pub(crate) const BGZIP: &str = "bgzip";
+    pub(crate) const QUERY: &str = "query";
     pub(crate) const SCRIPT: &str = "script";
     pub(crate) const VEP_TRANSFORM_PIPE: &str = "vep-transform-pipe";
     pub(crate) const ADAPT_ID_PIPE: &str = "adapt-id-pipe";
@@ -80,12 +278,198 @@ mod names {
     pub(crate) const INDEX_FILE: &str = "index-file";
     pub(crate) const INPUT_FILE: &str = "input-file";
     pub(crate) const REGIONS_FILE: &str = "regions-file";
+    pub(crate) const EXCLUDE_REGIONS_FILE: &str = "exclude-regions-file";
+    pub(crate) const REGIONS_FORMAT: &str = "regions-format";
+    pub(crate) const FILTERED_FILE: &str = "filtered-file";
+    pub(crate) const ANNOTATE_REGIONS_FILE: &str = "annotate-regions-file";
+    pub(crate) const ANNOTATE: &str = "annotate";
+    pub(crate) const COLUMNS: &str = "columns";
+    pub(crate) const EXCLUDE_COLUMNS: &str = "exclude-columns";
+    pub(crate) const SUMMARY_FILE: &str = "summary-file";
+    pub(crate) const SINGLE_STREAM: &str = "single-stream";
+    pub(crate) const ON_ERROR: &str = "on-error";
+    pub(crate) const MAX_ERRORS: &str = "max-errors";
+    pub(crate) const REJECTS_FILE: &str = "rejects-file";
+    pub(crate) const REFERENCE_FILE: &str = "reference-file";
     pub(crate) const CACHE_MISSES_FILE: &str = "cache-misses-file";
     pub(crate) const OUTPUT_FILE: &str = "output-file";
+    pub(crate) const REF_MISMATCHES_FILE: &str = "ref-mismatches-file";
     pub(crate) const COL_REF: &str = "col-ref";
     pub(crate) const COL_ALT: &str = "col-alt";
     pub(crate) const COL_ID: &str = "col-id";
+    pub(crate) const THREADS: &str = "threads";
+    pub(crate) const TRACE_INPUT: &str = "trace-input";
+    pub(crate) const NORMALIZE: &str = "normalize";
+    pub(crate) const ASSEMBLY: &str = "assembly";
+    pub(crate) const CHROM_ALIASES_FILE: &str = "chrom-aliases-file";
     pub(crate) const SCRIPT_FILE: &str = "script-file";
+    pub(crate) const MAX_PARALLEL: &str = "max-parallel";
+    pub(crate) const PRESET: &str = "preset";
+    pub(crate) const COL_SEQ: &str = "col-seq";
+    pub(crate) const COL_BEGIN: &str = "col-begin";
+    pub(crate) const COL_END: &str = "col-end";
+    pub(crate) const ZERO_BASED: &str = "zero-based";
+    pub(crate) const COMMENT_CHAR: &str = "comment-char";
+    pub(crate) const SKIP_LINES: &str = "skip-lines";
+    pub(crate) const CSI: &str = "csi";
+    pub(crate) const MIN_SHIFT: &str = "min-shift";
+    pub(crate) const DEPTH: &str = "depth";
+    pub(crate) const REGION: &str = "region";
+}
+
+fn parse_col(col_opt: Option<&str>, col_default: i32) -> Result<i32, Error> {
+    match col_opt {
+        None => { Ok(col_default) }
+        Some(col_str) => { Ok(col_str.parse::<i32>()?) }
+    }
+}
+
+fn parse_index_format(matches: &clap::ArgMatches) -> Result<IndexFormat, Error> {
+    let preset_format = IndexFormat::for_preset(matches.value_of(names::PRESET))?;
+    let format =
+        if matches.is_present(names::ZERO_BASED) {
+            preset_format.format | FLAG_ZERO_BASED
+        } else {
+            preset_format.format
+        };
+    let col_seq = parse_col(matches.value_of(names::COL_SEQ), preset_format.col_seq)?;
+    let col_begin = parse_col(matches.value_of(names::COL_BEGIN), preset_format.col_begin)?;
+    let col_end = parse_col(matches.value_of(names::COL_END), preset_format.col_end)?;
+    let meta_char =
+        match matches.value_of(names::COMMENT_CHAR) {
+            None => { preset_format.meta_char }
+            Some(comment_char) if comment_char.len() == 1 => { comment_char.as_bytes()[0] }
+            Some(comment_char) => {
+                return Err(Error::from(format!("Comment char needs to be a single character, \
+                but got '{}'.", comment_char)));
+            }
+        };
+    let skip_lines =
+        parse_col(matches.value_of(names::SKIP_LINES), preset_format.skip_lines)?;
+    Ok(IndexFormat::new(format, col_seq, col_begin, col_end, meta_char, skip_lines))
+}
+
+fn index_format_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec!(
+        Arg::with_name(names::PRESET)
+            .short("p")
+            .long("preset")
+            .takes_value(true)
+            .help("Preset for data file format (vcf or bed)"),
+        Arg::with_name(names::COL_SEQ)
+            .short("s")
+            .long("col-seq")
+            .takes_value(true)
+            .help("Column number (1-based) of sequence name (default 1)"),
+        Arg::with_name(names::COL_BEGIN)
+            .short("b")
+            .long("col-begin")
+            .takes_value(true)
+            .help("Column number (1-based) of start position (default 2)"),
+        Arg::with_name(names::COL_END)
+            .short("e")
+            .long("col-end")
+            .takes_value(true)
+            .help("Column number (1-based) of end position (default: same as start)"),
+        Arg::with_name(names::ZERO_BASED)
+            .short("0")
+            .long("zero-based")
+            .help("Positions are zero-based, with exclusive end"),
+        Arg::with_name(names::COMMENT_CHAR)
+            .short("c")
+            .long("comment-char")
+            .takes_value(true)
+            .help("Lines starting with this character are skipped (default #)"),
+        Arg::with_name(names::SKIP_LINES)
+            .short("S")
+            .long("skip-lines")
+            .takes_value(true)
+            .help("Number of lines to skip at the beginning (default 0)"),
+        Arg::with_name(names::CSI)
+            .short("C")
+            .long("csi")
+            .help("Write CSI instead of TBI index (for bgzip, implies --index)"),
+        Arg::with_name(names::MIN_SHIFT)
+            .long("min-shift")
+            .takes_value(true)
+            .help("Bits of smallest bin size of CSI index (implies --csi, default 14)"),
+        Arg::with_name(names::DEPTH)
+            .long("depth")
+            .takes_value(true)
+            .help("Number of bin levels of CSI index (implies --csi, default 6)")
+    )
+}
+
+fn parse_index_type(matches: &clap::ArgMatches) -> Result<IndexType, Error> {
+    let min_shift_opt = matches.value_of(names::MIN_SHIFT);
+    let depth_opt = matches.value_of(names::DEPTH);
+    if matches.is_present(names::CSI) || min_shift_opt.is_some() || depth_opt.is_some() {
+        let min_shift = parse_col(min_shift_opt, CSI_DEFAULT_MIN_SHIFT as i32)?;
+        let depth = parse_col(depth_opt, CSI_DEFAULT_DEPTH as i32)?;
+        if min_shift < 0 || depth < 0 {
+            return Err(Error::from("Min shift and depth cannot be negative."));
+        }
+        Ok(IndexType::Csi(Binning::new(min_shift as u32, depth as u32)?))
+    } else {
+        Ok(IndexType::Tbi)
+    }
+}
+
+fn parse_chroms_config(matches: &clap::ArgMatches) -> Result<ChromsConfig, Error> {
+    let assembly_opt = match matches.value_of(names::ASSEMBLY) {
+        None => { None }
+        Some(assembly) => { Some(Hg::parse(assembly)?) }
+    };
+    let chrom_aliases_file_opt = matches.value_of(names::CHROM_ALIASES_FILE).map(String::from);
+    Ok(ChromsConfig::new(assembly_opt, chrom_aliases_file_opt))
+}
+
+fn parse_regions_format(matches: &clap::ArgMatches) -> Result<Option<RegionsFormat>, Error> {
+    matches.value_of(names::REGIONS_FORMAT).map(RegionsFormat::parse).transpose()
+}
+
+fn parse_errors_config(matches: &clap::ArgMatches) -> Result<ErrorsConfig, Error> {
+    let max_errors_opt =
+        matches.value_of(names::MAX_ERRORS).map(|max_errors| { max_errors.parse::<usize>() })
+            .transpose()?;
+    let rejects_file_opt = matches.value_of(names::REJECTS_FILE).map(String::from);
+    ErrorsConfig::new(matches.value_of(names::ON_ERROR), max_errors_opt, rejects_file_opt)
+}
+
+fn parse_list(list_opt: Option<&str>) -> Vec<String> {
+    list_opt.map(|list| {
+        list.split(',').map(str::trim).filter(|item| { !item.is_empty() }).map(String::from)
+            .collect()
+    }).unwrap_or_default()
+}
+
+fn parse_threads(threads_opt: Option<&str>) -> Result<usize, Error> {
+    match threads_opt {
+        None => { Ok(1) }
+        Some(threads_str) => {
+            let threads = threads_str.parse::<usize>()?;
+            if threads == 0 {
+                Err(Error::from("Number of threads needs to be at least 1."))
+            } else {
+                Ok(threads)
+            }
+        }
+    }
+}
+
+fn parse_max_parallel(max_parallel_opt: Option<&str>) -> Result<usize, Error> {
+    match max_parallel_opt {
+        None => { Ok(thread::available_parallelism().map_or(1, |n| { n.get() })) }
+        Some(max_parallel_str) => {
+            let max_parallel = max_parallel_str.parse::<usize>()?;
+            if max_parallel == 0 {
+                Err(Error::from("Maximum number of parallel scatter bodies needs to be at least \
+                1."))
+            } else {
+                Ok(max_parallel)
+            }
+        }
+    }
 }
 
 pub(crate) fn get_config() -> Result<Config, Error> {
@@ -108,33 +492,109 @@ pub(crate) fn get_config() -> Result<Config, Error> {
                         .short("t")
                         .long("index-file")
                         .takes_value(true)
-                        .help("The index file")
+                        .help("The index file, TBI or CSI (default is data file plus .tbi or .csi)")
                     )
                     .arg(Arg::with_name(names::INPUT_FILE)
                         .short("i")
                         .long("input-file")
                         .takes_value(true)
                         .required(true)
-                        .help("The input file")
+                        .help("The input file (VCF, optionally gzip- or BGZF-compressed, or - for stdin)")
                     )
                     .arg(Arg::with_name(names::REGIONS_FILE)
                         .short("g")
                         .long("regions-file")
                         .takes_value(true)
                         .required(false)
-                        .help("Optional file with regions. If provided, only variants within regions will be considered.")
+                        .help("Optional file with regions (BED, id-first or VCF). If provided, only variants within regions will be considered.")
+                    )
+                    .arg(Arg::with_name(names::EXCLUDE_REGIONS_FILE)
+                        .long("exclude-regions-file")
+                        .takes_value(true)
+                        .help("Optional file with regions (BED, id-first or VCF). Variants within these regions will be dropped.")
+                    )
+                    .arg(Arg::with_name(names::REGIONS_FORMAT)
+                        .long("regions-format")
+                        .takes_value(true)
+                        .possible_values(&["bed", "id-first", "vcf"])
+                        .help("Format of regions files (default is detected from the VCF header or the first lines, reading files that fit both BED and id-first as id-first).")
+                    )
+                    .arg(Arg::with_name(names::REFERENCE_FILE)
+                        .short("f")
+                        .long("reference-file")
+                        .takes_value(true)
+                        .help("Optional reference FASTA file, plain or BGZF-compressed, indexed with samtools faidx.")
                     )
                     .arg(Arg::with_name(names::CACHE_MISSES_FILE)
                         .short("c")
                         .long("cache-misses-file")
                         .takes_value(true)
-                        .help("The file to write cache misses.")
+                        .help("The file to write cache misses (default or - is stdout).")
                     )
                     .arg(Arg::with_name(names::OUTPUT_FILE)
                         .short("o")
                         .long("output-file")
                         .takes_value(true)
-                        .help("The output file")
+                        .help("The output file (default or - is stdout)")
+                    )
+                    .arg(Arg::with_name(names::REF_MISMATCHES_FILE)
+                        .short("m")
+                        .long("ref-mismatches-file")
+                        .takes_value(true)
+                        .help("The file to write variants with REF not matching the reference file (default is to write them with the cache misses).")
+                    )
+                    .arg(Arg::with_name(names::FILTERED_FILE)
+                        .long("filtered-file")
+                        .takes_value(true)
+                        .help("The file to write variants dropped by the regions filters, with the reason.")
+                    )
+                    .arg(Arg::with_name(names::ANNOTATE_REGIONS_FILE)
+                        .long("annotate-regions-file")
+                        .takes_value(true)
+                        .help("Optional file with named regions (BED, id-first or VCF). Names of regions overlapping the variant are added as column Regions.")
+                    )
+                    .arg(Arg::with_name(names::ANNOTATE)
+                        .long("annotate")
+                        .takes_value(true)
+                        .possible_values(&["info", "columns"])
+                        .help("Write the input VCF with data file columns added to INFO (info) or appended as columns (columns, only for inputs without FORMAT and sample columns), instead of data file lines. INFO keys must not clash with the input's.")
+                    )
+                    .arg(Arg::with_name(names::COLUMNS)
+                        .long("columns")
+                        .takes_value(true)
+                        .help("Comma-separated data file columns to write, in order, each optionally renamed as old:new (default all).")
+                    )
+                    .arg(Arg::with_name(names::EXCLUDE_COLUMNS)
+                        .long("exclude-columns")
+                        .takes_value(true)
+                        .help("Comma-separated data file columns not to write.")
+                    )
+                    .arg(Arg::with_name(names::SUMMARY_FILE)
+                        .long("summary-file")
+                        .takes_value(true)
+                        .help("The file to write counts of input lines and variants, filtered variants, hits and misses as JSON. Variants are counted per ALT allele.")
+                    )
+                    .arg(Arg::with_name(names::SINGLE_STREAM)
+                        .long("single-stream")
+                        .takes_value(true)
+                        .possible_values(&["tagged", "jsonl"])
+                        .help("Allow output and cache misses to share stdout as one stream, with each line prefixed by HIT or MISS and a tab (tagged), or as one JSON object per line (jsonl).")
+                    )
+                    .arg(Arg::with_name(names::ON_ERROR)
+                        .long("on-error")
+                        .takes_value(true)
+                        .possible_values(&["strict", "lenient", "quarantine"])
+                        .help("What to do with malformed lines in input, data and regions files: abort (strict), warn and skip (lenient) or write to the rejects file and skip (quarantine). Default is quarantine if a rejects file is given, else to skip malformed input lines with a warning and to abort on malformed data and regions files.")
+                    )
+                    .arg(Arg::with_name(names::MAX_ERRORS)
+                        .long("max-errors")
+                        .takes_value(true)
+                        .help("In lenient mode, including the default for input lines, abort once more than this many lines were malformed. Not allowed with strict or quarantine.")
+                    )
+                    .arg(Arg::with_name(names::REJECTS_FILE)
+                        .long("rejects-file")
+                        .takes_value(true)
+                        .help("The file to write malformed lines to, with source and error, in quarantine mode.")
                     )
                     .arg(Arg::with_name(names::COL_REF)
                         .short("r")
@@ -148,12 +608,149 @@ pub(crate) fn get_config() -> Result<Config, Error> {
                         .takes_value(true)
                         .help("The column name in data file containing the alt allele")
                     )
+                    .arg(Arg::with_name(names::THREADS)
+                        .short("n")
+                        .long("threads")
+                        .takes_value(true)
+                        .help("Number of threads for cache lookups (default 1).")
+                    )
+                    .arg(Arg::with_name(names::TRACE_INPUT)
+                        .long("trace-input")
+                        .help("Add input line number and ALT allele index to hits and misses.")
+                    )
+                    .arg(Arg::with_name(names::NORMALIZE)
+                        .long("normalize")
+                        .help("Trim variants before lookup and, if a reference file is given, left-align them and match data indels anywhere within the same repeat.")
+                    )
+                    .arg(Arg::with_name(names::ASSEMBLY)
+                        .short("y")
+                        .long("assembly")
+                        .takes_value(true)
+                        .help("Assembly (hg19/GRCh37 or hg38/GRCh38) for chromosome name aliases.")
+                    )
+                    .arg(Arg::with_name(names::CHROM_ALIASES_FILE)
+                        .long("chrom-aliases-file")
+                        .takes_value(true)
+                        .help("Optional file with lines of alias and canonical chromosome name.")
+                    )
+            )
+            .subcommand(
+                SubCommand::with_name(names::INDEX)
+                    .help("Writes tabix index for block-gzipped, location-sorted TSV file")
+                    .arg(Arg::with_name(names::DATA_FILE)
+                        .short("d")
+                        .long("data-file")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The data file")
+                    )
+                    .arg(Arg::with_name(names::INDEX_FILE)
+                        .short("t")
+                        .long("index-file")
+                        .takes_value(true)
+                        .help("The index file to write (default is data file plus .tbi or .csi)")
+                    )
+                    .args(&index_format_args())
+            )
+            .subcommand(
+                SubCommand::with_name(names::BGZIP)
+                    .help("Compresses TSV file with block-gzip (BGZF), optionally writing tabix index")
+                    .arg(Arg::with_name(names::INPUT_FILE)
+                        .short("i")
+                        .long("input-file")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The input file, or - for stdin")
+                    )
+                    .arg(Arg::with_name(names::OUTPUT_FILE)
+                        .short("o")
+                        .long("output-file")
+                        .takes_value(true)
+                        .help("The output file (default is input file plus .gz)")
+                    )
+                    .arg(Arg::with_name(names::THREADS)
+                        .short("n")
+                        .long("threads")
+                        .takes_value(true)
+                        .help("Number of threads for compression (default 1).")
+                    )
+                    .arg(Arg::with_name(names::INDEX)
+                        .short("x")
+                        .long("index")
+                        .help("Also write tabix index")
+                    )
+                    .arg(Arg::with_name(names::INDEX_FILE)
+                        .short("t")
+                        .long("index-file")
+                        .takes_value(true)
+                        .help("The index file to write (implies --index, default is output file plus .tbi or .csi)")
+                    )
+                    .args(&index_format_args())
+            )
+            .subcommand(
+                SubCommand::with_name(names::QUERY)
+                    .help("Writes records of tabix-indexed file overlapping given regions")
+                    .arg(Arg::with_name(names::DATA_FILE)
+                        .short("d")
+                        .long("data-file")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The data file")
+                    )
+                    .arg(Arg::with_name(names::INDEX_FILE)
+                        .short("t")
+                        .long("index-file")
+                        .takes_value(true)
+                        .help("The index file, TBI or CSI (default is data file plus .tbi or .csi)")
+                    )
+                    .arg(Arg::with_name(names::REGION)
+                        .short("r")
+                        .long("region")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Region like chr7:117,000,000-117,300,000, chr7:117000000 or chr7 (can be repeated)")
+                    )
+                    .arg(Arg::with_name(names::REGIONS_FILE)
+                        .short("g")
+                        .long("regions-file")
+                        .takes_value(true)
+                        .help("File with regions (BED, id-first or VCF)")
+                    )
+                    .arg(Arg::with_name(names::REGIONS_FORMAT)
+                        .long("regions-format")
+                        .takes_value(true)
+                        .possible_values(&["bed", "id-first", "vcf"])
+                        .help("Format of regions files (default is detected from the VCF header or the first lines, reading files that fit both BED and id-first as id-first).")
+                    )
+                    .arg(Arg::with_name(names::OUTPUT_FILE)
+                        .short("o")
+                        .long("output-file")
+                        .takes_value(true)
+                        .help("The output file (default is stdout)")
+                    )
+                    .arg(Arg::with_name(names::ASSEMBLY)
+                        .short("y")
+                        .long("assembly")
+                        .takes_value(true)
+                        .help("Assembly (hg19/GRCh37 or hg38/GRCh38) for chromosome name aliases.")
+                    )
+                    .arg(Arg::with_name(names::CHROM_ALIASES_FILE)
+                        .long("chrom-aliases-file")
+                        .takes_value(true)
+                        .help("Optional file with lines of alias and canonical chromosome name.")
+                    )
             )
             .subcommand(
                 SubCommand::with_name(names::SCRIPT)
                     .arg(Arg::with_name(names::SCRIPT_FILE)
                         .value_name("script file")
                         .takes_value(true))
+                    .arg(Arg::with_name(names::MAX_PARALLEL)
+                        .long("max-parallel")
+                        .takes_value(true)
+                        .help("Maximum number of scatter bodies evaluated at the same time, across all scatters including nested ones (default number of CPUs). Scripts can override it by assigning max_parallel.")
+                    )
             )
             .subcommand(
                 SubCommand::with_name(names::VEP_TRANSFORM_PIPE)
@@ -178,28 +775,117 @@ pub(crate) fn get_config() -> Result<Config, Error> {
                 .ok_or_else(|| Error::from("Missing argument --input-file."))?);
         let regions_file_opt =
             tabix_matches.value_of(names::REGIONS_FILE).map(String::from);
+        let exclude_regions_file_opt =
+            tabix_matches.value_of(names::EXCLUDE_REGIONS_FILE).map(String::from);
+        let regions_format_opt = parse_regions_format(tabix_matches)?;
+        let reference_file_opt =
+            tabix_matches.value_of(names::REFERENCE_FILE).map(String::from);
         let cache_misses_file_opt =
             tabix_matches.value_of(names::CACHE_MISSES_FILE).map(String::from);
         let output_file_opt =
             tabix_matches.value_of(names::OUTPUT_FILE).map(String::from);
+        let ref_mismatches_file_opt =
+            tabix_matches.value_of(names::REF_MISMATCHES_FILE).map(String::from);
+        let filtered_file_opt =
+            tabix_matches.value_of(names::FILTERED_FILE).map(String::from);
+        let annotate_regions_file_opt =
+            tabix_matches.value_of(names::ANNOTATE_REGIONS_FILE).map(String::from);
+        let annotate_mode_opt =
+            tabix_matches.value_of(names::ANNOTATE).map(AnnotateMode::parse).transpose()?;
+        let summary_file_opt = tabix_matches.value_of(names::SUMMARY_FILE).map(String::from);
+        let stream_format_opt =
+            tabix_matches.value_of(names::SINGLE_STREAM).map(StreamFormat::parse).transpose()?;
         let col_ref =
             String::from(tabix_matches.value_of(names::COL_REF)
                 .ok_or_else(|| Error::from("Missing argument --col-ref."))?);
         let col_alt =
             String::from(tabix_matches.value_of(names::COL_ALT)
                 .ok_or_else(|| Error::from("Missing argument --col-alt."))?);
+        let threads = parse_threads(tabix_matches.value_of(names::THREADS))?;
+        let trace_input = tabix_matches.is_present(names::TRACE_INPUT);
+        let normalize = tabix_matches.is_present(names::NORMALIZE);
         let input_config =
-            TabixInputConfig::new(data_file, index_file_opt, input_file, regions_file_opt, col_ref,
-                                  col_alt);
+            TabixInputConfig::new(data_file, index_file_opt, input_file, regions_file_opt,
+                                  exclude_regions_file_opt, regions_format_opt,
+                                  reference_file_opt, col_ref, col_alt);
+        let output_config =
+            TabixOutputConfig::new(output_file_opt, cache_misses_file_opt,
+                                   ref_mismatches_file_opt, filtered_file_opt,
+                                   annotate_regions_file_opt, annotate_mode_opt,
+                                   summary_file_opt, stream_format_opt, trace_input);
+        let chroms_config = parse_chroms_config(tabix_matches)?;
+        let columns_config =
+            ColumnsConfig::new(parse_list(tabix_matches.value_of(names::COLUMNS)),
+                               parse_list(tabix_matches.value_of(names::EXCLUDE_COLUMNS)));
+        let errors_config = parse_errors_config(tabix_matches)?;
         let tabix_config =
-            TabixConfig::new(input_config, cache_misses_file_opt, output_file_opt);
-        Ok(Config::Tabix(tabix_config))
+            TabixConfig::new(input_config, output_config, chroms_config, columns_config,
+                             errors_config, threads, normalize);
+        Ok(Config::Tabix(Box::new(tabix_config)))
+    } else if let Some(index_matches) = matches.subcommand_matches(names::INDEX) {
+        let data_file =
+            String::from(index_matches.value_of(names::DATA_FILE)
+                .ok_or_else(|| Error::from("Missing argument --data-file."))?);
+        let index_file_opt = index_matches.value_of(names::INDEX_FILE).map(String::from);
+        let index_format = parse_index_format(index_matches)?;
+        let index_type = parse_index_type(index_matches)?;
+        let index_config = IndexConfig::new(data_file, index_file_opt, index_format, index_type);
+        Ok(Config::Index(index_config))
+    } else if let Some(bgzip_matches) = matches.subcommand_matches(names::BGZIP) {
+        let input_file =
+            String::from(bgzip_matches.value_of(names::INPUT_FILE)
+                .ok_or_else(|| Error::from("Missing argument --input-file."))?);
+        let output_file =
+            match bgzip_matches.value_of(names::OUTPUT_FILE) {
+                Some(output_file) => { String::from(output_file) }
+                None if input_file == "-" => {
+                    return Err(Error::from("Need --output-file when reading from stdin."));
+                }
+                None => { input_file.clone() + ".gz" }
+            };
+        let threads = parse_threads(bgzip_matches.value_of(names::THREADS))?;
+        let index_type = parse_index_type(bgzip_matches)?;
+        let index_file_opt =
+            match bgzip_matches.value_of(names::INDEX_FILE) {
+                Some(index_file) => { Some(String::from(index_file)) }
+                None if bgzip_matches.is_present(names::INDEX) ||
+                    matches!(index_type, IndexType::Csi(_)) => {
+                    Some(output_file.clone() + index_type.file_extension())
+                }
+                None => { None }
+            };
+        let index_format = parse_index_format(bgzip_matches)?;
+        let bgzip_config =
+            BgzipConfig::new(input_file, output_file, threads, index_file_opt, index_format,
+                             index_type);
+        Ok(Config::Bgzip(bgzip_config))
+    } else if let Some(query_matches) = matches.subcommand_matches(names::QUERY) {
+        let data_file =
+            String::from(query_matches.value_of(names::DATA_FILE)
+                .ok_or_else(|| Error::from("Missing argument --data-file."))?);
+        let index_file_opt = query_matches.value_of(names::INDEX_FILE).map(String::from);
+        let regions: Vec<String> =
+            query_matches.values_of(names::REGION).map(|regions| {
+                regions.map(String::from).collect()
+            }).unwrap_or_default();
+        let regions_file_opt = query_matches.value_of(names::REGIONS_FILE).map(String::from);
+        if regions.is_empty() && regions_file_opt.is_none() {
+            return Err(Error::from("Need --region or --regions-file."));
+        }
+        let regions_format_opt = parse_regions_format(query_matches)?;
+        let output_file_opt = query_matches.value_of(names::OUTPUT_FILE).map(String::from);
+        let chroms_config = parse_chroms_config(query_matches)?;
+        let query_config =
+            QueryConfig::new(data_file, index_file_opt, regions, regions_file_opt,
+                             regions_format_opt, output_file_opt, chroms_config);
+        Ok(Config::Query(query_config))
     } else if let Some(script_matches) =
     matches.subcommand_matches(names::SCRIPT) {
         let script_file =
             String::from(script_matches.value_of(names::SCRIPT_FILE)
                 .ok_or_else(|| Error::from("Missing argument for script file."))?);
-        let script_config = ScriptConfig::new(script_file);
+        let max_parallel = parse_max_parallel(script_matches.value_of(names::MAX_PARALLEL))?;
+        let script_config = ScriptConfig::new(script_file, max_parallel);
         Ok(Config::Script(script_config))
     } else if matches.subcommand_matches(names::VEP_TRANSFORM_PIPE).is_some() {
         Ok(Config::VepTransformPipe)
@@ -212,8 +898,9 @@ pub(crate) fn get_config() -> Result<Config, Error> {
         let adapt_id_pipe_config = AdaptIdPipeConfig::new(id_col);
         Ok(Config::AdaptIdPipe(adapt_id_pipe_config))
     } else {
-        Err(Error::from(format!("Need to specify sub-command ({}, {}, {} or {}).",
-                                names::TABIX, names::SCRIPT, names::VEP_TRANSFORM_PIPE,
-                                names::ADAPT_ID_PIPE)))
+        Err(Error::from(format!("Need to specify sub-command ({}, {}, {}, {}, {}, {} or {}).",
+                                names::TABIX, names::INDEX, names::BGZIP, names::QUERY,
+                                names::SCRIPT,
+                                names::VEP_TRANSFORM_PIPE, names::ADAPT_ID_PIPE)))
     }
 }
\ No newline at end of file
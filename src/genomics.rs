@@ -1,2 +1,4 @@
 pub(crate) mod variant;
 pub(crate) mod assembly;
+pub(crate) mod reference;
+pub(crate) mod chrom_aliases;
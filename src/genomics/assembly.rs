@@ -1,4 +1,5 @@
 use crate::util::error::Error;
+use crate::genomics::chrom_aliases::ChromAliases;
 
 pub(crate) enum Hg { Hg19, Hg38 }
 
@@ -14,6 +15,12 @@ impl Hg {
             Hg::Hg38 => { GRCH38 }
         }
     }
+    pub(crate) fn chrom_aliases(&self) -> ChromAliases {
+        match self {
+            Hg::Hg19 => { ChromAliases::grch37() }
+            Hg::Hg38 => { ChromAliases::grch38() }
+        }
+    }
     pub(crate) fn parse(string: &str) -> Result<Hg, Error> {
         match string {
             HG19 | GRCH37 => Ok(Hg::Hg19),
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use fs_err::File;
+use crate::util::error::Error;
+use crate::genomics::variant;
+
+const AUTOSOMES: [&str; 22] = [
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16", "17",
+    "18", "19", "20", "21", "22"
+];
+
+const REFSEQ_VERSIONS_GRCH37: [u32; 24] = [
+    10, 11, 11, 11, 9, 11, 13, 10, 11, 10, 9, 11, 10, 8, 9, 9, 10, 9, 9, 10, 8, 10, 10, 9
+];
+
+const MITOCHONDRIAL: &str = "MT";
+const MITOCHONDRIAL_REFSEQ: &str = "NC_012920.1";
+
+#[derive(Clone)]
+pub(crate) struct ChromAliases {
+    canonical_by_alias: HashMap<String, String>,
+}
+
+// whether a name is likely a chromosome rather than a position or a region name
+pub(crate) fn looks_like_chrom(name: &str) -> bool {
+    let stripped = variant::strip_chr(name);
+    stripped.len() < name.len() || AUTOSOMES.contains(&stripped) ||
+        ["X", "Y", "M", MITOCHONDRIAL].contains(&stripped) || name.starts_with("NC_")
+}
+
+impl ChromAliases {
+    pub(crate) fn new() -> ChromAliases {
+        ChromAliases { canonical_by_alias: HashMap::new() }
+    }
+    pub(crate) fn grch37() -> ChromAliases { ChromAliases::for_grc(0) }
+    // RefSeq accessions of GRCh38 are one version above those of GRCh37
+    pub(crate) fn grch38() -> ChromAliases { ChromAliases::for_grc(1) }
+    fn for_grc(refseq_version_offset: u32) -> ChromAliases {
+        let mut chrom_aliases = ChromAliases::new();
+        let chroms = AUTOSOMES.iter().cloned().chain(vec!("X", "Y"));
+        for (i_chrom, chrom) in chroms.enumerate() {
+            chrom_aliases.add(format!("chr{}", chrom), chrom);
+            let version = REFSEQ_VERSIONS_GRCH37[i_chrom] + refseq_version_offset;
+            chrom_aliases.add(format!("NC_{:06}.{}", i_chrom + 1, version), chrom);
+        }
+        for alias in &["chrM", "chrMT", "M", MITOCHONDRIAL_REFSEQ] {
+            chrom_aliases.add(String::from(*alias), MITOCHONDRIAL);
+        }
+        chrom_aliases
+    }
+    pub(crate) fn add(&mut self, alias: String, canonical: &str) {
+        self.canonical_by_alias.insert(alias, String::from(canonical));
+    }
+    pub(crate) fn add_from_file(&mut self, file: &str) -> Result<(), Error> {
+        let reader = BufReader::new(File::open(file)?);
+        for line_result in reader.lines() {
+            let line = line_result?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split('\t');
+            let alias = parts.next().ok_or("Alias column missing.")?;
+            let canonical =
+                parts.next().ok_or_else(|| {
+                    Error::from(format!("No canonical name for alias {}.", alias))
+                })?;
+            self.add(String::from(alias), canonical);
+        }
+        Ok(())
+    }
+    pub(crate) fn canonical<'a>(&'a self, chrom: &'a str) -> &'a str {
+        match self.canonical_by_alias.get(chrom) {
+            Some(canonical) => { canonical }
+            None => {
+                let chrom_stripped = variant::strip_chr(chrom);
+                match self.canonical_by_alias.get(chrom_stripped) {
+                    Some(canonical) => { canonical }
+                    None => { chrom_stripped }
+                }
+            }
+        }
+    }
+    pub(crate) fn canonicalize(&self, chrom: String) -> String {
+        let canonical = self.canonical(&chrom);
+        if canonical == chrom {
+            chrom
+        } else {
+            String::from(canonical)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use fs_err::File;
+    use crate::genomics::chrom_aliases::ChromAliases;
+    use crate::util::error::Error;
+
+    #[test]
+    fn canonical_names_for_grc() {
+        let grch37 = ChromAliases::grch37();
+        let grch38 = ChromAliases::grch38();
+        assert_eq!(grch37.canonical("chr7"), "7");
+        assert_eq!(grch37.canonical("NC_000007.13"), "7");
+        assert_eq!(grch38.canonical("NC_000007.14"), "7");
+        assert_eq!(grch37.canonical("NC_000023.10"), "X");
+        assert_eq!(grch38.canonical("NC_000024.10"), "Y");
+        assert_eq!(grch38.canonical("chrM"), "MT");
+        assert_eq!(grch38.canonical("NC_012920.1"), "MT");
+        assert_eq!(grch38.canonical("chrUn_gl000220"), "Un_gl000220");
+        assert_eq!(ChromAliases::new().canonical("chrX"), "X");
+    }
+
+    #[test]
+    fn add_aliases_from_file() -> Result<(), Error> {
+        let file = "tmp/chrom_aliases.tsv";
+        File::create(file)?.write_all(b"# alias\tcanonical\nCM000663.2\t1\n\nchrEBV\tEBV\n")?;
+        let mut chrom_aliases = ChromAliases::grch38();
+        chrom_aliases.add_from_file(file)?;
+        assert_eq!(chrom_aliases.canonical("CM000663.2"), "1");
+        assert_eq!(chrom_aliases.canonical("chrEBV"), "EBV");
+        assert_eq!(chrom_aliases.canonical("chr1"), "1");
+        assert_eq!(chrom_aliases.canonicalize(String::from("CM000663.2")), "1");
+        assert_eq!(chrom_aliases.canonicalize(String::from("22")), "22");
+        File::create(file)?.write_all(b"CM000663.2\n")?;
+        assert!(chrom_aliases.add_from_file(file).is_err());
+        Ok(())
+    }
+}
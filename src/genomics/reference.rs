@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use fs_err::File;
+use bgzip::BGZFReader;
+use crate::util::error::Error;
+use crate::genomics::variant::Variant;
+use crate::genomics::chrom_aliases::ChromAliases;
+
+struct FaiEntry {
+    length: u64,
+    offset: u64,
+    line_bases: u64,
+    line_width: u64,
+}
+
+struct GziEntry {
+    offset_compressed: u64,
+    offset_uncompressed: u64,
+}
+
+enum FastaReader {
+    Plain(File),
+    Bgzf(BGZFReader<File>, Vec<GziEntry>),
+}
+
+pub(crate) struct Reference {
+    reader: FastaReader,
+    entries: HashMap<String, FaiEntry>,
+    chrom_aliases: ChromAliases,
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+impl FaiEntry {
+    fn parse(line: &str) -> Result<(String, FaiEntry), Error> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 5 {
+            return Err(Error::from(format!("Cannot parse FASTA index line '{}'.", line)));
+        }
+        let name = String::from(fields[0]);
+        let length = fields[1].parse::<u64>()?;
+        let offset = fields[2].parse::<u64>()?;
+        let line_bases = fields[3].parse::<u64>()?;
+        let line_width = fields[4].parse::<u64>()?;
+        if line_bases == 0 || line_width < line_bases {
+            return Err(Error::from(format!("Invalid line lengths for sequence {}.", name)));
+        }
+        Ok((name, FaiEntry { length, offset, line_bases, line_width }))
+    }
+    fn pos_to_offset(&self, i_base: u64) -> u64 {
+        self.offset + (i_base / self.line_bases) * self.line_width + i_base % self.line_bases
+    }
+}
+
+fn read_fai(fai_file: &str, chrom_aliases: &ChromAliases)
+            -> Result<HashMap<String, FaiEntry>, Error> {
+    let mut entries = HashMap::<String, FaiEntry>::new();
+    for line in BufReader::new(File::open(fai_file)?).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let (name, entry) = FaiEntry::parse(&line)?;
+        entries.insert(chrom_aliases.canonicalize(name), entry);
+    }
+    Ok(entries)
+}
+
+fn read_u64(read: &mut impl Read) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    read.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_gzi(gzi_file: &str) -> Result<Vec<GziEntry>, Error> {
+    let mut read = BufReader::new(File::open(gzi_file)?);
+    let n_entries = read_u64(&mut read)?;
+    let mut entries = vec!(GziEntry { offset_compressed: 0, offset_uncompressed: 0 });
+    for _ in 0..n_entries {
+        let offset_compressed = read_u64(&mut read)?;
+        let offset_uncompressed = read_u64(&mut read)?;
+        entries.push(GziEntry { offset_compressed, offset_uncompressed });
+    }
+    Ok(entries)
+}
+
+impl FastaReader {
+    fn open(fasta_file: &str) -> Result<FastaReader, Error> {
+        let mut file = File::open(fasta_file)?;
+        let mut magic = [0u8; 2];
+        let is_gzipped = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+        file.seek(SeekFrom::Start(0))?;
+        if is_gzipped {
+            let gzi_entries = read_gzi(&format!("{}.gzi", fasta_file))?;
+            Ok(FastaReader::Bgzf(BGZFReader::new(file), gzi_entries))
+        } else {
+            Ok(FastaReader::Plain(file))
+        }
+    }
+    fn read_at(&mut self, offset: u64, n_bytes: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0u8; n_bytes];
+        match self {
+            FastaReader::Plain(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut bytes)?;
+            }
+            FastaReader::Bgzf(bgzf, gzi_entries) => {
+                let i_entry =
+                    gzi_entries.partition_point(|entry| {
+                        entry.offset_uncompressed <= offset
+                    }) - 1;
+                let entry = &gzi_entries[i_entry];
+                let vpos =
+                    (entry.offset_compressed << 16) | (offset - entry.offset_uncompressed);
+                bgzf.bgzf_seek(vpos)?;
+                let mut n_bytes_read: usize = 0;
+                while n_bytes_read < n_bytes {
+                    let buf = bgzf.fill_buf()?;
+                    if buf.is_empty() {
+                        return Err(Error::from("Unexpected end of reference file."));
+                    }
+                    let n_bytes_copied = std::cmp::min(buf.len(), n_bytes - n_bytes_read);
+                    bytes[n_bytes_read..(n_bytes_read + n_bytes_copied)]
+                        .copy_from_slice(&buf[0..n_bytes_copied]);
+                    bgzf.consume(n_bytes_copied);
+                    n_bytes_read += n_bytes_copied;
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl Reference {
+    pub(crate) fn from_file(fasta_file: &str, chrom_aliases: ChromAliases)
+                            -> Result<Reference, Error> {
+        let entries = read_fai(&format!("{}.fai", fasta_file), &chrom_aliases)?;
+        let reader = FastaReader::open(fasta_file)?;
+        Ok(Reference { reader, entries, chrom_aliases })
+    }
+    pub(crate) fn has_sequence(&self, chrom: &str) -> bool {
+        self.entries.contains_key(self.chrom_aliases.canonical(chrom))
+    }
+    pub(crate) fn sequence_length(&self, chrom: &str) -> Option<u64> {
+        self.entries.get(self.chrom_aliases.canonical(chrom)).map(|entry| { entry.length })
+    }
+    pub(crate) fn get_bases(&mut self, chrom: &str, begin: u32, end: u32)
+                            -> Result<Vec<u8>, Error> {
+        let entry =
+            self.entries.get(self.chrom_aliases.canonical(chrom)).ok_or_else(|| {
+                Error::from(format!("Sequence {} is not in the reference.", chrom))
+            })?;
+        if begin == 0 || begin > end || (end as u64) > entry.length {
+            return Err(Error::from(format!("Region {}:{}-{} is outside of the reference.",
+                                           chrom, begin, end)));
+        }
+        let offset_begin = entry.pos_to_offset((begin - 1) as u64);
+        let offset_end = entry.pos_to_offset((end - 1) as u64) + 1;
+        let raw = self.reader.read_at(offset_begin, (offset_end - offset_begin) as usize)?;
+        let bases: Vec<u8> =
+            raw.into_iter().filter(|byte| { !byte.is_ascii_whitespace() })
+                .map(|byte| { byte.to_ascii_uppercase() }).collect();
+        Ok(bases)
+    }
+    pub(crate) fn find_ref_mismatch(&mut self, variant: &Variant)
+                                    -> Result<Option<String>, Error> {
+        let length =
+            match self.entries.get(self.chrom_aliases.canonical(&variant.chrom)) {
+                None => { return Ok(None) }
+                Some(entry) => { entry.length }
+            };
+        if variant.pos == 0 || variant.ref_allele.is_empty() ||
+            (variant.end() - 1) as u64 > length {
+            return Ok(Some(String::from(".")));
+        }
+        let end = variant.end() - 1;
+        let expected = self.get_bases(&variant.chrom, variant.pos, end)?;
+        if variant.ref_allele.as_bytes().eq_ignore_ascii_case(&expected) {
+            Ok(None)
+        } else {
+            Ok(Some(String::from_utf8(expected)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::Compression;
+    use fs_err::File;
+    use crate::genomics::chrom_aliases::ChromAliases;
+    use crate::genomics::reference::Reference;
+    use crate::genomics::variant::Variant;
+    use crate::tabix::bgzf;
+    use crate::util::error::Error;
+
+    const FASTA: &[u8] = b">chr1\nGATTACAGCA\nCACACATTTT\nTGC\n>chr2\nccccggggaa\nAA\n";
+    const FAI: &[u8] = b"chr1\t23\t6\t10\t11\nchr2\t12\t38\t10\t11\n";
+
+    // small blocks, so that sequences and lines span block boundaries
+    fn write_bgzipped_reference(path: &str) -> Result<(), Error> {
+        let mut fasta_file = File::create(path)?;
+        let mut gzi = Vec::<u8>::new();
+        let mut offset_compressed: u64 = 0;
+        let blocks: Vec<&[u8]> = FASTA.chunks(7).collect();
+        gzi.extend_from_slice(&((blocks.len() - 1) as u64).to_le_bytes());
+        for (i, data) in blocks.iter().enumerate() {
+            if i > 0 {
+                gzi.extend_from_slice(&offset_compressed.to_le_bytes());
+                gzi.extend_from_slice(&((7 * i) as u64).to_le_bytes());
+            }
+            let block = bgzf::compress_block(data, Compression::default())?;
+            fasta_file.write_all(&block)?;
+            offset_compressed += block.len() as u64;
+        }
+        fasta_file.write_all(&bgzf::EOF_BLOCK)?;
+        File::create(format!("{}.gzi", path))?.write_all(&gzi)?;
+        File::create(format!("{}.fai", path))?.write_all(FAI)?;
+        Ok(())
+    }
+
+    fn assert_bases(reference: &mut Reference, chrom: &str, begin: u32, end: u32,
+                    expected: &str) -> Result<(), Error> {
+        let bases = reference.get_bases(chrom, begin, end)?;
+        assert_eq!(String::from_utf8(bases)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn get_bases_from_bgzipped_fasta() -> Result<(), Error> {
+        let path = "tmp/reference_bgzipped.fa.gz";
+        write_bgzipped_reference(path)?;
+        let mut reference = Reference::from_file(path, ChromAliases::new())?;
+        assert!(reference.has_sequence("1"));
+        assert_eq!(reference.sequence_length("2"), Some(12));
+        assert_bases(&mut reference, "1", 1, 1, "G")?;
+        assert_bases(&mut reference, "1", 8, 14, "GCACACA")?;
+        assert_bases(&mut reference, "1", 19, 23, "TTTGC")?;
+        assert_bases(&mut reference, "2", 3, 12, "CCGGGGAAAA")?;
+        assert!(reference.get_bases("1", 20, 24).is_err());
+        assert!(reference.get_bases("3", 1, 1).is_err());
+        let variant = Variant::new(String::from("1"), 5, String::from("ac"), String::from("A"));
+        assert_eq!(reference.find_ref_mismatch(&variant)?, None);
+        let variant = Variant::new(String::from("1"), 5, String::from("AG"), String::from("A"));
+        assert_eq!(reference.find_ref_mismatch(&variant)?, Some(String::from("AC")));
+        Ok(())
+    }
+}
@@ -2,9 +2,13 @@ use crate::util::error::Error;
 use std::str::Split;
 use std::cmp;
 use std::fmt::{Display, Formatter};
+use crate::genomics::reference::Reference;
 
 pub(crate) type Pos = u32;
 
+const NON_VARIANT_ALLELES: [&str; 2] = [".", "*"];
+const BASES_PER_READ: u64 = 64;
+
 pub(crate) struct ICols {
     pub(crate) i_col_chrom: usize,
     pub(crate) i_col_pos: usize,
@@ -12,7 +16,7 @@ pub(crate) struct ICols {
     pub(crate) i_col_alt: usize,
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Clone)]
 pub(crate) struct Variant {
     pub(crate) chrom: String,
     pub(crate) pos: u32,
@@ -20,6 +24,10 @@ pub(crate) struct Variant {
     pub(crate) alt_allele: String,
 }
 
+pub(crate) struct Normalizer<'a> {
+    reference_opt: Option<&'a mut Reference>,
+}
+
 impl ICols {
     pub(crate) fn new(i_col_chrom: usize, i_col_pos: usize, i_col_ref: usize, i_col_alt: usize)
                       -> ICols { ICols { i_col_chrom, i_col_pos, i_col_ref, i_col_alt } }
@@ -53,19 +61,121 @@ fn cannot_parse(string: &str) -> Error {
     Error::from(format!("Cannot parse '{}' as a canonical variant id.", string))
 }
 
-fn strip_chr(chrom: &str) -> &str {
+pub(crate) fn strip_chr(chrom: &str) -> &str {
     chrom.strip_prefix("chr").unwrap_or(chrom)
 }
 
-pub(crate) fn parse_vcf_line(line: String) -> Result<(Variant, String), Error> {
+fn is_plain_allele(allele: &[u8]) -> bool {
+    !allele.is_empty() && allele.iter().all(|base| {
+        matches!(base.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N')
+    })
+}
+
+impl<'a> Normalizer<'a> {
+    pub(crate) fn new(reference_opt: Option<&'a mut Reference>) -> Normalizer<'a> {
+        Normalizer { reference_opt }
+    }
+    fn can_extend(&self, chrom: &str, pos: u32) -> bool {
+        pos > 1 && match &self.reference_opt {
+            None => { false }
+            Some(reference) => { reference.has_sequence(chrom) }
+        }
+    }
+    // trims shared bases and, given a reference, left-aligns, as in vt normalize
+    pub(crate) fn normalize(&mut self, variant: &Variant) -> Result<Variant, Error> {
+        let mut ref_allele = variant.ref_allele.as_bytes().to_vec();
+        let mut alt_allele = variant.alt_allele.as_bytes().to_vec();
+        if ref_allele == alt_allele || !is_plain_allele(&ref_allele) ||
+            !is_plain_allele(&alt_allele) {
+            return Ok(variant.clone());
+        }
+        let mut pos = variant.pos;
+        loop {
+            let is_last_shared = ref_allele.last() == alt_allele.last();
+            let is_trimmable = ref_allele.len() > 1 && alt_allele.len() > 1;
+            if !is_last_shared || !(is_trimmable || self.can_extend(&variant.chrom, pos)) {
+                break;
+            }
+            ref_allele.pop();
+            alt_allele.pop();
+            if ref_allele.is_empty() || alt_allele.is_empty() {
+                if let Some(reference) = &mut self.reference_opt {
+                    pos -= 1;
+                    let base = reference.get_bases(&variant.chrom, pos, pos)?;
+                    ref_allele.splice(0..0, base.iter().cloned());
+                    alt_allele.splice(0..0, base.iter().cloned());
+                }
+            }
+        }
+        let n_shared_first =
+            ref_allele.iter().zip(alt_allele.iter())
+                .take(std::cmp::min(ref_allele.len(), alt_allele.len()) - 1)
+                .take_while(|(ref_base, alt_base)| { ref_base == alt_base }).count();
+        pos += n_shared_first as u32;
+        let ref_allele = String::from_utf8(ref_allele[n_shared_first..].to_vec())?;
+        let alt_allele = String::from_utf8(alt_allele[n_shared_first..].to_vec())?;
+        Ok(Variant::new(variant.chrom.clone(), pos, ref_allele, alt_allele))
+    }
+    // rightmost position a normalized indel can be shifted to within a repeat, since data
+    // files may have it right-aligned
+    pub(crate) fn right_shifted_pos(&mut self, normalized: &Variant) -> Result<u32, Error> {
+        let reference = match &mut self.reference_opt {
+            None => { return Ok(normalized.pos) }
+            Some(reference) => { reference }
+        };
+        let length = match reference.sequence_length(&normalized.chrom) {
+            None => { return Ok(normalized.pos) }
+            Some(length) => { length }
+        };
+        let ref_allele = normalized.ref_allele.to_ascii_uppercase().into_bytes();
+        let alt_allele = normalized.alt_allele.to_ascii_uppercase().into_bytes();
+        let (shorter, longer) =
+            if ref_allele.len() < alt_allele.len() {
+                (ref_allele.as_slice(), alt_allele.as_slice())
+            } else {
+                (alt_allele.as_slice(), ref_allele.as_slice())
+            };
+        if shorter.len() != 1 || longer.len() < 2 || shorter[0] != longer[0] {
+            return Ok(normalized.pos);
+        }
+        let indel = &longer[1..];
+        let mut n_shifts: usize = 0;
+        let mut pos_next = normalized.pos + (ref_allele.len() as u32);
+        while (pos_next as u64) <= length {
+            let pos_last = cmp::min(pos_next as u64 + BASES_PER_READ - 1, length) as u32;
+            let bases = reference.get_bases(&normalized.chrom, pos_next, pos_last)?;
+            for base in &bases {
+                if *base != indel[n_shifts % indel.len()] {
+                    return Ok(normalized.pos + (n_shifts as u32));
+                }
+                n_shifts += 1;
+            }
+            pos_next = pos_last + 1;
+        }
+        Ok(normalized.pos + (n_shifts as u32))
+    }
+}
+
+// one variant per ALT allele with its 1-based index, skipping missing (.) and spanning
+// deletion (*) alleles, which have nothing to look up
+pub(crate) fn parse_vcf_line(line: &str) -> Result<Vec<(usize, Variant)>, Error> {
     let mut fields = line.split('\t');
     let chrom =
         String::from(strip_chr(get_vcf_field(&mut fields, "CHROM")?));
     let pos = str::parse::<u32>(get_vcf_field(&mut fields, "POS")?)?;
     get_vcf_field(&mut fields, "ID")?;
-    let ref_allele = String::from(get_vcf_field(&mut fields, "REF")?);
-    let alt_allele = String::from(get_vcf_field(&mut fields, "ALT")?);
-    Ok((Variant::new(chrom, pos, ref_allele, alt_allele), line))
+    let ref_allele = get_vcf_field(&mut fields, "REF")?;
+    let alt_alleles = get_vcf_field(&mut fields, "ALT")?;
+    let variants: Vec<(usize, Variant)> =
+        alt_alleles.split(',').enumerate().filter(|(_, alt_allele)| {
+            !NON_VARIANT_ALLELES.contains(alt_allele)
+        }).map(|(i, alt_allele)| {
+            let variant =
+                Variant::new(chrom.clone(), pos, String::from(ref_allele),
+                             String::from(alt_allele));
+            (i + 1, variant)
+        }).collect();
+    Ok(variants)
 }
 
 fn get_vcf_field<'a, 'b>(fields: &'a mut Split<'b, char>, field_name: &str)
@@ -73,7 +183,7 @@ fn get_vcf_field<'a, 'b>(fields: &'a mut Split<'b, char>, field_name: &str)
     fields.next().ok_or_else(|| Error::from(format!("Missing {} field", field_name)))
 }
 
-pub(crate) fn parse_tsv_line(line: String, i_cols: &ICols) -> Result<(Variant, String), Error> {
+pub(crate) fn parse_tsv_line(line: &str, i_cols: &ICols) -> Result<Variant, Error> {
     let i_max =
         cmp::max(cmp::max(i_cols.i_col_chrom, i_cols.i_col_pos),
                  cmp::max(i_cols.i_col_ref, i_cols.i_col_alt)) + 1;
@@ -82,7 +192,7 @@ pub(crate) fn parse_tsv_line(line: String, i_cols: &ICols) -> Result<(Variant, S
     let pos = str::parse::<u32>(get_tsv_field(&fields, i_cols.i_col_pos)?)?;
     let ref_allele = String::from(get_tsv_field(&fields, i_cols.i_col_ref)?);
     let alt_allele = String::from(get_tsv_field(&fields, i_cols.i_col_alt)?);
-    Ok((Variant::new(chrom, pos, ref_allele, alt_allele), line))
+    Ok(Variant::new(chrom, pos, ref_allele, alt_allele))
 }
 
 fn get_tsv_field<'b>(fields: &[&'b str], i_col: usize) -> Result<&'b str, Error> {
@@ -97,3 +207,75 @@ impl Display for Variant {
         write!(f, "{}:{}_{}/{}", self.chrom, self.pos, self.ref_allele, self.alt_allele)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::util::error::Error;
+    use crate::genomics::variant::{parse_vcf_line, Normalizer, Variant};
+    use crate::genomics::reference::Reference;
+    use crate::genomics::chrom_aliases::ChromAliases;
+    use std::io::Write;
+    use fs_err::File;
+
+    fn write_reference_files(path: &str) -> Result<(), Error> {
+        let mut fasta_file = File::create(path)?;
+        fasta_file.write_all(b">chr1\nGATTACAGCA\nCACACATTTT\nTGC\n")?;
+        let mut fai_file = File::create(format!("{}.fai", path))?;
+        fai_file.write_all(b"chr1\t23\t6\t10\t11\n")?;
+        Ok(())
+    }
+
+    fn new_variant(chrom: &str, pos: u32, ref_allele: &str, alt_allele: &str) -> Variant {
+        Variant::new(chrom.to_string(), pos, ref_allele.to_string(),
+                     alt_allele.to_string())
+    }
+
+    fn assert_normalized(normalizer: &mut Normalizer, variant: Variant, expected: Variant)
+                         -> Result<(), Error> {
+        let normalized = normalizer.normalize(&variant)?;
+        assert!(normalized == expected, "Expected {} to normalize to {}, but got {}.",
+                variant, expected, normalized);
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_variants() -> Result<(), Error> {
+        let mut trimmer = Normalizer::new(None);
+        assert_normalized(&mut trimmer, new_variant("1", 100, "AT", "AGT"),
+                          new_variant("1", 100, "A", "AG"))?;
+        assert_normalized(&mut trimmer, new_variant("1", 100, "CAGT", "CAGA"),
+                          new_variant("1", 103, "T", "A"))?;
+        assert_normalized(&mut trimmer, new_variant("1", 101, "T", "GT"),
+                          new_variant("1", 101, "T", "GT"))?;
+        let reference_file_path = "tmp/reference.fa";
+        write_reference_files(reference_file_path)?;
+        let mut reference = Reference::from_file(reference_file_path, ChromAliases::new())?;
+        let mut normalizer = Normalizer::new(Some(&mut reference));
+        assert_normalized(&mut normalizer, new_variant("1", 13, "CAC", "C"),
+                          new_variant("1", 8, "GCA", "G"))?;
+        assert_normalized(&mut normalizer, new_variant("1", 20, "T", "TT"),
+                          new_variant("1", 16, "A", "AT"))?;
+        assert_normalized(&mut normalizer, new_variant("1", 2, "ATT", "AT"),
+                          new_variant("1", 2, "AT", "A"))?;
+        assert_eq!(normalizer.right_shifted_pos(&new_variant("1", 8, "GCA", "G"))?, 14);
+        assert_eq!(normalizer.right_shifted_pos(&new_variant("1", 16, "A", "AT"))?, 21);
+        assert_eq!(normalizer.right_shifted_pos(&new_variant("1", 20, "T", "TT"))?, 21);
+        assert_eq!(normalizer.right_shifted_pos(&new_variant("1", 1, "G", "C"))?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn split_vcf_line_by_alt_allele() -> Result<(), Error> {
+        let variants = parse_vcf_line("chr1\t100\trs1\tA\tG,*,AT,.\t.\t.\t.")?;
+        assert_eq!(variants.len(), 2);
+        let (i_allele, variant) = &variants[0];
+        assert_eq!(*i_allele, 1);
+        assert!(*variant == new_variant("1", 100, "A", "G"), "Got {}", variant);
+        let (i_allele, variant) = &variants[1];
+        assert_eq!(*i_allele, 3);
+        assert!(*variant == new_variant("1", 100, "A", "AT"), "Got {}", variant);
+        assert!(parse_vcf_line("1\t100\t.\tA\t.\t.\t.\t.")?.is_empty());
+        assert!(parse_vcf_line("1\t100\t.\tA").is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use crate::config::Config;
 use crate::util::error::Error;
-use crate::tools::{vep_output_transform, id_adapt};
+use crate::tools::{vep_output_transform, id_adapt, index, bgzip, query};
 
 mod util;
 mod config;
@@ -17,7 +17,10 @@ extern crate nom;
 pub fn run() -> Result<(), Error> {
     let config = config::get_config()?;
     match config {
-        Config::Tabix(tabix_config) => { cache::run::run_cache(tabix_config) }
+        Config::Tabix(tabix_config) => { cache::run::run_cache(*tabix_config).map(|_| ()) }
+        Config::Index(index_config) => { index::build_index(&index_config) }
+        Config::Bgzip(bgzip_config) => { bgzip::compress(&bgzip_config) }
+        Config::Query(query_config) => { query::query(&query_config) }
         Config::Script(script_config) => { script::run::run_script(script_config) }
         Config::VepTransformPipe => { vep_output_transform::transform_vep_output_pipe() }
         Config::AdaptIdPipe(adapt_id_config) => {
@@ -1,9 +1,12 @@
 pub(crate) mod expressions;
 pub(crate) mod symbols;
 pub(crate) mod values;
+pub(crate) mod ops;
 pub(crate) mod identifier;
 pub(crate) mod predef;
 pub(crate) mod builtin;
+pub(crate) mod closure;
+pub(crate) mod thread_slots;
 
 
 
@@ -8,3 +8,5 @@ pub(crate) mod replace_file_name;
 pub(crate) mod transform_vep_results;
 pub(crate) mod join_files;
 pub(crate) mod merge_sorted_files;
+pub(crate) mod bgzip;
+pub(crate) mod tabix_query;
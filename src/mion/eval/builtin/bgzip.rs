@@ -0,0 +1,70 @@
+use crate::mion::eval::expressions::Function;
+use std::collections::HashMap;
+use crate::mion::eval::identifier::Identifier;
+use crate::mion::eval::values::{Value, ObjectBuilder};
+use crate::util::error::Error;
+use crate::mion::eval::builtin::utils::{get_string_arg, get_string_opt_arg, get_int_opt_arg};
+use crate::config::BgzipConfig;
+use crate::tabix::index_builder::IndexFormat;
+use crate::tabix::tabix_index::{Binning, IndexType, CSI_DEFAULT_DEPTH, CSI_DEFAULT_MIN_SHIFT};
+use crate::tools::bgzip::compress;
+
+pub(crate) struct Bgzip {}
+
+const INPUT_FILE_ARG: &str = "input_file";
+const OUTPUT_FILE_ARG: &str = "output_file";
+const INDEX_FILE_ARG: &str = "index_file";
+const PRESET_ARG: &str = "preset";
+const THREADS_ARG: &str = "threads";
+const MIN_SHIFT_ARG: &str = "min_shift";
+const DEPTH_ARG: &str = "depth";
+const CSI_EXTENSION: &str = ".csi";
+
+fn get_index_type(index_file_opt: &Option<String>, min_shift_opt: Option<i64>,
+                  depth_opt: Option<i64>) -> Result<IndexType, Error> {
+    let is_csi_file =
+        index_file_opt.as_ref().is_some_and(|index_file| index_file.ends_with(CSI_EXTENSION));
+    if is_csi_file || min_shift_opt.is_some() || depth_opt.is_some() {
+        let min_shift = min_shift_opt.unwrap_or(CSI_DEFAULT_MIN_SHIFT as i64);
+        let depth = depth_opt.unwrap_or(CSI_DEFAULT_DEPTH as i64);
+        if min_shift < 0 || depth < 0 {
+            return Err(Error::from("Min shift and depth cannot be negative."));
+        }
+        Ok(IndexType::Csi(Binning::new(min_shift as u32, depth as u32)?))
+    } else {
+        Ok(IndexType::Tbi)
+    }
+}
+
+impl Function for Bgzip {
+    fn id(&self) -> &str { "bgzip" }
+
+    fn call(&self, args_map: HashMap<Identifier, Value>) -> Result<Value, Error> {
+        let input_file = get_string_arg(&args_map, INPUT_FILE_ARG)?;
+        let output_file = get_string_arg(&args_map, OUTPUT_FILE_ARG)?;
+        let index_file_opt = get_string_opt_arg(&args_map, INDEX_FILE_ARG)?;
+        let preset_opt = get_string_opt_arg(&args_map, PRESET_ARG)?;
+        let index_format = IndexFormat::for_preset(preset_opt.as_deref())?;
+        let min_shift_opt = get_int_opt_arg(&args_map, MIN_SHIFT_ARG)?;
+        let depth_opt = get_int_opt_arg(&args_map, DEPTH_ARG)?;
+        let index_type = get_index_type(&index_file_opt, min_shift_opt, depth_opt)?;
+        let threads = match get_int_opt_arg(&args_map, THREADS_ARG)? {
+            None => { 1 }
+            Some(threads) if threads > 0 => { threads as usize }
+            Some(threads) => {
+                return Err(Error::from(format!("Invalid number of threads {}.", threads)));
+            }
+        };
+        let mut object_builder = ObjectBuilder::new()
+            .with_string("input_file", &input_file)
+            .with_string("output_file", &output_file);
+        if let Some(index_file) = &index_file_opt {
+            object_builder = object_builder.with_string("index_file", index_file);
+        }
+        let config =
+            BgzipConfig::new(input_file, output_file, threads, index_file_opt, index_format,
+                             index_type);
+        compress(&config)?;
+        Ok(object_builder.into_object())
+    }
+}
@@ -3,9 +3,14 @@ use crate::mion::eval::values::{Value, ObjectBuilder};
 use crate::util::error::Error;
 use std::collections::HashMap;
 use crate::mion::eval::identifier::Identifier;
-use crate::mion::eval::builtin::utils::{get_string_arg, get_string_opt_arg};
-use crate::config::{TabixInputConfig, TabixConfig};
+use crate::mion::eval::builtin::utils::{get_string_arg, get_string_opt_arg, get_int_opt_arg,
+                                        get_strings_arg};
+use crate::config::{TabixInputConfig, TabixConfig, TabixOutputConfig, ChromsConfig,
+                    ColumnsConfig, ErrorsConfig};
+use crate::genomics::assembly::Hg;
 use crate::cache::run::run_cache;
+use crate::cache::annotated::AnnotateMode;
+use crate::cache::regions::RegionsFormat;
 
 pub(crate) struct Tabix {}
 
@@ -13,10 +18,26 @@ const CACHE_FILE_ARG: &str = "cache_file";
 const INDEX_FILE_ARG: &str = "index_file";
 const INPUT_FILE_ARG: &str = "input_file";
 const REGIONS_FILE_ARG: &str = "regions_file";
+const EXCLUDE_REGIONS_FILE_ARG: &str = "exclude_regions_file";
+const REGIONS_FORMAT_ARG: &str = "regions_format";
+const REFERENCE_FILE_ARG: &str = "reference_file";
 const COL_REF_ARG: &str = "col_ref";
 const COL_ALT_ARG: &str = "col_alt";
 const OUTPUT_FILE_ARG: &str = "output_file";
 const MISSES_FILE_ARG: &str = "misses_file";
+const REF_MISMATCHES_FILE_ARG: &str = "ref_mismatches_file";
+const FILTERED_FILE_ARG: &str = "filtered_file";
+const ANNOTATE_REGIONS_FILE_ARG: &str = "annotate_regions_file";
+const ANNOTATE_ARG: &str = "annotate";
+const COLUMNS_ARG: &str = "columns";
+const SUMMARY_FILE_ARG: &str = "summary_file";
+const EXCLUDE_COLUMNS_ARG: &str = "exclude_columns";
+const ON_ERROR_ARG: &str = "on_error";
+const MAX_ERRORS_ARG: &str = "max_errors";
+const REJECTS_FILE_ARG: &str = "rejects_file";
+const THREADS_ARG: &str = "threads";
+const ASSEMBLY_ARG: &str = "assembly";
+const CHROM_ALIASES_FILE_ARG: &str = "chrom_aliases_file";
 
 impl Function for Tabix {
     fn id(&self) -> &str { "tabix" }
@@ -26,22 +47,83 @@ impl Function for Tabix {
         let index_file_opt = get_string_opt_arg(&args_map, INDEX_FILE_ARG)?;
         let input_file = get_string_arg(&args_map, INPUT_FILE_ARG)?;
         let regions_file_opt = get_string_opt_arg(&args_map, REGIONS_FILE_ARG)?;
+        let exclude_regions_file_opt = get_string_opt_arg(&args_map, EXCLUDE_REGIONS_FILE_ARG)?;
+        let regions_format_opt =
+            get_string_opt_arg(&args_map, REGIONS_FORMAT_ARG)?.as_deref()
+                .map(RegionsFormat::parse).transpose()?;
+        let reference_file_opt = get_string_opt_arg(&args_map, REFERENCE_FILE_ARG)?;
         let col_ref = get_string_arg(&args_map, COL_REF_ARG)?;
         let col_alt = get_string_arg(&args_map, COL_ALT_ARG)?;
         let output_file = get_string_arg(&args_map, OUTPUT_FILE_ARG)?;
         let misses_file = get_string_arg(&args_map, MISSES_FILE_ARG)?;
-        let object = ObjectBuilder::new()
+        let ref_mismatches_file_opt = get_string_opt_arg(&args_map, REF_MISMATCHES_FILE_ARG)?;
+        let filtered_file_opt = get_string_opt_arg(&args_map, FILTERED_FILE_ARG)?;
+        let annotate_regions_file_opt =
+            get_string_opt_arg(&args_map, ANNOTATE_REGIONS_FILE_ARG)?;
+        let annotate_mode_opt =
+            get_string_opt_arg(&args_map, ANNOTATE_ARG)?.as_deref().map(AnnotateMode::parse)
+                .transpose()?;
+        let columns = get_strings_arg(&args_map, COLUMNS_ARG)?;
+        let exclude_columns = get_strings_arg(&args_map, EXCLUDE_COLUMNS_ARG)?;
+        let summary_file_opt = get_string_opt_arg(&args_map, SUMMARY_FILE_ARG)?;
+        let on_error_opt = get_string_opt_arg(&args_map, ON_ERROR_ARG)?;
+        let max_errors_opt = match get_int_opt_arg(&args_map, MAX_ERRORS_ARG)? {
+            None => { None }
+            Some(max_errors) if max_errors >= 0 => { Some(max_errors as usize) }
+            Some(max_errors) => {
+                return Err(Error::from(format!("Invalid maximum number of errors {}.",
+                                               max_errors)));
+            }
+        };
+        let rejects_file_opt = get_string_opt_arg(&args_map, REJECTS_FILE_ARG)?;
+        let threads = match get_int_opt_arg(&args_map, THREADS_ARG)? {
+            None => { 1 }
+            Some(threads) if threads > 0 => { threads as usize }
+            Some(threads) => {
+                return Err(Error::from(format!("Invalid number of threads {}.", threads)));
+            }
+        };
+        let assembly_opt = match get_string_opt_arg(&args_map, ASSEMBLY_ARG)? {
+            None => { None }
+            Some(assembly) => { Some(Hg::parse(&assembly)?) }
+        };
+        let chrom_aliases_file_opt = get_string_opt_arg(&args_map, CHROM_ALIASES_FILE_ARG)?;
+        let mut object_builder = ObjectBuilder::new()
             .with_string("output_file", &output_file)
-            .with_string("misses_file", &misses_file)
-            .into_object();
+            .with_string("misses_file", &misses_file);
+        if let Some(ref_mismatches_file) = &ref_mismatches_file_opt {
+            object_builder = object_builder.with_string("ref_mismatches_file", ref_mismatches_file);
+        }
+        if let Some(filtered_file) = &filtered_file_opt {
+            object_builder = object_builder.with_string("filtered_file", filtered_file);
+        }
+        if let Some(summary_file) = &summary_file_opt {
+            object_builder = object_builder.with_string("summary_file", summary_file);
+        }
+        if let Some(rejects_file) = &rejects_file_opt {
+            object_builder = object_builder.with_string("rejects_file", rejects_file);
+        }
         let input_config =
             TabixInputConfig::new(
-                cache_file, index_file_opt, input_file, regions_file_opt, col_ref, col_alt
+                cache_file, index_file_opt, input_file, regions_file_opt,
+                exclude_regions_file_opt, regions_format_opt, reference_file_opt, col_ref,
+                col_alt
             );
+        let output_config =
+            TabixOutputConfig::new(Some(output_file), Some(misses_file), ref_mismatches_file_opt,
+                                   filtered_file_opt, annotate_regions_file_opt,
+                                   annotate_mode_opt, summary_file_opt, None, false);
+        let chroms_config = ChromsConfig::new(assembly_opt, chrom_aliases_file_opt);
+        let columns_config = ColumnsConfig::new(columns, exclude_columns);
+        let errors_config =
+            ErrorsConfig::new(on_error_opt.as_deref(), max_errors_opt, rejects_file_opt)?;
         let config =
-            TabixConfig::new(input_config, Some(misses_file),
-                             Some(output_file));
-        run_cache(config)?;
-        Ok(object)
+            TabixConfig::new(input_config, output_config, chroms_config, columns_config,
+                             errors_config, threads, false);
+        let stats = run_cache(config)?;
+        for (key, count) in stats.counts() {
+            object_builder = object_builder.with_int(key, count as i64);
+        }
+        Ok(object_builder.into_object())
     }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use crate::mion::eval::expressions::Function;
+use std::collections::HashMap;
+use crate::mion::eval::identifier::Identifier;
+use crate::mion::eval::values::{Value, ObjectBuilder};
+use crate::util::error::Error;
+use crate::mion::eval::builtin::utils::{get_string_arg, get_string_opt_arg};
+use crate::config::{ChromsConfig, QueryConfig};
+use crate::genomics::assembly::Hg;
+use crate::tools::query::query;
+use crate::cache::regions::RegionsFormat;
+
+pub(crate) struct TabixQuery {}
+
+const DATA_FILE_ARG: &str = "data_file";
+const INDEX_FILE_ARG: &str = "index_file";
+const REGIONS_ARG: &str = "regions";
+const REGIONS_FILE_ARG: &str = "regions_file";
+const REGIONS_FORMAT_ARG: &str = "regions_format";
+const OUTPUT_FILE_ARG: &str = "output_file";
+const ASSEMBLY_ARG: &str = "assembly";
+const CHROM_ALIASES_FILE_ARG: &str = "chrom_aliases_file";
+
+fn get_regions(args_map: &HashMap<Identifier, Value>) -> Result<Vec<String>, Error> {
+    match args_map.get(&Identifier::from_str(REGIONS_ARG)) {
+        None => { Ok(Vec::new()) }
+        Some(Value::String(region)) => { Ok(vec!(region.to_string())) }
+        Some(value) => { value.as_vec_ref()?.iter().map(|region| region.as_string()).collect() }
+    }
+}
+
+impl Function for TabixQuery {
+    fn id(&self) -> &str { "tabix_query" }
+
+    fn call(&self, args_map: HashMap<Identifier, Value>) -> Result<Value, Error> {
+        let data_file = get_string_arg(&args_map, DATA_FILE_ARG)?;
+        let index_file_opt = get_string_opt_arg(&args_map, INDEX_FILE_ARG)?;
+        let regions = get_regions(&args_map)?;
+        let regions_file_opt = get_string_opt_arg(&args_map, REGIONS_FILE_ARG)?;
+        if regions.is_empty() && regions_file_opt.is_none() {
+            return Err(Error::from(format!("Need argument {} or {}.", REGIONS_ARG,
+                                           REGIONS_FILE_ARG)));
+        }
+        let regions_format_opt =
+            get_string_opt_arg(&args_map, REGIONS_FORMAT_ARG)?.as_deref()
+                .map(RegionsFormat::parse).transpose()?;
+        let output_file = get_string_arg(&args_map, OUTPUT_FILE_ARG)?;
+        let assembly_opt = match get_string_opt_arg(&args_map, ASSEMBLY_ARG)? {
+            None => { None }
+            Some(assembly) => { Some(Hg::parse(&assembly)?) }
+        };
+        let chrom_aliases_file_opt = get_string_opt_arg(&args_map, CHROM_ALIASES_FILE_ARG)?;
+        let object = ObjectBuilder::new()
+            .with_string("output_file", &output_file)
+            .into_object();
+        let chroms_config = ChromsConfig::new(assembly_opt, chrom_aliases_file_opt);
+        let config =
+            QueryConfig::new(data_file, index_file_opt, regions, regions_file_opt,
+                             regions_format_opt, Some(output_file), chroms_config);
+        query(&config)?;
+        Ok(object)
+    }
+}
@@ -18,6 +18,24 @@ pub(crate) fn get_string_opt_arg(args_map: &ArgsMap, id: &str) -> Result<Option<
     }
 }
 
+// a comma-separated string or an array of strings
+pub(crate) fn get_strings_arg(args_map: &ArgsMap, id: &str) -> Result<Vec<String>, Error> {
+    match args_map.get(&Identifier::from_str(id)) {
+        None => { Ok(Vec::new()) }
+        Some(Value::String(string)) => {
+            Ok(string.split(',').map(|item| { item.trim().to_string() }).collect())
+        }
+        Some(value) => { value.as_vec_ref()?.iter().map(|item| { item.as_string() }).collect() }
+    }
+}
+
+pub(crate) fn get_int_opt_arg(args_map: &ArgsMap, id: &str) -> Result<Option<i64>, Error> {
+    match args_map.get(&Identifier::from_str(id)) {
+        None => { Ok(None) }
+        Some(value) => { Ok(Some(value.as_int()?)) }
+    }
+}
+
 pub(crate) fn get_object_arg<'a>(args_map: &'a ArgsMap, id: &str)
     -> Result<&'a HashMap<Identifier, Value>, Error> {
     let value = args_map.get(&Identifier::from_str(id))
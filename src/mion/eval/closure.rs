@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::Weak;
+use crate::mion::eval::expressions::{Expression, Function};
+use crate::mion::eval::identifier::Identifier;
+use crate::mion::eval::symbols::Symbols;
+use crate::mion::eval::values::Value;
+use crate::util::error::Error;
+
+// A function defined in the script, with default values evaluated where it is defined. The body
+// sees the symbols of the definition site, plus the function itself, so it can call itself, plus
+// the arguments. The reference to itself is weak, since the function owns its symbols.
+pub(crate) struct Closure {
+    name: String,
+    params: Vec<(Identifier, Option<Value>)>,
+    body: Expression,
+    symbols: Symbols,
+    self_ref: Weak<Box<dyn Function + Send + Sync>>,
+}
+
+impl Closure {
+    pub(crate) fn new(name: String, params: Vec<(Identifier, Option<Value>)>, body: Expression,
+                      symbols: Symbols, self_ref: Weak<Box<dyn Function + Send + Sync>>)
+                      -> Closure {
+        Closure { name, params, body, symbols, self_ref }
+    }
+}
+
+impl Function for Closure {
+    fn id(&self) -> &str { &self.name }
+
+    fn call(&self, mut args_map: HashMap<Identifier, Value>) -> Result<Value, Error> {
+        let mut symbols = self.symbols.clone();
+        if let Some(function) = self.self_ref.upgrade() {
+            let name = Identifier::new(self.name.clone());
+            symbols = symbols.with_var_value_entry(&name, &Value::Function(function));
+        }
+        for (param, default_opt) in &self.params {
+            let value =
+                match (args_map.remove(param), default_opt) {
+                    (Some(value), _) => { value }
+                    (None, Some(default)) => { default.clone() }
+                    (None, None) => {
+                        return Err(Error::from(format!("Missing argument {} for function {}.",
+                                                       param, self.name)));
+                    }
+                };
+            symbols = symbols.with_var_value_entry(param, &value);
+        }
+        if let Some(arg) = args_map.keys().next() {
+            return Err(Error::from(format!("Function {} has no parameter {}.", self.name, arg)));
+        }
+        self.body.evaluate(&symbols)
+    }
+}
@@ -3,19 +3,28 @@ use super::values::Value;
 use crate::mion::syntax::ops::BinOp;
 use crate::mion::eval::identifier::Identifier;
 use crate::mion::eval::symbols::{Symbols, VarEntry};
-use crate::mion::eval::predef;
+use crate::mion::eval::{ops, predef};
+use crate::mion::eval::closure::Closure;
 use std::collections::HashMap;
+use std::cmp;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::thread;
-use std::thread::JoinHandle;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 pub(crate) enum Expression {
     Identifier(Identifier),
     Value(Value),
     Binary(Box<Expression>, BinOp, Box<Expression>),
     Member(Box<Expression>, Identifier),
+    Index(Box<Expression>, Box<Expression>),
     Call(Box<Expression>, Vec<Assignment>),
+    Array(Vec<Expression>),
+    Object(Vec<Assignment>),
     Scatter(Box<Scatter>),
+    Conditional(Box<Conditional>),
+    FunctionDefinition(Box<FunctionDefinition>),
     Assignment(Box<Assignment>),
     Block(Box<Block>),
 }
@@ -44,12 +53,19 @@ impl Expression {
                 }
             }
             Expression::Value(value) => { Ok(value.clone()) }
-            Expression::Binary(_, _, _) => { todo!() }
+            Expression::Binary(lhs, op, rhs) => {
+                let lhs_value = lhs.evaluate(symbols)?;
+                match (op, &lhs_value) {
+                    (BinOp::And, Value::Bool(false)) => { Ok(Value::Bool(false)) }
+                    (BinOp::Or, Value::Bool(true)) => { Ok(Value::Bool(true)) }
+                    _ => { ops::apply(&lhs_value, *op, &rhs.evaluate(symbols)?) }
+                }
+            }
             Expression::Member(expression, member) => {
                 let value = expression.evaluate(symbols)?;
                 match &value {
-                    Value::Unit | Value::String(_) | Value::Int(_) | Value::Float(_) |
-                    Value::Array(_) | Value::Function(_) => {
+                    Value::Unit | Value::Bool(_) | Value::String(_) | Value::Int(_) |
+                    Value::Float(_) | Value::Array(_) | Value::Function(_) => {
                         err_no_such_member(&value, member)
                     }
                     Value::Object(members) => {
@@ -64,6 +80,9 @@ impl Expression {
                     }
                 }
             }
+            Expression::Index(expression, index) => {
+                ops::index(&expression.evaluate(symbols)?, &index.evaluate(symbols)?)
+            }
             Expression::Call(callee, args) => {
                 let callee_value = callee.evaluate(symbols)?;
                 if let Value::Function(function) = callee_value {
@@ -79,33 +98,65 @@ impl Expression {
                                             callee_value)))
                 }
             }
+            Expression::Array(elements) => {
+                let mut values = Vec::<Value>::with_capacity(elements.len());
+                for element in elements {
+                    values.push(element.evaluate(symbols)?);
+                }
+                Ok(Value::Array(Arc::new(values)))
+            }
+            Expression::Object(entries) => {
+                let mut members = HashMap::<Identifier, Value>::new();
+                for entry in entries {
+                    members.insert(entry.lhs.clone(), entry.rhs.evaluate(symbols)?);
+                }
+                Ok(Value::Object(members))
+            }
             Expression::Scatter(scatter) => {
-                let scatter_identifier = &scatter.iteration.lhs;
                 let iterator_expression = &scatter.iteration.rhs;
                 let iterator_value = iterator_expression.evaluate(symbols)?;
                 if let Value::Array(array) = iterator_value {
-                    let mut children =
-                        Vec::<JoinHandle<Result<Value, Error>>>::new();
-                    for array_value in &*array {
-                        let symbols_scatter =
-                            symbols
-                                .clone().with_var_value_entry(scatter_identifier, array_value);
-                        let scatter_clone = scatter.clone();
-                        let child = thread::spawn(move ||{
-                            scatter_clone.expression.evaluate(&symbols_scatter)
-                        });
-                        children.push(child);
-                    }
-                    let mut values = Vec::<Value>::new();
-                    for child in children {
-                        let value = child.join()??;
-                        values.push(value);
-                    }
-                    Ok(Value::Array(Arc::new(values)))
+                    let max_parallel = get_max_parallel(symbols)?;
+                    evaluate_scatter(scatter, &array, symbols, max_parallel)
                 } else {
                     Err(Error::from(format!("Expected array, but got {}", iterator_value)))
                 }
             }
+            Expression::Conditional(conditional) => {
+                let condition = conditional.condition.evaluate(symbols)?;
+                match condition {
+                    Value::Bool(true) => { conditional.then_expression.evaluate(symbols) }
+                    Value::Bool(false) => {
+                        match &conditional.else_expression_opt {
+                            None => { Ok(Value::Unit) }
+                            Some(else_expression) => { else_expression.evaluate(symbols) }
+                        }
+                    }
+                    _ => {
+                        Err(Error::from(format!("Condition needs to be true or false, but got \
+                        {}.", condition)))
+                    }
+                }
+            }
+            Expression::FunctionDefinition(definition) => {
+                let mut params = Vec::<(Identifier, Option<Value>)>::new();
+                for param in &definition.params {
+                    let default_opt = match &param.default_opt {
+                        None => { None }
+                        Some(default) => { Some(default.evaluate(symbols)?) }
+                    };
+                    params.push((param.name.clone(), default_opt));
+                }
+                let function =
+                    Arc::new_cyclic(|self_ref| {
+                        let closure =
+                            Closure::new(definition.name.to_string(), params,
+                                         definition.body.clone(), symbols.clone(),
+                                         self_ref.clone());
+                        Box::new(closure) as Box<dyn Function + Send + Sync>
+                    });
+                Ok(Value::Function(function))
+            }
             Expression::Assignment(assignment) => {
                 assignment.rhs.evaluate(symbols)
             }
@@ -116,6 +167,81 @@ impl Expression {
     }
 }
 
+fn get_max_parallel(symbols: &Symbols) -> Result<usize, Error> {
+    let identifier = Identifier::from_str(predef::MAX_PARALLEL_VAR);
+    match symbols.var_entries.get(&identifier) {
+        Some(VarEntry::Value(Value::Int(max_parallel))) if *max_parallel > 0 => {
+            Ok(*max_parallel as usize)
+        }
+        Some(VarEntry::Value(value)) => {
+            Err(Error::from(format!("{} needs to be a positive int, but is {}.", identifier,
+                                    value)))
+        }
+        _ => { Err(Error::from(format!("Variable {} is not set.", identifier))) }
+    }
+}
+
+// results of scatter bodies with the index of their element
+type IndexedResults = Vec<(usize, Result<Value, Error>)>;
+
+// a panic in one scatter body becomes the error of that element
+fn evaluate_scatter_element(scatter: &Scatter, value: &Value, symbols: &Symbols)
+                            -> Result<Value, Error> {
+    let symbols_scatter = symbols.clone().with_var_value_entry(&scatter.iteration.lhs, value);
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        scatter.expression.evaluate(&symbols_scatter)
+    })).unwrap_or_else(|panic| { Err(Error::from(panic)) })
+}
+
+// Runs the scatter body on the current thread plus as many helper threads as there are free
+// slots, all within max_parallel, each taking the next element in order. After a failure no new
+// elements are started, and since elements start in order, the error reported is always that
+// of the first failing element.
+fn evaluate_scatter(scatter: &Scatter, array: &[Value], symbols: &Symbols, max_parallel: usize)
+                    -> Result<Value, Error> {
+    let i_next = AtomicUsize::new(0);
+    let has_failed = AtomicBool::new(false);
+    let thread_slots = &symbols.thread_slots;
+    let n_helpers =
+        (1..cmp::min(max_parallel, array.len())).take_while(|_| {
+            thread_slots.try_take(max_parallel)
+        }).count();
+    let evaluate_next = || {
+        let mut results = IndexedResults::new();
+        while !has_failed.load(Ordering::SeqCst) {
+            let i = i_next.fetch_add(1, Ordering::SeqCst);
+            if i >= array.len() {
+                break;
+            }
+            let result = evaluate_scatter_element(scatter, &array[i], symbols);
+            if result.is_err() {
+                has_failed.store(true, Ordering::SeqCst);
+            }
+            results.push((i, result));
+        }
+        results
+    };
+    let results_by_thread: Vec<thread::Result<IndexedResults>> =
+        thread::scope(|scope| {
+            let helpers: Vec<_> =
+                (0..n_helpers).map(|_| { scope.spawn(evaluate_next) }).collect();
+            let own_results = evaluate_next();
+            helpers.into_iter().map(|helper| { helper.join() })
+                .chain(std::iter::once(Ok(own_results))).collect()
+        });
+    thread_slots.release(n_helpers);
+    let mut results = IndexedResults::with_capacity(array.len());
+    for thread_results in results_by_thread {
+        results.append(&mut thread_results?);
+    }
+    results.sort_by_key(|(i, _)| { *i });
+    let mut values = Vec::<Value>::with_capacity(array.len());
+    for (_, result) in results {
+        values.push(result?);
+    }
+    Ok(Value::Array(Arc::new(values)))
+}
+
 impl Clone for Expression {
     fn clone(&self) -> Self {
         match self {
@@ -131,6 +257,9 @@ impl Clone for Expression {
             Expression::Member(expression, identifier) => {
                 Expression::Member(expression.clone(), identifier.clone())
             }
+            Expression::Index(expression, index) => {
+                Expression::Index(expression.clone(), index.clone())
+            }
             Expression::Call(callee, args) => {
                 let mut args_cloned = Vec::<Assignment>::new();
                 for arg in args {
@@ -138,9 +267,21 @@ impl Clone for Expression {
                 }
                 Expression::Call(callee.clone(), args.clone())
             }
+            Expression::Array(elements) => {
+                Expression::Array(elements.clone())
+            }
+            Expression::Object(entries) => {
+                Expression::Object(entries.clone())
+            }
             Expression::Scatter(scatter) => {
                 Expression::Scatter(scatter.clone())
             }
+            Expression::Conditional(conditional) => {
+                Expression::Conditional(conditional.clone())
+            }
+            Expression::FunctionDefinition(definition) => {
+                Expression::FunctionDefinition(definition.clone())
+            }
             Expression::Assignment(assignment) => {
                 Expression::Assignment(assignment.clone())
             }
@@ -168,6 +309,62 @@ impl Clone for Scatter {
     }
 }
 
+pub(crate) struct Conditional {
+    pub(crate) condition: Expression,
+    pub(crate) then_expression: Expression,
+    pub(crate) else_expression_opt: Option<Expression>,
+}
+
+impl Conditional {
+    pub(crate) fn new(condition: Expression, then_expression: Expression,
+                      else_expression_opt: Option<Expression>) -> Conditional {
+        Conditional { condition, then_expression, else_expression_opt }
+    }
+}
+
+impl Clone for Conditional {
+    fn clone(&self) -> Self {
+        Conditional::new(self.condition.clone(), self.then_expression.clone(),
+                         self.else_expression_opt.clone())
+    }
+}
+
+pub(crate) struct Parameter {
+    pub(crate) name: Identifier,
+    pub(crate) default_opt: Option<Expression>,
+}
+
+impl Parameter {
+    pub(crate) fn new(name: Identifier, default_opt: Option<Expression>) -> Parameter {
+        Parameter { name, default_opt }
+    }
+}
+
+impl Clone for Parameter {
+    fn clone(&self) -> Self {
+        Parameter::new(self.name.clone(), self.default_opt.clone())
+    }
+}
+
+pub(crate) struct FunctionDefinition {
+    pub(crate) name: Identifier,
+    pub(crate) params: Vec<Parameter>,
+    pub(crate) body: Expression,
+}
+
+impl FunctionDefinition {
+    pub(crate) fn new(name: Identifier, params: Vec<Parameter>, body: Expression)
+                      -> FunctionDefinition {
+        FunctionDefinition { name, params, body }
+    }
+}
+
+impl Clone for FunctionDefinition {
+    fn clone(&self) -> Self {
+        FunctionDefinition::new(self.name.clone(), self.params.clone(), self.body.clone())
+    }
+}
+
 pub(crate) struct Iteration {
     pub(crate) lhs: Identifier,
     pub(crate) rhs: Expression,
@@ -229,13 +426,16 @@ pub(crate) trait Function {
 
 pub(crate) struct Script {
     expressions: Vec<Expression>,
+    max_parallel: usize,
 }
 
 impl Script {
-    pub(crate) fn new(expressions: Vec<Expression>) -> Script { Script { expressions } }
+    pub(crate) fn new(expressions: Vec<Expression>, max_parallel: usize) -> Script {
+        Script { expressions, max_parallel }
+    }
     pub(crate) fn optimize(self) -> Script { self }
     pub(crate) fn evaluate(&self) -> Result<Value, Error> {
-        let symbols = predef::predef_symbols();
+        let symbols = predef::predef_symbols(self.max_parallel);
         evaluate_expressions(&self.expressions, &symbols)
     }
 }
@@ -253,3 +453,102 @@ fn evaluate_expressions(expressions: &[Expression], symbols: &Symbols) -> Result
     Ok(value)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use crate::util::error::Error;
+    use crate::mion::eval::expressions::{evaluate_scatter, Assignment, Expression, Function,
+                                         Iteration, Scatter};
+    use crate::mion::eval::identifier::Identifier;
+    use crate::mion::eval::predef;
+    use crate::mion::eval::symbols::Symbols;
+    use crate::mion::eval::values::Value;
+
+    // doubles its argument, fails for some, and records how many calls overlapped
+    struct Probe {
+        n_running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    impl Function for Probe {
+        fn id(&self) -> &str { "probe" }
+        fn call(&self, args_map: HashMap<Identifier, Value>) -> Result<Value, Error> {
+            let n_running = self.n_running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(n_running, Ordering::SeqCst);
+            let x = args_map.get(&Identifier::from_str("x")).unwrap().as_int()?;
+            thread::sleep(Duration::from_millis(if x == 7 { 1 } else { 20 }));
+            self.n_running.fetch_sub(1, Ordering::SeqCst);
+            if x == 5 || x == 7 {
+                Err(Error::from(format!("Failed for {}", x)))
+            } else {
+                Ok(Value::Int(2 * x))
+            }
+        }
+    }
+
+    // a call of the probe with x as argument
+    fn probe_call(max_running: &Arc<AtomicUsize>) -> Expression {
+        let probe =
+            Probe { n_running: Arc::new(AtomicUsize::new(0)), max_running: max_running.clone() };
+        let function: Box<dyn Function + Send + Sync> = Box::new(probe);
+        let x = Identifier::from_str("x");
+        Expression::Call(Box::new(Expression::Value(Value::Function(Arc::new(function)))),
+                         vec!(Assignment::new(x.clone(), Expression::Identifier(x))))
+    }
+
+    fn int_array(ints: std::ops::Range<i64>) -> Value {
+        Value::Array(Arc::new(ints.map(Value::Int).collect()))
+    }
+
+    #[test]
+    fn scatter_is_bounded_ordered_and_deterministic() {
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let call = probe_call(&max_running);
+        let x = Identifier::from_str("x");
+        let scatter = Scatter::new(Iteration::new(x, Expression::Value(Value::Unit)), call);
+        let symbols = Symbols::new();
+        let array: Vec<Value> = (0..5).map(Value::Int).collect();
+        let value = evaluate_scatter(&scatter, &array, &symbols, 2).unwrap();
+        let ints: Vec<i64> =
+            value.as_vec_ref().unwrap().iter().map(|value| { value.as_int().unwrap() }).collect();
+        assert_eq!(ints, vec!(0, 2, 4, 6, 8));
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+        let array: Vec<Value> = (0..10).map(Value::Int).collect();
+        for _ in 0..5 {
+            match evaluate_scatter(&scatter, &array, &symbols, 4) {
+                Ok(value) => { panic!("Expected error, but got {}", value) }
+                Err(error) => { assert_eq!(error.to_string(), "Failed for 5") }
+            }
+        }
+    }
+
+    #[test]
+    fn nested_scatter_stays_within_max_parallel() {
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let inner =
+            Scatter::new(Iteration::new(Identifier::from_str("x"),
+                                        Expression::Value(int_array(0..4))),
+                         probe_call(&max_running));
+        let outer =
+            Scatter::new(Iteration::new(Identifier::from_str("i"),
+                                        Expression::Value(int_array(0..4))),
+                         Expression::Scatter(Box::new(inner)));
+        let symbols =
+            Symbols::new().with_var_value_entry(&Identifier::from_str(predef::MAX_PARALLEL_VAR),
+                                                &Value::Int(3));
+        let value = Expression::Scatter(Box::new(outer)).evaluate(&symbols).unwrap();
+        for inner_value in value.as_vec_ref().unwrap().iter() {
+            let ints: Vec<i64> =
+                inner_value.as_vec_ref().unwrap().iter().map(|value| {
+                    value.as_int().unwrap()
+                }).collect();
+            assert_eq!(ints, vec!(0, 2, 4, 6));
+        }
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+}
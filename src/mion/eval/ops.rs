@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::util::error::Error;
+use crate::mion::eval::identifier::Identifier;
+use crate::mion::eval::values::Value;
+use crate::mion::syntax::ops::BinOp;
+
+// Operands of a binary operation, with an int promoted to float if the other one is a float
+enum Operands<'a> {
+    Bools(bool, bool),
+    Ints(i64, i64),
+    Floats(f64, f64),
+    Strings(&'a str, &'a str),
+}
+
+fn operands<'a>(lhs: &'a Value, rhs: &'a Value) -> Option<Operands<'a>> {
+    match (lhs, rhs) {
+        (Value::Bool(lhs), Value::Bool(rhs)) => { Some(Operands::Bools(*lhs, *rhs)) }
+        (Value::Int(lhs), Value::Int(rhs)) => { Some(Operands::Ints(*lhs, *rhs)) }
+        (Value::Int(lhs), Value::Float(rhs)) => { Some(Operands::Floats(*lhs as f64, *rhs)) }
+        (Value::Float(lhs), Value::Int(rhs)) => { Some(Operands::Floats(*lhs, *rhs as f64)) }
+        (Value::Float(lhs), Value::Float(rhs)) => { Some(Operands::Floats(*lhs, *rhs)) }
+        (Value::String(lhs), Value::String(rhs)) => {
+            Some(Operands::Strings(lhs.as_str(), rhs.as_str()))
+        }
+        _ => { None }
+    }
+}
+
+fn err_not_applicable(lhs: &Value, op: BinOp, rhs: &Value) -> Error {
+    Error::from(format!("Cannot apply {} to {} {} and {} {}.", op, lhs.type_name(), lhs,
+                        rhs.type_name(), rhs))
+}
+
+fn err_overflow(lhs: i64, op: BinOp, rhs: i64) -> Error {
+    Error::from(format!("Int overflow in {} {} {}.", lhs, op, rhs))
+}
+
+fn apply_ints(lhs: i64, op: BinOp, rhs: i64) -> Result<Value, Error> {
+    let result_opt =
+        match op {
+            BinOp::Times => { lhs.checked_mul(rhs) }
+            BinOp::Plus => { lhs.checked_add(rhs) }
+            BinOp::Minus => { lhs.checked_sub(rhs) }
+            BinOp::By | BinOp::Modulo if rhs == 0 => {
+                return Err(Error::from(format!("Division by zero in {} {} {}.", lhs, op, rhs)));
+            }
+            BinOp::By => { lhs.checked_div(rhs) }
+            BinOp::Modulo => { lhs.checked_rem(rhs) }
+            _ => { return Ok(Value::Bool(compare(lhs.cmp(&rhs), op))) }
+        };
+    result_opt.map(Value::Int).ok_or_else(|| { err_overflow(lhs, op, rhs) })
+}
+
+fn apply_floats(lhs: f64, op: BinOp, rhs: f64) -> Value {
+    match op {
+        BinOp::Times => { Value::Float(lhs * rhs) }
+        BinOp::By => { Value::Float(lhs / rhs) }
+        BinOp::Modulo => { Value::Float(lhs % rhs) }
+        BinOp::Plus => { Value::Float(lhs + rhs) }
+        BinOp::Minus => { Value::Float(lhs - rhs) }
+        BinOp::Equal => { Value::Bool(lhs == rhs) }
+        BinOp::NotEqual => { Value::Bool(lhs != rhs) }
+        BinOp::LessThan => { Value::Bool(lhs < rhs) }
+        BinOp::LessOrEqual => { Value::Bool(lhs <= rhs) }
+        BinOp::GreaterThan => { Value::Bool(lhs > rhs) }
+        BinOp::GreaterOrEqual => { Value::Bool(lhs >= rhs) }
+        BinOp::And | BinOp::Or => { unreachable!("Logical operators take bools only.") }
+    }
+}
+
+// only called with comparison operators
+fn compare(ordering: Ordering, op: BinOp) -> bool {
+    match op {
+        BinOp::Equal => { ordering == Ordering::Equal }
+        BinOp::NotEqual => { ordering != Ordering::Equal }
+        BinOp::LessThan => { ordering == Ordering::Less }
+        BinOp::LessOrEqual => { ordering != Ordering::Greater }
+        BinOp::GreaterThan => { ordering == Ordering::Greater }
+        _ => { ordering != Ordering::Less }
+    }
+}
+
+fn is_logical(op: BinOp) -> bool { matches!(op, BinOp::And | BinOp::Or) }
+
+fn is_equality(op: BinOp) -> bool { matches!(op, BinOp::Equal | BinOp::NotEqual) }
+
+fn is_comparison(op: BinOp) -> bool {
+    is_equality(op) ||
+        matches!(op, BinOp::LessThan | BinOp::LessOrEqual | BinOp::GreaterThan |
+            BinOp::GreaterOrEqual)
+}
+
+// Arithmetic and comparisons take ints and floats, with ints promoted to float if the other
+// operand is a float. Strings can be concatenated with + and compared. Bools can be combined with
+// && and || and tested for equality. Anything else is an error.
+pub(crate) fn apply(lhs: &Value, op: BinOp, rhs: &Value) -> Result<Value, Error> {
+    match operands(lhs, rhs) {
+        Some(Operands::Bools(lhs, rhs)) if is_logical(op) || is_equality(op) => {
+            match op {
+                BinOp::And => { Ok(Value::Bool(lhs && rhs)) }
+                BinOp::Or => { Ok(Value::Bool(lhs || rhs)) }
+                _ => { Ok(Value::Bool(compare(lhs.cmp(&rhs), op))) }
+            }
+        }
+        Some(Operands::Ints(lhs, rhs)) if !is_logical(op) => { apply_ints(lhs, op, rhs) }
+        Some(Operands::Floats(lhs, rhs)) if !is_logical(op) => { Ok(apply_floats(lhs, op, rhs)) }
+        Some(Operands::Strings(lhs, rhs)) if matches!(op, BinOp::Plus) => {
+            Ok(Value::String(Arc::new(format!("{}{}", lhs, rhs))))
+        }
+        Some(Operands::Strings(lhs, rhs)) if is_comparison(op) => {
+            Ok(Value::Bool(compare(lhs.cmp(rhs), op)))
+        }
+        _ => { Err(err_not_applicable(lhs, op, rhs)) }
+    }
+}
+
+// Arrays are indexed by int, starting at zero, and objects by the name of a member
+pub(crate) fn index(value: &Value, index: &Value) -> Result<Value, Error> {
+    match (value, index) {
+        (Value::Array(elements), Value::Int(i)) => {
+            usize::try_from(*i).ok().and_then(|i| { elements.get(i) }).cloned()
+                .ok_or_else(|| {
+                    Error::from(format!("Index {} is out of bounds for array of length {}.", i,
+                                        elements.len()))
+                })
+        }
+        (Value::Object(members), Value::String(name)) => {
+            members.get(&Identifier::new(name.as_ref().clone())).cloned().ok_or_else(|| {
+                Error::from(format!("{} does not have a member {}.", value, name))
+            })
+        }
+        _ => {
+            Err(Error::from(format!("Cannot index {} {} with {} {}.", value.type_name(), value,
+                                    index.type_name(), index)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mion::eval::ops::apply;
+    use crate::mion::eval::values::Value;
+    use crate::mion::syntax::ops::BinOp;
+
+    fn apply_to_string(lhs: Value, op: BinOp, rhs: Value) -> String {
+        match apply(&lhs, op, &rhs) {
+            Ok(value) => { value.to_string() }
+            Err(error) => { error.to_string() }
+        }
+    }
+
+    #[test]
+    fn apply_bin_ops() {
+        assert_eq!(apply_to_string(Value::Int(7), BinOp::By, Value::Int(2)), "3");
+        assert_eq!(apply_to_string(Value::Int(7), BinOp::Modulo, Value::Int(2)), "1");
+        assert_eq!(apply_to_string(Value::Int(7), BinOp::By, Value::Float(2.0)), "3.5");
+        assert_eq!(apply_to_string(Value::Int(2), BinOp::LessThan, Value::Float(2.5)), "true");
+        assert_eq!(apply_to_string(Value::from("a/"), BinOp::Plus, Value::from("b")), "a/b");
+        assert_eq!(apply_to_string(Value::from("a"), BinOp::LessThan, Value::from("b")), "true");
+        assert_eq!(apply_to_string(Value::Bool(true), BinOp::And, Value::Bool(false)), "false");
+        assert_eq!(apply_to_string(Value::Bool(true), BinOp::NotEqual, Value::Bool(false)),
+                   "true");
+        assert_eq!(apply_to_string(Value::Int(1), BinOp::By, Value::Int(0)),
+                   "Division by zero in 1 / 0.");
+        assert_eq!(apply_to_string(Value::Int(i64::MAX), BinOp::Plus, Value::Int(1)),
+                   format!("Int overflow in {} + 1.", i64::MAX));
+        assert_eq!(apply_to_string(Value::from("a"), BinOp::Plus, Value::Int(1)),
+                   "Cannot apply + to string a and int 1.");
+        assert_eq!(apply_to_string(Value::Int(1), BinOp::And, Value::Int(1)),
+                   "Cannot apply && to int 1 and int 1.");
+        assert_eq!(apply_to_string(Value::Bool(true), BinOp::LessThan, Value::Bool(false)),
+                   "Cannot apply < to bool true and bool false.");
+    }
+}
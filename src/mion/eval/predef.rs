@@ -1,4 +1,6 @@
 use crate::mion::eval::symbols::Symbols;
+use crate::mion::eval::identifier::Identifier;
+use crate::mion::eval::values::Value;
 use crate::mion::eval::builtin::split_by_chrom::SplitByChrom;
 use crate::mion::eval::builtin::tabix::Tabix;
 use crate::mion::eval::builtin::new::New;
@@ -7,9 +9,16 @@ use crate::mion::eval::builtin::vep::Vep;
 use crate::mion::eval::builtin::replace_file_name::ReplaceFileName;
 use crate::mion::eval::builtin::transform_vep_results::TransformVepResults;
 use crate::mion::eval::builtin::merge_sorted_files::MergeSortedFiles;
+use crate::mion::eval::builtin::bgzip::Bgzip;
+use crate::mion::eval::builtin::tabix_query::TabixQuery;
 
-pub(crate) fn predef_symbols() -> Symbols {
+// how many scatter bodies run at a time, unless the script assigns another value
+pub(crate) const MAX_PARALLEL_VAR: &str = "max_parallel";
+
+pub(crate) fn predef_symbols(max_parallel: usize) -> Symbols {
+    let max_parallel_identifier = Identifier::from_str(MAX_PARALLEL_VAR);
     Symbols::new()
+        .with_var_value_entry(&max_parallel_identifier, &Value::Int(max_parallel as i64))
         .with_function_entry(Box::new(SplitByChrom {}))
         .with_function_entry(Box::new(Tabix {}))
         .with_function_entry(Box::new(New {}))
@@ -18,4 +27,6 @@ pub(crate) fn predef_symbols() -> Symbols {
         .with_function_entry(Box::new(ReplaceFileName {}))
         .with_function_entry(Box::new(TransformVepResults {}))
         .with_function_entry(Box::new(MergeSortedFiles {}))
+        .with_function_entry(Box::new(Bgzip {}))
+        .with_function_entry(Box::new(TabixQuery {}))
 }
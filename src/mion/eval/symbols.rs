@@ -1,10 +1,12 @@
 use crate::mion::eval::values::Value;
 use crate::mion::eval::identifier::Identifier;
 use crate::mion::eval::expressions::Function;
+use crate::mion::eval::thread_slots::ThreadSlots;
 use std::sync::Arc;
 
 pub(crate) struct Symbols {
     pub(crate) var_entries: VarEntries,
+    pub(crate) thread_slots: Arc<ThreadSlots>,
 }
 
 pub(crate) enum VarEntry {
@@ -20,20 +22,21 @@ pub(crate) enum VarEntries {
 impl Symbols {
     pub(crate) fn new() -> Symbols {
         let var_entries = VarEntries::new();
-        Symbols { var_entries }
+        let thread_slots = Arc::new(ThreadSlots::new());
+        Symbols { var_entries, thread_slots }
     }
     pub(crate) fn with_var_uninitialized_entry(self, identifier: &Identifier) -> Symbols {
         let var_entries = self.var_entries.with_uninitialized_entry(identifier);
-        Symbols { var_entries }
+        Symbols { var_entries, thread_slots: self.thread_slots }
     }
     pub(crate) fn with_var_value_entry(self, identifier: &Identifier, value: &Value) -> Symbols {
         let var_entries = self.var_entries.with_value_entry(identifier, value);
-        Symbols { var_entries }
+        Symbols { var_entries, thread_slots: self.thread_slots }
     }
     pub(crate) fn with_function_entry(self, function: Box<dyn Function + Send + Sync>)
                                       -> Symbols {
         let var_entries = self.var_entries.with_function_entry(function);
-        Symbols { var_entries }
+        Symbols { var_entries, thread_slots: self.thread_slots }
     }
 }
 
@@ -91,6 +94,6 @@ impl Clone for VarEntries {
 impl Clone for Symbols {
     fn clone(&self) -> Self {
         let var_entries = self.var_entries.clone();
-        Symbols { var_entries }
+        Symbols { var_entries, thread_slots: self.thread_slots.clone() }
     }
 }
\ No newline at end of file
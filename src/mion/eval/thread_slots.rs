@@ -0,0 +1,20 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Threads evaluating the script, the main thread included, shared by all scatters, nested or
+// not, so that together they stay within max_parallel. Taking a slot never blocks, since a
+// scatter waiting for slots held by its own enclosing scatter would wait forever.
+pub(crate) struct ThreadSlots {
+    n_taken: AtomicUsize,
+}
+
+impl ThreadSlots {
+    pub(crate) fn new() -> ThreadSlots { ThreadSlots { n_taken: AtomicUsize::new(1) } }
+    pub(crate) fn try_take(&self, max_parallel: usize) -> bool {
+        self.n_taken.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n_taken| {
+            if n_taken < max_parallel { Some(n_taken + 1) } else { None }
+        }).is_ok()
+    }
+    pub(crate) fn release(&self, n_slots: usize) {
+        self.n_taken.fetch_sub(n_slots, Ordering::SeqCst);
+    }
+}
@@ -8,6 +8,7 @@ use crate::mion::eval::identifier::Identifier;
 
 pub(crate) enum Value {
     Unit,
+    Bool(bool),
     String(Arc<String>),
     Int(i64),
     Float(f64),
@@ -17,6 +18,18 @@ pub(crate) enum Value {
 }
 
 impl Value {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => { "unit" }
+            Value::Bool(_) => { "bool" }
+            Value::String(_) => { "string" }
+            Value::Int(_) => { "int" }
+            Value::Float(_) => { "float" }
+            Value::Array(_) => { "array" }
+            Value::Object(_) => { "object" }
+            Value::Function(_) => { "function" }
+        }
+    }
     pub(crate) fn as_string(&self) -> Result<String, Error> {
         if let Value::String(string_rc) = self {
             Ok(string_rc.as_ref().clone())
@@ -24,6 +37,13 @@ impl Value {
             Err(Error::from(format!("Value {} is not a string value.", self)))
         }
     }
+    pub(crate) fn as_int(&self) -> Result<i64, Error> {
+        if let Value::Int(int) = self {
+            Ok(*int)
+        } else {
+            Err(Error::from(format!("Value {} is not an int value.", self)))
+        }
+    }
     pub(crate) fn as_map_ref(&self) -> Result<&HashMap<Identifier, Value>, Error> {
         if let Value::Object(map) = self {
             Ok(map)
@@ -43,6 +63,7 @@ impl Value {
 impl Clone for Value {
     fn clone(&self) -> Self {
         match self {
+            Value::Bool(bool) => { Value::Bool(*bool) }
             Value::String(string_rc) => { Value::String(string_rc.clone()) }
             Value::Int(int) => { Value::Int(*int) }
             Value::Float(float) => { Value::Float(*float) }
@@ -68,6 +89,12 @@ impl From<&str> for Value {
     }
 }
 
+impl From<&bool> for Value {
+    fn from(bool: &bool) -> Self {
+        Value::Bool(*bool)
+    }
+}
+
 impl From<&i64> for Value {
     fn from(int: &i64) -> Self {
         Value::Int(*int)
@@ -83,6 +110,7 @@ impl From<&f64> for Value {
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            Value::Bool(bool) => { bool.fmt(f) }
             Value::String(string) => { string.fmt(f) }
             Value::Int(int) => { int.fmt(f) }
             Value::Float(float) => { float.fmt(f) }
@@ -112,6 +140,12 @@ impl ObjectBuilder {
         map.insert(identifier, value);
         ObjectBuilder { map }
     }
+    pub(crate) fn with_int(self, key: &str, value: i64) -> ObjectBuilder {
+        let identifier = Identifier::new(String::from(key));
+        let mut map = self.map;
+        map.insert(identifier, Value::Int(value));
+        ObjectBuilder { map }
+    }
     pub(crate) fn into_object(self) -> Value {
         let map = self.map;
         Value::Object(map)
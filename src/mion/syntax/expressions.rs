@@ -16,10 +16,10 @@ impl Script {
     pub(crate) fn new(expressions: Vec<Expression>) -> Script {
         Script { expressions }
     }
-    pub(crate) fn compile(&self) -> Result<expressions::Script, Error> {
-        let symbols = predef::predef_symbols();
+    pub(crate) fn compile(&self, max_parallel: usize) -> Result<expressions::Script, Error> {
+        let symbols = predef::predef_symbols(max_parallel);
         let eval_expressions = compile_expressions(&self.expressions, &symbols)?;
-        Ok(expressions::Script::new(eval_expressions))
+        Ok(expressions::Script::new(eval_expressions, max_parallel))
     }
 }
 
@@ -47,6 +47,12 @@ pub(crate) struct Block {
     pub(crate) expressions: Vec<Expression>,
 }
 
+fn compile_block(block: &Block, symbols: &Symbols) -> Result<expressions::Expression, Error> {
+    let eval_expressions = compile_expressions(&block.expressions, symbols)?;
+    let eval_block = expressions::Block::new(eval_expressions);
+    Ok(expressions::Expression::Block(Box::new(eval_block)))
+}
+
 pub(crate) struct Assignment {
     pub(crate) lhs: Identifier,
     pub(crate) rhs: Expression,
@@ -71,13 +77,63 @@ pub(crate) struct Iteration {
     pub(crate) rhs: Expression,
 }
 
+// the else branch is a block or another conditional, for else if
+pub(crate) struct Conditional {
+    pub(crate) condition: Expression,
+    pub(crate) then_block: Block,
+    pub(crate) else_opt: Option<Expression>,
+}
+
+pub(crate) struct Parameter {
+    pub(crate) name: Identifier,
+    pub(crate) default_opt: Option<Expression>,
+}
+
+// a definition binds the name, like an assignment would, and the body sees the name too, for
+// recursive calls
+pub(crate) struct FunctionDefinition {
+    pub(crate) name: Identifier,
+    pub(crate) params: Vec<Parameter>,
+    pub(crate) body: Block,
+}
+
+impl FunctionDefinition {
+    fn compile(&self, symbols: &Symbols) -> Result<expressions::Expression, Error> {
+        let mut eval_params = Vec::<expressions::Parameter>::new();
+        let mut symbols_body = symbols.clone().with_var_uninitialized_entry(&self.name);
+        for param in &self.params {
+            if eval_params.iter().any(|eval_param| { eval_param.name == param.name }) {
+                return Err(Error::from(format!("Duplicate parameter {} of function {}.",
+                                               param.name, self.name)));
+            }
+            let eval_default_opt = match &param.default_opt {
+                None => { None }
+                Some(default) => { Some(default.compile(symbols)?) }
+            };
+            eval_params.push(expressions::Parameter::new(param.name.clone(), eval_default_opt));
+            symbols_body = symbols_body.with_var_uninitialized_entry(&param.name);
+        }
+        let eval_body = compile_block(&self.body, &symbols_body)?;
+        let eval_definition =
+            expressions::FunctionDefinition::new(self.name.clone(), eval_params, eval_body);
+        let eval_rhs = expressions::Expression::FunctionDefinition(Box::new(eval_definition));
+        let eval_assignment = expressions::Assignment::new(self.name.clone(), eval_rhs);
+        Ok(expressions::Expression::Assignment(Box::new(eval_assignment)))
+    }
+}
+
 pub(crate) enum Expression {
     Identifier(Identifier),
     Literal(Literal),
     Binary(Box<Expression>, BinOp, Box<Expression>),
     Member(Box<Expression>, Identifier),
+    Index(Box<Expression>, Box<Expression>),
     Call(Box<Expression>, Vec<Assignment>),
+    Array(Vec<Expression>),
+    Object(Vec<Assignment>),
     Scatter(Box<Scatter>),
+    Conditional(Box<Conditional>),
+    FunctionDefinition(Box<FunctionDefinition>),
     Assignment(Box<Assignment>),
     Block(Block),
 }
@@ -112,6 +168,11 @@ impl Expression {
                 Ok(expressions::Expression::Member(eval_expression,
                                                    identifier.clone()))
             }
+            Expression::Index(expression, index) => {
+                let eval_expression = Box::new(expression.compile(symbols)?);
+                let eval_index = Box::new(index.compile(symbols)?);
+                Ok(expressions::Expression::Index(eval_expression, eval_index))
+            }
             Expression::Call(callee, arguments) => {
                 let eval_callee = Box::new(callee.compile(symbols)?);
                 let mut eval_arguments = Vec::<eval::expressions::Assignment>::new();
@@ -120,6 +181,24 @@ impl Expression {
                 }
                 Ok(expressions::Expression::Call(eval_callee, eval_arguments))
             }
+            Expression::Array(elements) => {
+                let mut eval_elements = Vec::<expressions::Expression>::new();
+                for element in elements {
+                    eval_elements.push(element.compile(symbols)?);
+                }
+                Ok(expressions::Expression::Array(eval_elements))
+            }
+            Expression::Object(entries) => {
+                let mut eval_entries = Vec::<eval::expressions::Assignment>::new();
+                for entry in entries {
+                    if eval_entries.iter().any(|eval_entry| { eval_entry.lhs == entry.lhs }) {
+                        return Err(Error::from(format!("Duplicate key {} in object.",
+                                                       entry.lhs)));
+                    }
+                    eval_entries.push(entry.compile(symbols)?);
+                }
+                Ok(expressions::Expression::Object(eval_entries))
+            }
             Expression::Scatter(scatter) => {
                 let eval_iteration_lhs = scatter.iteration.lhs.clone();
                 let eval_iteration_expression = scatter.iteration.rhs.compile(symbols)?;
@@ -134,21 +213,29 @@ impl Expression {
                     expressions::Scatter::new(eval_iteration, eval_expression);
                 Ok(expressions::Expression::Scatter(Box::new(eval_scatter)))
             }
+            Expression::Conditional(conditional) => {
+                let eval_condition = conditional.condition.compile(symbols)?;
+                let eval_then = compile_block(&conditional.then_block, symbols)?;
+                let eval_else_opt = match &conditional.else_opt {
+                    None => { None }
+                    Some(else_expression) => { Some(else_expression.compile(symbols)?) }
+                };
+                let eval_conditional =
+                    expressions::Conditional::new(eval_condition, eval_then, eval_else_opt);
+                Ok(expressions::Expression::Conditional(Box::new(eval_conditional)))
+            }
+            Expression::FunctionDefinition(definition) => { definition.compile(symbols) }
             Expression::Assignment(assignment) => {
                 let eval_assignment = assignment.compile(symbols)?;
                 Ok(expressions::Expression::Assignment(Box::new(eval_assignment)))
             }
-            Expression::Block(block) => {
-                let eval_expressions =
-                    compile_expressions(&block.expressions, symbols)?;
-                let eval_block = expressions::Block::new(eval_expressions);
-                Ok(expressions::Expression::Block(Box::new(eval_block)))
-            }
+            Expression::Block(block) => { compile_block(block, symbols) }
         }
     }
 }
 
 pub(crate) enum Literal {
+    Bool(bool),
     Int(i64),
     String(String),
     Float(f64),
@@ -157,6 +244,7 @@ pub(crate) enum Literal {
 impl Literal {
     pub(crate) fn to_value(&self) -> Value {
         match self {
+            Literal::Bool(bool) => { Value::from(bool) }
             Literal::Int(int) => { Value::from(int) }
             Literal::String(string) => { Value::from(string) }
             Literal::Float(float) => { Value::from(float) }
@@ -184,11 +272,18 @@ impl Display for Expression {
             Expression::Member(expression, identifier) => {
                 format!("{}.{}", expression, identifier).fmt(f)
             }
+            Expression::Index(expression, index) => {
+                format!("{}[{}]", expression, index).fmt(f)
+            }
             Expression::Call(callee, args) => {
                 callee.fmt(f)?;
                 fmt_vec("(", args, ")", f)
             }
+            Expression::Array(elements) => { fmt_vec("[", elements, "]", f) }
+            Expression::Object(entries) => { fmt_vec("{ ", entries, " }", f) }
             Expression::Scatter(scatter) => { scatter.fmt(f) }
+            Expression::Conditional(conditional) => { conditional.fmt(f) }
+            Expression::FunctionDefinition(definition) => { definition.fmt(f) }
             Expression::Assignment(assignment) => { assignment.fmt(f) }
             Expression::Block(block) => { block.fmt(f) }
         }
@@ -198,6 +293,7 @@ impl Display for Expression {
 impl Display for Literal {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            Literal::Bool(bool) => { bool.fmt(f) }
             Literal::Int(int) => { int.fmt(f) }
             Literal::String(string) => { format!("\"{}\"", string).fmt(f) }
             Literal::Float(float) => { float.fmt(f) }
@@ -211,6 +307,33 @@ impl Display for Scatter {
     }
 }
 
+impl Display for Conditional {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        format!("if {} {}", self.condition, self.then_block).fmt(f)?;
+        match &self.else_opt {
+            None => { Ok(()) }
+            Some(else_expression) => { format!(" else {}", else_expression).fmt(f) }
+        }
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.default_opt {
+            None => { self.name.fmt(f) }
+            Some(default) => { format!("{} = {}", self.name, default).fmt(f) }
+        }
+    }
+}
+
+impl Display for FunctionDefinition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        format!("fn {}", self.name).fmt(f)?;
+        fmt_vec("(", &self.params, ")", f)?;
+        format!(" {}", self.body).fmt(f)
+    }
+}
+
 impl Display for Iteration {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         format!("{} <- {}", self.lhs, self.rhs).fmt(f)
@@ -23,6 +23,17 @@ pub(crate) mod symbols {
     pub(crate) const CLOSE_PARENS: &str = ")";
     pub(crate) const OPEN_BRACKETS: &str = "{";
     pub(crate) const CLOSE_BRACKETS: &str = "}";
+    pub(crate) const OPEN_SQUARE_BRACKETS: &str = "[";
+    pub(crate) const CLOSE_SQUARE_BRACKETS: &str = "]";
+}
+
+pub(crate) mod keywords {
+    pub(crate) const TRUE: &str = "true";
+    pub(crate) const FALSE: &str = "false";
+    pub(crate) const IF: &str = "if";
+    pub(crate) const ELSE: &str = "else";
+    pub(crate) const FN: &str = "fn";
+    pub(crate) const ALL: [&str; 5] = [TRUE, FALSE, IF, ELSE, FN];
 }
 
 #[derive(Clone, Copy)]
@@ -1,35 +1,52 @@
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::{alpha1, alphanumeric1, none_of, one_of};
-use nom::combinator::{map_res, opt, recognize, value, map};
+use nom::combinator::{all_consuming, map_res, not, opt, recognize, value, map, verify};
 use nom::error::{context, VerboseError};
 use nom::IResult;
 use nom::multi::{many0, many1};
 use nom::number::complete::double;
 use nom::Parser;
-use nom::sequence::{pair, tuple, delimited};
+use nom::sequence::{pair, tuple, delimited, terminated};
 use crate::Error;
 
-use crate::mion::syntax::expressions::{Expression, Iteration, Assignment, Scatter, Block, Script};
+use crate::mion::syntax::expressions::{Expression, Iteration, Assignment, Scatter, Conditional,
+                                       Block, Script, FunctionDefinition, Parameter};
 use crate::mion::syntax::expressions::Literal;
-use crate::mion::syntax::ops::{BinOp, symbols};
+use crate::mion::syntax::ops::{BinOp, symbols, keywords};
 use crate::mion::syntax::string;
 use crate::mion::eval::identifier::Identifier;
 
 type ParseResult<'a, O> = IResult<&'a str, O, VerboseError<&'a str>>;
 
+fn word(i: &str) -> ParseResult<'_, &str> {
+    recognize(
+        pair(
+            alt((alpha1, tag("_"))),
+            many0(alt((alphanumeric1, tag("_")))),
+        ))(i)
+}
+
+pub(crate) fn keyword<'a>(keyword: &'static str)
+                          -> impl FnMut(&'a str) -> ParseResult<'a, &'a str> {
+    verify(word, move |word: &str| { word == keyword })
+}
+
 pub(crate) fn identifier(i: &str) -> ParseResult<Identifier> {
     context(
         "identifier",
-        map(map(recognize(
-            pair(
-                alt((alpha1, tag("_"))),
-                many0(alt((alphanumeric1, tag("_")))),
-            )),
+        map(map(verify(word, |word: &str| { !keywords::ALL.contains(&word) }),
                 String::from,
         ), Identifier::new))(i)
 }
 
+pub(crate) fn bool_literal(i: &str) -> ParseResult<'_, Literal> {
+    context("bool literal",
+            alt((keyword(keywords::TRUE).map(|_| { Literal::Bool(true) }),
+                 keyword(keywords::FALSE).map(|_| { Literal::Bool(false) }))),
+    )(i)
+}
+
 pub(crate) fn string_literal(i: &str) -> ParseResult<Literal> {
     context("string literal", map(string::parse_string, Literal::String))(i)
 }
@@ -40,20 +57,21 @@ pub(crate) fn float_literal(i: &str) -> ParseResult<Literal> {
 
 pub(crate) fn integer_literal(i: &str) -> ParseResult<Literal> {
     context("integer literal",
-            map_res(recognize(pair(opt(alt((tag("-"), tag("+")))),
-                                   many1(one_of("0123456789")))),
+            map_res(terminated(recognize(pair(opt(alt((tag("-"), tag("+")))),
+                                              many1(one_of("0123456789")))),
+                               not(one_of(".eE"))),
                     |int_str: &str| { int_str.parse::<i64>() })
                 .map(|i| { Literal::Int(i) }),
     )(i)
 }
 
 pub(crate) fn literal(i: &str) -> ParseResult<Literal> {
-    context("literal", alt((integer_literal, float_literal, string_literal)))(i)
+    context("literal", alt((bool_literal, integer_literal, float_literal, string_literal)))(i)
 }
 
 pub(crate) fn comment(i: &str) -> ParseResult<()> {
     context("comment",
-            value((), pair(tag("//"), none_of("\r\n"))),
+            value((), pair(tag("//"), many0(none_of("\r\n")))),
     )(i)
 }
 
@@ -70,24 +88,97 @@ pub(crate) fn atomic(i: &str) -> ParseResult<Expression> {
     context("atomic expression",
             alt(
                 (identifier.map(Expression::Identifier),
-                 literal.map(Expression::Literal))
+                 literal.map(Expression::Literal),
+                 parenthesized,
+                 array_literal,
+                 object_literal)
+            ),
+    )(i)
+}
+
+// comma-separated items, with an optional trailing comma for lists spanning several lines
+fn comma_separated<'a, O, F>(item: F) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<O>>
+    where F: FnMut(&'a str) -> ParseResult<'a, O> + Copy {
+    map(opt(terminated(
+        pair(item, many0(tuple((whitespace, tag(symbols::COMMA), whitespace, item)))),
+        opt(pair(whitespace, tag(symbols::COMMA))),
+    )), |parsed| {
+        let mut items = Vec::<O>::new();
+        if let Some((item0, items_remainder)) = parsed {
+            items.push(item0);
+            for (_, _, _, item) in items_remainder {
+                items.push(item);
+            }
+        }
+        items
+    })
+}
+
+pub(crate) fn array_literal(i: &str) -> ParseResult<'_, Expression> {
+    context("array literal",
+            delimited(
+                pair(tag(symbols::OPEN_SQUARE_BRACKETS), whitespace),
+                comma_separated(expression),
+                pair(whitespace, tag(symbols::CLOSE_SQUARE_BRACKETS)),
+            ).map(Expression::Array),
+    )(i)
+}
+
+pub(crate) fn object_literal(i: &str) -> ParseResult<'_, Expression> {
+    context("object literal",
+            delimited(
+                pair(tag(symbols::OPEN_BRACKETS), whitespace),
+                comma_separated(assignment),
+                pair(whitespace, tag(symbols::CLOSE_BRACKETS)),
+            ).map(Expression::Object),
+    )(i)
+}
+
+pub(crate) fn parenthesized(i: &str) -> ParseResult<'_, Expression> {
+    context("parenthesized expression",
+            delimited(
+                pair(tag(symbols::OPEN_PARENS), whitespace),
+                disjunction,
+                pair(whitespace, tag(symbols::CLOSE_PARENS)),
             ),
     )(i)
 }
 
+// what follows an expression to select from it, .member or [index]
+enum Selector {
+    Member(Identifier),
+    Index(Expression),
+}
+
 pub(crate) fn member_selection(i: &str) -> ParseResult<Expression> {
     context("member selection",
             pair(
                 atomic,
                 many0(
-                    tuple((whitespace, tag(symbols::DOT), whitespace, identifier))
+                    pair(whitespace, alt((
+                        tuple((tag(symbols::DOT), whitespace, identifier)).map(|parsed| {
+                            let (_, _, identifier) = parsed;
+                            Selector::Member(identifier)
+                        }),
+                        delimited(
+                            pair(tag(symbols::OPEN_SQUARE_BRACKETS), whitespace),
+                            disjunction,
+                            pair(whitespace, tag(symbols::CLOSE_SQUARE_BRACKETS)),
+                        ).map(Selector::Index)
+                    )))
                 ),
             ).map(|parsed| {
-                let (expr0, member_selection_parts) = parsed;
+                let (expr0, selectors) = parsed;
                 let mut expr: Expression = expr0;
-                for member_selection_part in member_selection_parts {
-                    let (_, _, _, identifier) = member_selection_part;
-                    expr = Expression::Member(Box::new(expr), identifier);
+                for (_, selector) in selectors {
+                    expr = match selector {
+                        Selector::Member(identifier) => {
+                            Expression::Member(Box::new(expr), identifier)
+                        }
+                        Selector::Index(index) => {
+                            Expression::Index(Box::new(expr), Box::new(index))
+                        }
+                    };
                 }
                 expr
             }),
@@ -183,10 +274,10 @@ pub(crate) fn comparison(i: &str) -> ParseResult<Expression> {
                 alt((
                     value(BinOp::Equal, tag(symbols::EQUAL)),
                     value(BinOp::NotEqual, tag(symbols::NOT_EQUAL)),
-                    value(BinOp::LessThan, tag(symbols::LESS_THAN)),
                     value(BinOp::LessOrEqual, tag(symbols::LESS_OR_EQUAL)),
-                    value(BinOp::GreaterThan, tag(symbols::GREATER_THAN)),
-                    value(BinOp::GreaterOrEqual, tag(symbols::GREATER_OR_EQUAL))
+                    value(BinOp::LessThan, tag(symbols::LESS_THAN)),
+                    value(BinOp::GreaterOrEqual, tag(symbols::GREATER_OR_EQUAL)),
+                    value(BinOp::GreaterThan, tag(symbols::GREATER_THAN))
                 )),
                 whitespace,
                 sum,
@@ -200,12 +291,12 @@ pub(crate) fn comparison(i: &str) -> ParseResult<Expression> {
 
 pub(crate) fn conjunction(i: &str) -> ParseResult<Expression> {
     context("conjunction",
-            pair(alt((comparison, function_call)),
+            pair(alt((comparison, sum)),
                  many0(tuple((
                      whitespace,
                      value(BinOp::And, tag(symbols::AND)),
                      whitespace,
-                     alt((comparison, function_call))
+                     alt((comparison, sum))
                  ))),
             ).map(build_bin_ops_chain),
     )(i)
@@ -260,8 +351,10 @@ pub(crate) fn assignment(i: &str) -> ParseResult<Assignment> {
 
 pub(crate) fn expression(i: &str) -> ParseResult<Expression> {
     alt((
+        function_definition.map(|definition| Expression::FunctionDefinition(Box::new(definition))),
         assignment.map(|assignment| Expression::Assignment(Box::new(assignment))),
         scatter.map(|scatter| Expression::Scatter(Box::new(scatter))),
+        conditional.map(|conditional| Expression::Conditional(Box::new(conditional))),
         block.map(Expression::Block),
         disjunction,
         sum,
@@ -286,6 +379,67 @@ pub(crate) fn scatter(i: &str) -> ParseResult<Scatter> {
     )(i)
 }
 
+pub(crate) fn conditional(i: &str) -> ParseResult<'_, Conditional> {
+    context("conditional",
+            tuple((
+                keyword(keywords::IF),
+                whitespace,
+                disjunction,
+                whitespace,
+                block,
+                opt(tuple((
+                    whitespace,
+                    keyword(keywords::ELSE),
+                    whitespace,
+                    alt((
+                        conditional.map(|conditional| {
+                            Expression::Conditional(Box::new(conditional))
+                        }),
+                        block.map(Expression::Block)
+                    ))
+                )))
+            )).map(|parsed| {
+                let (_, _, condition, _, then_block, else_part_opt) = parsed;
+                let else_opt = else_part_opt.map(|(_, _, _, else_expression)| { else_expression });
+                Conditional { condition, then_block, else_opt }
+            }),
+    )(i)
+}
+
+pub(crate) fn parameter(i: &str) -> ParseResult<'_, Parameter> {
+    context("parameter",
+            pair(
+                identifier,
+                opt(tuple((whitespace, tag(symbols::ASSIGN), whitespace, expression))),
+            ).map(|parsed| {
+                let (name, default_part_opt) = parsed;
+                let default_opt = default_part_opt.map(|(_, _, _, default)| { default });
+                Parameter { name, default_opt }
+            }),
+    )(i)
+}
+
+pub(crate) fn function_definition(i: &str) -> ParseResult<'_, FunctionDefinition> {
+    context("function definition",
+            tuple((
+                keyword(keywords::FN),
+                whitespace,
+                identifier,
+                whitespace,
+                delimited(
+                    pair(tag(symbols::OPEN_PARENS), whitespace),
+                    comma_separated(parameter),
+                    pair(whitespace, tag(symbols::CLOSE_PARENS)),
+                ),
+                whitespace,
+                block
+            )).map(|parsed| {
+                let (_, _, name, _, params, _, body) = parsed;
+                FunctionDefinition { name, params, body }
+            }),
+    )(i)
+}
+
 pub(crate) fn expressions(i: &str) -> ParseResult<Vec<Expression>> {
     println!("expressions: {}", i);
     context("expressions",
@@ -324,9 +478,86 @@ pub(crate) fn block(i: &str) -> ParseResult<Block> {
 }
 
 pub(crate) fn script(i: &str) -> ParseResult<Script> {
-    context("script", expressions.map(Script::new))(i)
+    context("script",
+            all_consuming(delimited(whitespace, expressions, whitespace)).map(Script::new),
+    )(i)
 }
 
 pub(crate) fn parse_script(string: &str) -> Result<Script, Error> {
     Ok(script(string.trim())?.1)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Error;
+    use crate::mion::syntax::parser::parse_script;
+
+    fn evaluate(script: &str) -> Result<String, Error> {
+        Ok(parse_script(script)?.compile(1)?.evaluate()?.to_string())
+    }
+
+    #[test]
+    fn evaluate_conditionals() -> Result<(), Error> {
+        assert_eq!(evaluate("x = if true { \"a\"; } else { \"b\"; }; x;")?, "a");
+        assert_eq!(evaluate("if false { 1; } else if true { 2; } else { 3; };")?, "2");
+        assert_eq!(evaluate("if false { 1; };")?, "unit");
+        assert_eq!(evaluate("iffy = true; if iffy { 1; };")?, "1");
+        assert!(evaluate("if 3 { 1; } else { 2; };").is_err());
+        assert!(evaluate("if = 3;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_binary_operations() -> Result<(), Error> {
+        assert_eq!(evaluate("1 + 2 * 3;")?, "7");
+        assert_eq!(evaluate("(1 + 2) * 3;")?, "9");
+        assert_eq!(evaluate("10 - 2 - 3;")?, "5");
+        assert_eq!(evaluate("7 / 2.0;")?, "3.5");
+        assert_eq!(evaluate("n = 3; // shards\nn * 2 >= 6 && n <= 3;")?, "true");
+        assert_eq!(evaluate("\"data/\" + \"chr1\";")?, "data/chr1");
+        assert_eq!(evaluate("false && 1 / 0 == 1;")?, "false");
+        assert!(evaluate("1 / 0;").is_err());
+        assert!(evaluate("1 + 2; 3 +").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_arrays_and_objects() -> Result<(), Error> {
+        assert_eq!(evaluate("xs = [\"chr1\", \"chr2\",\n]; xs[1];")?, "chr2");
+        assert_eq!(evaluate("[[1, 2], [3]][0][1];")?, "2");
+        assert_eq!(evaluate("o = { a = 1, b = { c = \"x\" } }; o[\"b\"].c;")?, "x");
+        assert_eq!(evaluate("[{ n = 2 }, { n = 3 }][1].n;")?, "3");
+        assert_eq!(evaluate("[];")?, "[]");
+        assert_eq!(evaluate("{ y = 2; y * 3; };")?, "6");
+        assert!(evaluate("[1][1];").is_err());
+        assert!(evaluate("{ a = 1 }[\"b\"];").is_err());
+        assert!(evaluate("{ a = 1, a = 2 };").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_function_definitions() -> Result<(), Error> {
+        assert_eq!(evaluate("fn add(a, b = 10) { a + b; }; add(a = 1) + add(a = 1, b = 2);")?,
+                   "14");
+        assert_eq!(evaluate("k = 100; fn f(x) { x + k; }; k = 1; f(x = 1);")?, "101");
+        assert_eq!(evaluate("fn make(n) { fn g(m = n) { m * 2; }; g; }; h = make(n = 4); h();")?,
+                   "8");
+        assert_eq!(evaluate("fn sq(v) { v * v; }; (i <- [1, 2]) { sq(v = i); };")?, "[1, 4]");
+        assert!(evaluate("fn f(x) { x; }; f();").is_err());
+        assert!(evaluate("fn f(x) { x; }; f(x = 1, y = 2);").is_err());
+        assert!(evaluate("fn f(x, x) { x; };").is_err());
+        assert!(evaluate("fn = 3;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_recursive_functions() -> Result<(), Error> {
+        assert_eq!(evaluate("fn fact(n) { if n <= 1 { 1; } else { n * fact(n = n - 1); }; }; \
+        fact(n = 5);")?, "120");
+        assert_eq!(evaluate("fn count(n) { if n == 0 { 0; } else { 1 + count(n = n - 1); }; }; \
+        (i <- [2, 3]) { count(n = i); };")?, "[2, 3]");
+        assert_eq!(evaluate("fn f(f) { f; }; f(f = 7);")?, "7");
+        assert!(evaluate("fn f() { g(); };").is_err());
+        Ok(())
+    }
+}
@@ -14,7 +14,7 @@ pub(crate) fn run_script(script_config: ScriptConfig) -> Result<(), Error> {
     println!("Begin parsed script");
     println!("{}", &script);
     println!("End parsed script");
-    let compiled = script.compile()?;
+    let compiled = script.compile(script_config.max_parallel)?;
     let optimized = compiled.optimize();
     let value = optimized.evaluate()?;
     println!("Begin final value");
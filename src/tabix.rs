@@ -1,2 +1,5 @@
 pub mod tabix_index;
 pub mod tsv;
+pub mod index_builder;
+pub mod bgzf;
+pub mod data_index;
@@ -0,0 +1,97 @@
+use std::io::{BufRead, Read, Seek, Write};
+use bgzip::BGZFReader;
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use crate::util::error::Error;
+
+pub(crate) const MAX_BLOCK_DATA_SIZE: usize = 0xff00;
+const MAX_BLOCK_SIZE: usize = 0x10000;
+const HEADER_SIZE: usize = 18;
+const FOOTER_SIZE: usize = 8;
+
+pub(crate) const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02,
+    0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+];
+
+fn deflate(data: &[u8], compression: Compression) -> Result<Vec<u8>, Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), compression);
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+pub(crate) fn compress_block(data: &[u8], compression: Compression) -> Result<Vec<u8>, Error> {
+    if data.len() > MAX_BLOCK_DATA_SIZE {
+        return Err(Error::from(format!("BGZF block data can be at most {} bytes, but got {}.",
+                                       MAX_BLOCK_DATA_SIZE, data.len())));
+    }
+    let mut deflated = deflate(data, compression)?;
+    if deflated.len() + HEADER_SIZE + FOOTER_SIZE > MAX_BLOCK_SIZE {
+        deflated = deflate(data, Compression::none())?;
+    }
+    let block_size = deflated.len() + HEADER_SIZE + FOOTER_SIZE;
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    let mut block = Vec::with_capacity(block_size);
+    block.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0]);
+    block.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+    block.extend_from_slice(&deflated);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(block)
+}
+
+// Unlike BufRead::read_line, never consumes zero bytes, because for BGZFReader, that would
+// skip past an empty block such as the EOF marker.
+pub(crate) fn read_line<R: Read + Seek>(bgzf: &mut BGZFReader<R>, line: &mut Vec<u8>)
+                                        -> Result<usize, Error> {
+    let mut n_bytes_total: usize = 0;
+    loop {
+        let buf = bgzf.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(n_bytes_total);
+        }
+        let (n_bytes, is_done) =
+            match buf.iter().position(|byte| { *byte == b'\n' }) {
+                Some(i_newline) => { (i_newline + 1, true) }
+                None => { (buf.len(), false) }
+            };
+        line.extend_from_slice(&buf[0..n_bytes]);
+        bgzf.consume(n_bytes);
+        n_bytes_total += n_bytes;
+        if is_done {
+            return Ok(n_bytes_total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor};
+    use bgzip::BGZFReader;
+    use flate2::Compression;
+    use crate::tabix::bgzf::{compress_block, EOF_BLOCK, MAX_BLOCK_DATA_SIZE};
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> =
+            (0..(3 * MAX_BLOCK_DATA_SIZE)).map(|i| { b"ACGT\t17\n"[i % 8] }).collect();
+        let mut bytes = Vec::new();
+        for chunk in data.chunks(MAX_BLOCK_DATA_SIZE) {
+            bytes.extend(compress_block(chunk, Compression::default()).unwrap());
+        }
+        bytes.extend_from_slice(&EOF_BLOCK);
+        let mut reader = BGZFReader::new(Cursor::new(bytes));
+        let mut data_read = Vec::new();
+        loop {
+            let buf = reader.fill_buf().unwrap();
+            if buf.is_empty() {
+                break;
+            }
+            let n_bytes = buf.len();
+            data_read.extend_from_slice(buf);
+            reader.consume(n_bytes);
+        }
+        assert_eq!(data_read, data);
+    }
+}
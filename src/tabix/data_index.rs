@@ -0,0 +1,225 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::io::Read;
+use fs_err::File;
+use flate2::read::MultiGzDecoder;
+use crate::util::error::Error;
+use crate::genomics::variant;
+use crate::genomics::chrom_aliases::ChromAliases;
+use crate::tabix::index_builder::IndexFormat;
+use crate::tabix::tabix_index::{Binning, IndexType};
+
+pub(crate) const TBI_MAGIC: &[u8; 4] = b"TBI\x01";
+pub(crate) const CSI_MAGIC: &[u8; 4] = b"CSI\x01";
+
+#[derive(Clone, Copy)]
+pub(crate) struct Chunk {
+    pub(crate) begin: u64,
+    pub(crate) end: u64,
+}
+
+pub(crate) struct Bin {
+    pub(crate) loffset: u64,
+    pub(crate) chunks: Vec<Chunk>,
+}
+
+pub(crate) struct SequenceIndex {
+    bins: HashMap<u32, Bin>,
+    intervals: Vec<u64>,
+    vpos_first_opt: Option<u64>,
+}
+
+pub(crate) struct DataIndex {
+    pub(crate) index_type: IndexType,
+    pub(crate) format: IndexFormat,
+    pub(crate) names: Vec<String>,
+    pub(crate) sequences: Vec<SequenceIndex>,
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    i_byte: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(bytes: &'a [u8]) -> Parser<'a> { Parser { bytes, i_byte: 0 } }
+    fn read_bytes(&mut self, n_bytes: usize) -> Result<&'a [u8], Error> {
+        let i_end = self.i_byte + n_bytes;
+        if i_end > self.bytes.len() {
+            return Err(Error::from("Unexpected end of index file."));
+        }
+        let bytes = &self.bytes[self.i_byte..i_end];
+        self.i_byte = i_end;
+        Ok(bytes)
+    }
+    fn read_i32(&mut self) -> Result<i32, Error> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(i32::from_le_bytes(bytes))
+    }
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+    fn read_count(&mut self) -> Result<usize, Error> {
+        let count = self.read_i32()?;
+        if count < 0 {
+            Err(Error::from(format!("Invalid negative count {} in index file.", count)))
+        } else {
+            Ok(count as usize)
+        }
+    }
+}
+
+fn parse_format_and_names(parser: &mut Parser) -> Result<(IndexFormat, Vec<String>), Error> {
+    let format = parser.read_i32()?;
+    let col_seq = parser.read_i32()?;
+    let col_begin = parser.read_i32()?;
+    let col_end = parser.read_i32()?;
+    let meta_char = parser.read_i32()? as u8;
+    let skip_lines = parser.read_i32()?;
+    let names_length = parser.read_count()?;
+    let names_bytes = parser.read_bytes(names_length)?;
+    let names =
+        names_bytes.split(|byte| { *byte == 0 }).filter(|name| { !name.is_empty() })
+            .map(|name| { String::from_utf8(name.to_vec()) })
+            .collect::<Result<Vec<String>, _>>()?;
+    let index_format =
+        IndexFormat::new(format, col_seq, col_begin, col_end, meta_char, skip_lines);
+    Ok((index_format, names))
+}
+
+fn parse_sequence(parser: &mut Parser, index_type: IndexType) -> Result<SequenceIndex, Error> {
+    let pseudo_bin = index_type.binning().pseudo_bin();
+    let n_bins = parser.read_count()?;
+    let mut bins = HashMap::<u32, Bin>::with_capacity(n_bins);
+    let mut vpos_first_opt: Option<u64> = None;
+    for _ in 0..n_bins {
+        let bin = parser.read_u32()?;
+        let loffset =
+            match index_type {
+                IndexType::Tbi => { 0 }
+                IndexType::Csi(_) => { parser.read_u64()? }
+            };
+        let n_chunks = parser.read_count()?;
+        let mut chunks = Vec::<Chunk>::with_capacity(n_chunks);
+        for _ in 0..n_chunks {
+            let begin = parser.read_u64()?;
+            let end = parser.read_u64()?;
+            chunks.push(Chunk { begin, end });
+        }
+        if bin != pseudo_bin {
+            for chunk in &chunks {
+                vpos_first_opt = Some(vpos_first_opt.map_or(chunk.begin, |vpos_first| {
+                    cmp::min(vpos_first, chunk.begin)
+                }));
+            }
+            bins.insert(bin, Bin { loffset, chunks });
+        }
+    }
+    let mut intervals = Vec::<u64>::new();
+    if let IndexType::Tbi = index_type {
+        let n_intervals = parser.read_count()?;
+        intervals.reserve(n_intervals);
+        for _ in 0..n_intervals {
+            intervals.push(parser.read_u64()?);
+        }
+    }
+    Ok(SequenceIndex { bins, intervals, vpos_first_opt })
+}
+
+impl DataIndex {
+    pub(crate) fn from_file(file: &str) -> Result<DataIndex, Error> {
+        let mut bytes = Vec::<u8>::new();
+        MultiGzDecoder::new(File::open(file)?).read_to_end(&mut bytes)?;
+        DataIndex::from_bytes(&bytes).map_err(|error| {
+            Error::from(format!("Could not read index file {}: {}", file, error))
+        })
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<DataIndex, Error> {
+        let mut parser = Parser::new(bytes);
+        let magic = parser.read_bytes(4)?;
+        let (index_type, format, names, n_sequences) =
+            if magic == TBI_MAGIC {
+                let n_sequences = parser.read_count()?;
+                let (format, names) = parse_format_and_names(&mut parser)?;
+                (IndexType::Tbi, format, names, n_sequences)
+            } else if magic == CSI_MAGIC {
+                let min_shift = parser.read_i32()?;
+                let depth = parser.read_i32()?;
+                let binning = Binning::new(min_shift as u32, depth as u32)?;
+                let aux_length = parser.read_count()?;
+                let mut aux_parser = Parser::new(parser.read_bytes(aux_length)?);
+                let (format, names) =
+                    parse_format_and_names(&mut aux_parser).map_err(|_| {
+                        Error::from("CSI index has no tabix columns and sequence names.")
+                    })?;
+                let n_sequences = parser.read_count()?;
+                (IndexType::Csi(binning), format, names, n_sequences)
+            } else {
+                return Err(Error::from("Not a TBI or CSI index."));
+            };
+        if names.len() != n_sequences {
+            return Err(Error::from(format!("Index has {} sequences, but {} names.",
+                                           n_sequences, names.len())));
+        }
+        let mut sequences = Vec::<SequenceIndex>::with_capacity(n_sequences);
+        for _ in 0..n_sequences {
+            sequences.push(parse_sequence(&mut parser, index_type)?);
+        }
+        Ok(DataIndex { index_type, format, names, sequences })
+    }
+    pub(crate) fn binning(&self) -> Binning { self.index_type.binning() }
+    pub(crate) fn i_sequence(&self, chrom: &str, chrom_aliases: &ChromAliases) -> Option<usize> {
+        let chrom = chrom_aliases.canonical(chrom);
+        self.names.iter().position(|name| { chrom_aliases.canonical(name) == chrom })
+    }
+    // smallest virtual offset of records that may overlap pos, None if there are none
+    pub(crate) fn min_vpos(&self, sequence: &SequenceIndex, pos: variant::Pos) -> Option<u64> {
+        let vpos_first = sequence.vpos_first_opt?;
+        let binning = self.binning();
+        let vpos_opt =
+            match self.index_type {
+                IndexType::Tbi => {
+                    sequence.intervals.get(binning.pos_to_i_interval(pos) as usize).cloned()
+                }
+                IndexType::Csi(_) => {
+                    binning.bins_for_min_vpos(pos).iter().find_map(|bin| {
+                        sequence.bins.get(bin)
+                    }).map(|bin| { bin.loffset }).or(Some(0))
+                }
+            };
+        vpos_opt.map(|vpos| { cmp::max(vpos, vpos_first) })
+    }
+    fn chunks<'a>(&self, sequence: &'a SequenceIndex, pos_begin: variant::Pos,
+                  pos_end: variant::Pos) -> impl Iterator<Item=&'a Chunk> {
+        self.binning().range_to_bins(pos_begin, pos_end).into_iter()
+            .flat_map(move |bin| { sequence.bins.get(&bin) })
+            .flat_map(|bin| { &bin.chunks })
+    }
+    // sorted virtual offsets to start reading from for records overlapping the range
+    pub(crate) fn seek_vposes(&self, sequence: &SequenceIndex, pos_begin: variant::Pos,
+                              pos_end: variant::Pos) -> Vec<u64> {
+        let vpos_min = match self.min_vpos(sequence, pos_begin) {
+            None => { return Vec::new() }
+            Some(vpos_min) => { vpos_min }
+        };
+        let mut vposes: Vec<u64> =
+            self.chunks(sequence, pos_begin, pos_end).filter_map(|chunk| {
+                if chunk.end <= vpos_min {
+                    None
+                } else {
+                    Some(cmp::max(chunk.begin, vpos_min))
+                }
+            }).collect();
+        vposes.sort_unstable();
+        vposes.dedup();
+        vposes
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use fs_err::File;
+use bgzip::BGZFWriter;
+use crate::util::error::Error;
+use crate::tabix::data_index::{Chunk, CSI_MAGIC, TBI_MAGIC};
+use crate::tabix::tabix_index::{Binning, IndexType};
+
+pub(crate) const FORMAT_GENERIC: i32 = 0;
+pub(crate) const FORMAT_VCF: i32 = 2;
+pub(crate) const FLAG_ZERO_BASED: i32 = 0x10000;
+const VCF_COL_REF: i32 = 4;
+const VCF_COL_INFO: i32 = 8;
+const VCF_END_KEY: &str = "END=";
+
+mod presets {
+    pub(crate) const VCF: &str = "vcf";
+    pub(crate) const BED: &str = "bed";
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct IndexFormat {
+    pub(crate) format: i32,
+    pub(crate) col_seq: i32,
+    pub(crate) col_begin: i32,
+    pub(crate) col_end: i32,
+    pub(crate) meta_char: u8,
+    pub(crate) skip_lines: i32,
+}
+
+struct SequenceIndex {
+    bins: BTreeMap<u32, Vec<Chunk>>,
+    intervals: Vec<Option<u64>>,
+    vpos_first: u64,
+    vpos_last: u64,
+    n_records: u64,
+}
+
+struct OpenChunk {
+    bin: u32,
+    chunk: Chunk,
+}
+
+pub(crate) struct IndexBuilder {
+    format: IndexFormat,
+    index_type: IndexType,
+    binning: Binning,
+    names: Vec<String>,
+    names_seen: HashSet<String>,
+    sequences: Vec<SequenceIndex>,
+    open_chunk_opt: Option<OpenChunk>,
+    beg_last: u64,
+}
+
+impl IndexFormat {
+    pub(crate) fn new(format: i32, col_seq: i32, col_begin: i32, col_end: i32, meta_char: u8,
+                      skip_lines: i32) -> IndexFormat {
+        IndexFormat { format, col_seq, col_begin, col_end, meta_char, skip_lines }
+    }
+    pub(crate) fn for_preset(preset_opt: Option<&str>) -> Result<IndexFormat, Error> {
+        match preset_opt {
+            None => { Ok(IndexFormat::new(FORMAT_GENERIC, 1, 2, 0, b'#', 0)) }
+            Some(presets::VCF) => { Ok(IndexFormat::new(FORMAT_VCF, 1, 2, 0, b'#', 0)) }
+            Some(presets::BED) => {
+                Ok(IndexFormat::new(FORMAT_GENERIC | FLAG_ZERO_BASED, 1, 2, 3, b'#', 0))
+            }
+            Some(preset) => {
+                Err(Error::from(format!("Unknown preset '{}', need {} or {}.", preset,
+                                        presets::VCF, presets::BED)))
+            }
+        }
+    }
+    pub(crate) fn is_vcf(&self) -> bool { self.format & 0xffff == FORMAT_VCF }
+    pub(crate) fn is_zero_based(&self) -> bool { self.format & FLAG_ZERO_BASED != 0 }
+    // sequence name and range of a data line, zero-based, end exclusive
+    pub(crate) fn parse_record<'a>(&self, line: &'a str) -> Result<(&'a str, u64, u64), Error> {
+        let line = line.trim_end_matches(&['\n', '\r'][..]);
+        let fields: Vec<&str> = line.split('\t').collect();
+        let seq = get_field(&fields, self.col_seq)?;
+        let begin = get_field(&fields, self.col_begin)?.parse::<u64>()?;
+        let beg = if self.is_zero_based() { begin } else { begin.saturating_sub(1) };
+        let end =
+            if self.is_vcf() {
+                let end_ref = beg + get_field(&fields, VCF_COL_REF)?.len() as u64;
+                match get_field(&fields, VCF_COL_INFO).ok().and_then(info_end) {
+                    Some(end_info) if end_info > beg => { end_info }
+                    _ => { end_ref }
+                }
+            } else if self.col_end > 0 {
+                get_field(&fields, self.col_end)?.parse::<u64>()?
+            } else {
+                beg + 1
+            };
+        Ok((seq, beg, end))
+    }
+}
+
+// the INFO key END, like for symbolic SVs, is one-based and inclusive, which is the same value
+// as zero-based and exclusive
+fn info_end(info: &str) -> Option<u64> {
+    info.split(';').find_map(|entry| { entry.strip_prefix(VCF_END_KEY) })
+        .and_then(|end| { end.parse::<u64>().ok() })
+}
+
+fn get_field<'a>(fields: &[&'a str], col: i32) -> Result<&'a str, Error> {
+    let field_opt = if col > 0 { fields.get((col - 1) as usize) } else { None };
+    field_opt.cloned().ok_or_else(|| { Error::from(format!("No column {}.", col)) })
+}
+
+impl SequenceIndex {
+    fn new(vpos_first: u64) -> SequenceIndex {
+        SequenceIndex {
+            bins: BTreeMap::new(),
+            intervals: Vec::new(),
+            vpos_first,
+            vpos_last: vpos_first,
+            n_records: 0,
+        }
+    }
+    fn add_chunk(&mut self, bin: u32, chunk: Chunk) {
+        let chunks = self.bins.entry(bin).or_default();
+        match chunks.last_mut() {
+            Some(chunk_last) if chunk_last.end >> 16 == chunk.begin >> 16 => {
+                chunk_last.end = chunk.end;
+            }
+            _ => { chunks.push(chunk) }
+        }
+    }
+    fn add_to_intervals(&mut self, beg: u64, end: u64, vpos: u64, min_shift: u32) {
+        let i_first = (beg >> min_shift) as usize;
+        let i_last = ((end - 1) >> min_shift) as usize;
+        if self.intervals.len() <= i_last {
+            self.intervals.resize(i_last + 1, None);
+        }
+        for interval in &mut self.intervals[i_first..=i_last] {
+            if interval.is_none() {
+                *interval = Some(vpos);
+            }
+        }
+    }
+    fn filled_intervals(&self) -> Vec<u64> {
+        let mut vpos_previous = self.vpos_first;
+        self.intervals.iter().map(|interval| {
+            if let Some(vpos) = interval {
+                vpos_previous = *vpos;
+            }
+            vpos_previous
+        }).collect()
+    }
+}
+
+fn write_i32(write: &mut impl Write, value: i32) -> Result<(), Error> {
+    write.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u32(write: &mut impl Write, value: u32) -> Result<(), Error> {
+    write.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u64(write: &mut impl Write, value: u64) -> Result<(), Error> {
+    write.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+impl IndexBuilder {
+    pub(crate) fn new(format: IndexFormat, index_type: IndexType) -> IndexBuilder {
+        IndexBuilder {
+            format,
+            index_type,
+            binning: index_type.binning(),
+            names: Vec::new(),
+            names_seen: HashSet::new(),
+            sequences: Vec::new(),
+            open_chunk_opt: None,
+            beg_last: 0,
+        }
+    }
+    fn close_chunk(&mut self) {
+        if let Some(open_chunk) = self.open_chunk_opt.take() {
+            if let Some(sequence) = self.sequences.last_mut() {
+                sequence.add_chunk(open_chunk.bin, open_chunk.chunk);
+            }
+        }
+    }
+    // beg and end are zero-based, end exclusive
+    pub(crate) fn add_record(&mut self, seq: &str, beg: u64, end: u64, vpos_beg: u64,
+                             vpos_end: u64) -> Result<(), Error> {
+        let end = if end > beg { end } else { beg + 1 };
+        if end > self.binning.max_pos() {
+            return Err(Error::from(format!(
+                "Record at {}:{} is beyond the maximum position {} of the index; use CSI with \
+                larger depth.", seq, beg + 1, self.binning.max_pos()
+            )));
+        }
+        if self.names.last().map(|name| name.as_str()) != Some(seq) {
+            if !self.names_seen.insert(seq.to_string()) {
+                return Err(Error::from(format!(
+                    "Records for sequence {} are not contiguous - data file is not sorted.", seq
+                )));
+            }
+            self.close_chunk();
+            self.names.push(seq.to_string());
+            self.sequences.push(SequenceIndex::new(vpos_beg));
+        } else if beg < self.beg_last {
+            return Err(Error::from(format!(
+                "Record at {}:{} is out of order - data file is not sorted.", seq, beg + 1
+            )));
+        }
+        self.beg_last = beg;
+        let bin = self.binning.reg2bin(beg, end);
+        match &mut self.open_chunk_opt {
+            Some(open_chunk) if open_chunk.bin == bin => { open_chunk.chunk.end = vpos_end; }
+            _ => {
+                self.close_chunk();
+                let chunk = Chunk { begin: vpos_beg, end: vpos_end };
+                self.open_chunk_opt = Some(OpenChunk { bin, chunk });
+            }
+        }
+        if let Some(sequence) = self.sequences.last_mut() {
+            sequence.add_to_intervals(beg, end, vpos_beg, self.binning.min_shift);
+            sequence.vpos_last = vpos_end;
+            sequence.n_records += 1;
+        }
+        Ok(())
+    }
+    fn write_format_and_names(&self, write: &mut impl Write) -> Result<(), Error> {
+        write_i32(write, self.format.format)?;
+        write_i32(write, self.format.col_seq)?;
+        write_i32(write, self.format.col_begin)?;
+        write_i32(write, self.format.col_end)?;
+        write_i32(write, self.format.meta_char as i32)?;
+        write_i32(write, self.format.skip_lines)?;
+        let names_length: usize = self.names.iter().map(|name| name.len() + 1).sum();
+        write_i32(write, names_length as i32)?;
+        for name in &self.names {
+            write.write_all(name.as_bytes())?;
+            write.write_all(&[0])?;
+        }
+        Ok(())
+    }
+    fn write_sequence(&self, write: &mut impl Write, sequence: &SequenceIndex)
+                      -> Result<(), Error> {
+        let is_csi = matches!(self.index_type, IndexType::Csi(_));
+        let intervals = sequence.filled_intervals();
+        write_i32(write, (sequence.bins.len() + 1) as i32)?;
+        for (bin, chunks) in &sequence.bins {
+            write_u32(write, *bin)?;
+            if is_csi {
+                let i_interval = self.binning.bin_bottom(*bin) as usize;
+                write_u64(write, intervals.get(i_interval).cloned().unwrap_or(0))?;
+            }
+            write_i32(write, chunks.len() as i32)?;
+            for chunk in chunks {
+                write_u64(write, chunk.begin)?;
+                write_u64(write, chunk.end)?;
+            }
+        }
+        write_u32(write, self.binning.pseudo_bin())?;
+        if is_csi {
+            write_u64(write, 0)?;
+        }
+        write_i32(write, 2)?;
+        write_u64(write, sequence.vpos_first)?;
+        write_u64(write, sequence.vpos_last)?;
+        write_u64(write, sequence.n_records)?;
+        write_u64(write, 0)?;
+        if !is_csi {
+            write_i32(write, intervals.len() as i32)?;
+            for vpos in intervals {
+                write_u64(write, vpos)?;
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn write(mut self, index_file: &str) -> Result<(), Error> {
+        self.close_chunk();
+        let mut write =
+            BGZFWriter::new(File::create(index_file)?, flate2::Compression::default());
+        match self.index_type {
+            IndexType::Tbi => {
+                write.write_all(TBI_MAGIC)?;
+                write_i32(&mut write, self.names.len() as i32)?;
+                self.write_format_and_names(&mut write)?;
+            }
+            IndexType::Csi(binning) => {
+                let mut aux = Vec::<u8>::new();
+                self.write_format_and_names(&mut aux)?;
+                write.write_all(CSI_MAGIC)?;
+                write_i32(&mut write, binning.min_shift as i32)?;
+                write_i32(&mut write, binning.depth as i32)?;
+                write_i32(&mut write, aux.len() as i32)?;
+                write.write_all(&aux)?;
+                write_i32(&mut write, self.names.len() as i32)?;
+            }
+        }
+        for sequence in &self.sequences {
+            self.write_sequence(&mut write, sequence)?;
+        }
+        write_u64(&mut write, 0)?;
+        write.close()?;
+        Ok(())
+    }
+}
@@ -1,22 +1,139 @@
+use std::cmp;
 use crate::genomics::variant;
-use crate::genomics::variant::Variant;
+use crate::util::error::Error;
 
-const INTERVAL_SIZE: variant::Pos = 1 << 14;  //  16,384
+const TBI_MIN_SHIFT: u32 = 14;  //  16,384
+const TBI_DEPTH: u32 = 5;
+pub(crate) const CSI_DEFAULT_MIN_SHIFT: u32 = 14;
+pub(crate) const CSI_DEFAULT_DEPTH: u32 = 6;  //  2^32, enough for any position
+const MAX_SHIFT: u32 = 63;
+const MAX_DEPTH: u32 = 10;
 
-pub(crate) fn pos_to_i_interval(pos: variant::Pos) -> u32 { pos / INTERVAL_SIZE }
+#[derive(Clone, Copy)]
+pub(crate) struct Binning {
+    pub(crate) min_shift: u32,
+    pub(crate) depth: u32,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum IndexType {
+    Tbi,
+    Csi(Binning),
+}
+
+impl Binning {
+    pub(crate) const TBI: Binning = Binning { min_shift: TBI_MIN_SHIFT, depth: TBI_DEPTH };
+    pub(crate) fn new(min_shift: u32, depth: u32) -> Result<Binning, Error> {
+        if min_shift == 0 || depth > MAX_DEPTH || min_shift + 3 * depth > MAX_SHIFT {
+            Err(Error::from(format!("Invalid binning with min shift {} and depth {}.",
+                                    min_shift, depth)))
+        } else {
+            Ok(Binning { min_shift, depth })
+        }
+    }
+    pub(crate) fn max_pos(&self) -> u64 { 1u64 << (self.min_shift + 3 * self.depth) }
+    fn level_shift(&self, level: u32) -> u32 { self.min_shift + 3 * (self.depth - level) }
+    fn bin_first(level: u32) -> u32 { (((1u64 << (3 * level)) - 1) / 7) as u32 }
+    fn bin_parent(bin: u32) -> u32 { (bin - 1) >> 3 }
+    fn bin_level(bin: u32) -> u32 {
+        let mut level: u32 = 0;
+        let mut bin_ancestor = bin;
+        while bin_ancestor > 0 {
+            level += 1;
+            bin_ancestor = Binning::bin_parent(bin_ancestor);
+        }
+        level
+    }
+    pub(crate) fn n_bins(&self) -> u32 { Binning::bin_first(self.depth + 1) }
+    pub(crate) fn pseudo_bin(&self) -> u32 { self.n_bins() + 1 }
+    // index of the first linear interval of the bin
+    pub(crate) fn bin_bottom(&self, bin: u32) -> u64 {
+        let level = Binning::bin_level(bin);
+        ((bin - Binning::bin_first(level)) as u64) << (3 * (self.depth - level))
+    }
+    pub(crate) fn pos_to_i_interval(&self, pos: variant::Pos) -> u64 {
+        (pos.saturating_sub(1) as u64) >> self.min_shift
+    }
+    // ported from https://samtools.github.io/hts-specs/tabix.pdf and CSIv1.pdf
+    pub(crate) fn range_to_bins(&self, pos_begin: variant::Pos, pos_end: variant::Pos)
+                                -> Vec<u32> {
+        let rbeg = pos_begin.saturating_sub(1) as u64;
+        let rend = cmp::min(pos_end.saturating_sub(1) as u64, self.max_pos() - 1);
+        let reasonable_initial_capacity: usize = 8;
+        let mut bins = Vec::<u32>::with_capacity(reasonable_initial_capacity);
+        for level in 0..=self.depth {
+            let shift = self.level_shift(level);
+            let first = Binning::bin_first(level) as u64;
+            for k in (first + (rbeg >> shift))..=(first + (rend >> shift)) {
+                bins.push(k as u32)
+            }
+        }
+        bins
+    }
+    // beg and end are zero-based, end exclusive
+    pub(crate) fn reg2bin(&self, beg: u64, end: u64) -> u32 {
+        let end = end - 1;
+        for level in (1..=self.depth).rev() {
+            let shift = self.level_shift(level);
+            if beg >> shift == end >> shift {
+                return Binning::bin_first(level) + (beg >> shift) as u32;
+            }
+        }
+        0
+    }
+    // bins to try, in order, for the smallest virtual offset of records overlapping pos
+    pub(crate) fn bins_for_min_vpos(&self, pos: variant::Pos) -> Vec<u32> {
+        let mut bins = Vec::<u32>::new();
+        let i_interval =
+            cmp::min(self.pos_to_i_interval(pos), (self.max_pos() - 1) >> self.min_shift);
+        let mut bin = Binning::bin_first(self.depth) + (i_interval as u32);
+        loop {
+            bins.push(bin);
+            if bin == 0 {
+                break;
+            }
+            let first_sibling = (Binning::bin_parent(bin) << 3) + 1;
+            bin = if bin > first_sibling { bin - 1 } else { Binning::bin_parent(bin) };
+        }
+        bins
+    }
+}
+
+impl IndexType {
+    pub(crate) fn binning(&self) -> Binning {
+        match self {
+            IndexType::Tbi => { Binning::TBI }
+            IndexType::Csi(binning) => { *binning }
+        }
+    }
+    pub(crate) fn file_extension(&self) -> &'static str {
+        match self {
+            IndexType::Tbi => { ".tbi" }
+            IndexType::Csi(_) => { ".csi" }
+        }
+    }
+}
 
-// ported from https://samtools.github.io/hts-specs/tabix.pdf
+#[cfg(test)]
+mod tests {
+    use crate::tabix::tabix_index::Binning;
 
-pub(crate) fn variant_to_bins(variant: &Variant) -> Vec<u32> {
-    let rbeg = variant.pos;
-    let rend = variant.end() - 1;
-    let reasonable_initial_capacity: usize = 8;
-    let mut bins = Vec::<u32>::with_capacity(reasonable_initial_capacity);
-    bins.push(0);
-    for k in (1 + (rbeg >> 26))..=(1 + (rend >> 26)) { bins.push(k) }
-    for k in (9 + (rbeg >> 23))..=(9 + (rend >> 23)) { bins.push(k) }
-    for k in (73 + (rbeg >> 20))..=(73 + (rend >> 20)) { bins.push(k) }
-    for k in (585 + (rbeg >> 17))..=(585 + (rend >> 17)) { bins.push(k) }
-    for k in (4681 + (rbeg >> 14))..=(4681 + (rend >> 14)) { bins.push(k) }
-    bins
+    #[test]
+    fn bins_of_tbi_and_csi() {
+        let tbi = Binning::TBI;
+        assert_eq!(tbi.pseudo_bin(), 37450);
+        assert_eq!(tbi.reg2bin(0, 1), 4681);
+        assert_eq!(tbi.reg2bin(16383, 16385), 585);
+        assert_eq!(tbi.reg2bin(0, 1 << 29), 0);
+        assert_eq!(tbi.range_to_bins(1, 1), vec!(0, 1, 9, 73, 585, 4681));
+        let csi = Binning::new(14, 6).unwrap();
+        assert_eq!(csi.pseudo_bin(), 299594);
+        let ranges: [(u64, u64); 3] =
+            [(0, 1), (1 << 29, (1 << 29) + 100), (4_000_000_000, 4_000_016_384)];
+        for (beg, end) in ranges {
+            let bin = csi.reg2bin(beg, end);
+            assert!(csi.range_to_bins((beg + 1) as u32, end as u32).contains(&bin));
+            assert!(csi.bin_bottom(bin) <= beg >> 14);
+        }
+    }
 }
@@ -55,5 +55,96 @@ pub(crate) fn get_header_line<R: BufRead>(read: &mut R) -> Result<String, Error>
     }
 }
 
+// Data file columns to write, in order, each with its index and output name
+pub(crate) struct ColSelection {
+    pub(crate) cols: Vec<(usize, String)>,
+    is_commented: bool,
+}
+
+fn find_col(col_names: &[&str], col_name: &str) -> Result<usize, Error> {
+    col_names.iter().position(|name| { *name == col_name }).ok_or_else(|| {
+        Error::from(format!("No column {} in header line of data file.", col_name))
+    })
+}
+
+impl ColSelection {
+    // columns are name or name:new_name, and all columns are selected if there are none
+    pub(crate) fn parse(header_line: &str, columns: &[String], exclude_columns: &[String])
+                        -> Result<ColSelection, Error> {
+        let col_names: Vec<&str> =
+            header_line.strip_prefix('#').unwrap_or(header_line).split('\t').collect();
+        let mut cols = Vec::<(usize, String)>::new();
+        if columns.is_empty() {
+            for (i_col, col_name) in col_names.iter().enumerate() {
+                cols.push((i_col, col_name.to_string()));
+            }
+        } else {
+            for column in columns {
+                let (col_name, new_name) = column.split_once(':').unwrap_or((column, column));
+                cols.push((find_col(&col_names, col_name)?, new_name.to_string()));
+            }
+        }
+        for exclude_column in exclude_columns {
+            let i_col = find_col(&col_names, exclude_column)?;
+            if !columns.is_empty() && cols.iter().any(|(i, _)| { *i == i_col }) {
+                return Err(Error::from(format!("Column {} is both selected and excluded.",
+                                               exclude_column)));
+            }
+            cols.retain(|(i, _)| { *i != i_col });
+        }
+        if cols.is_empty() {
+            return Err(Error::from("No columns of data file selected."));
+        }
+        for (i, (_, name)) in cols.iter().enumerate() {
+            if cols[..i].iter().any(|(_, name_previous)| { name_previous == name }) {
+                return Err(Error::from(format!("Column name {} is used more than once.", name)));
+            }
+        }
+        let is_commented = header_line.starts_with('#');
+        Ok(ColSelection { cols, is_commented })
+    }
+    pub(crate) fn header_line(&self) -> String {
+        let names: Vec<&str> = self.cols.iter().map(|(_, name)| { name.as_str() }).collect();
+        let prefix = if self.is_commented { "#" } else { "" };
+        format!("{}{}", prefix, names.join("\t"))
+    }
+    pub(crate) fn select_line(&self, line: &str) -> String {
+        let fields: Vec<&str> = line.trim_end_matches(&['\n', '\r'][..]).split('\t').collect();
+        let selected: Vec<&str> =
+            self.cols.iter().map(|(i_col, _)| { fields.get(*i_col).cloned().unwrap_or("") })
+                .collect();
+        selected.join("\t")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tabix::tsv::ColSelection;
+    use crate::util::error::Error;
 
+    const HEADER_LINE: &str = "#Chrom\tPos\tRef\tAlt\tGene\tScore";
 
+    fn strings(strs: &[&str]) -> Vec<String> { strs.iter().map(|s| { s.to_string() }).collect() }
+
+    #[test]
+    fn select_and_rename_columns() -> Result<(), Error> {
+        let columns = strings(&["Score:score", "Gene"]);
+        let col_selection = ColSelection::parse(HEADER_LINE, &columns, &[])?;
+        assert_eq!(col_selection.header_line(), "#score\tGene");
+        assert_eq!(col_selection.select_line("1\t13\tG\tT\tG2\t0.5\n"), "0.5\tG2");
+        let col_selection = ColSelection::parse(HEADER_LINE, &[], &strings(&["Ref", "Alt"]))?;
+        assert_eq!(col_selection.header_line(), "#Chrom\tPos\tGene\tScore");
+        assert!(ColSelection::parse(HEADER_LINE, &strings(&["Foo"]), &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ambiguous_selections() {
+        assert!(ColSelection::parse(HEADER_LINE, &strings(&["Gene:x", "Score:x"]), &[]).is_err());
+        assert!(ColSelection::parse(HEADER_LINE, &strings(&["Gene", "Score:Gene"]), &[]).is_err());
+        assert!(ColSelection::parse(HEADER_LINE, &strings(&["Gene", "Score"]), &strings(&["Gene"]))
+            .is_err());
+        assert!(ColSelection::parse(HEADER_LINE, &strings(&["Gene"]), &strings(&["Score"]))
+            .is_ok());
+    }
+}
@@ -2,4 +2,7 @@ pub(crate) mod vep;
 pub(crate) mod vep_output_transform;
 pub(crate) mod file_join;
 pub(crate) mod file_sorted_merge;
-pub(crate) mod id_adapt;
\ No newline at end of file
+pub(crate) mod id_adapt;
+pub(crate) mod index;
+pub(crate) mod bgzip;
+pub(crate) mod query;
@@ -0,0 +1,149 @@
+use std::io::{BufWriter, Read, Write};
+use std::thread;
+use fs_err::File;
+use flate2::Compression;
+use crate::util::error::Error;
+use crate::config::BgzipConfig;
+use crate::tabix::bgzf;
+use crate::tools::index::LineIndexer;
+
+const STDIN_FILE: &str = "-";
+const BLOCKS_PER_THREAD: usize = 16;
+
+struct BlockIndexer {
+    line_indexer: LineIndexer,
+    line: Vec<u8>,
+    vpos_line_beg: u64,
+}
+
+impl BlockIndexer {
+    fn new(line_indexer: LineIndexer) -> BlockIndexer {
+        BlockIndexer { line_indexer, line: Vec::new(), vpos_line_beg: 0 }
+    }
+    fn add_line(&mut self, vpos_end: u64) -> Result<(), Error> {
+        let line = std::str::from_utf8(&self.line)?;
+        self.line_indexer.add_line(line, self.vpos_line_beg, vpos_end)?;
+        self.line.clear();
+        Ok(())
+    }
+    fn add_block(&mut self, data: &[u8], coffset: u64, coffset_next: u64)
+                 -> Result<(), Error> {
+        let mut i_beg: usize = 0;
+        while i_beg < data.len() {
+            if self.line.is_empty() {
+                self.vpos_line_beg = (coffset << 16) | (i_beg as u64);
+            }
+            match data[i_beg..].iter().position(|byte| { *byte == b'\n' }) {
+                Some(i_newline) => {
+                    let i_end = i_beg + i_newline + 1;
+                    self.line.extend_from_slice(&data[i_beg..i_end]);
+                    let vpos_end =
+                        if i_end == data.len() {
+                            coffset_next << 16
+                        } else {
+                            (coffset << 16) | (i_end as u64)
+                        };
+                    self.add_line(vpos_end)?;
+                    i_beg = i_end;
+                }
+                None => {
+                    self.line.extend_from_slice(&data[i_beg..]);
+                    i_beg = data.len();
+                }
+            }
+        }
+        Ok(())
+    }
+    fn finish(mut self, coffset_eof: u64) -> Result<LineIndexer, Error> {
+        if !self.line.is_empty() {
+            self.add_line(coffset_eof << 16)?;
+        }
+        Ok(self.line_indexer)
+    }
+}
+
+fn read_block_data(read: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let mut data = vec![0u8; bgzf::MAX_BLOCK_DATA_SIZE];
+    let mut n_bytes: usize = 0;
+    while n_bytes < data.len() {
+        let n_bytes_read = read.read(&mut data[n_bytes..])?;
+        if n_bytes_read == 0 {
+            break;
+        }
+        n_bytes += n_bytes_read;
+    }
+    data.truncate(n_bytes);
+    Ok(data)
+}
+
+fn read_batch(read: &mut impl Read, n_blocks: usize) -> Result<Vec<Vec<u8>>, Error> {
+    let mut batch = Vec::with_capacity(n_blocks);
+    while batch.len() < n_blocks {
+        let data = read_block_data(read)?;
+        if data.is_empty() {
+            break;
+        }
+        batch.push(data);
+    }
+    Ok(batch)
+}
+
+fn compress_chunk(chunk: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, Error> {
+    chunk.iter().map(|data| { bgzf::compress_block(data, Compression::default()) }).collect()
+}
+
+fn compress_batch(batch: &[Vec<u8>], threads: usize) -> Result<Vec<Vec<u8>>, Error> {
+    if threads == 1 {
+        return compress_chunk(batch);
+    }
+    let chunk_size = batch.len().div_ceil(threads);
+    let results: Vec<Result<Vec<Vec<u8>>, Error>> = thread::scope(|scope| {
+        let children: Vec<_> =
+            batch.chunks(chunk_size).map(|chunk| {
+                scope.spawn(move || { compress_chunk(chunk) })
+            }).collect();
+        children.into_iter().map(|child| { child.join()? }).collect()
+    });
+    let mut blocks = Vec::with_capacity(batch.len());
+    for result in results {
+        blocks.extend(result?);
+    }
+    Ok(blocks)
+}
+
+pub(crate) fn compress(config: &BgzipConfig) -> Result<(), Error> {
+    let mut read: Box<dyn Read> =
+        if config.input_file == STDIN_FILE {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(File::open(&config.input_file)?)
+        };
+    let mut write = BufWriter::new(File::create(&config.output_file)?);
+    let mut block_indexer_opt =
+        config.index_file_opt.as_ref().map(|_| {
+            BlockIndexer::new(LineIndexer::new(config.index_format, config.index_type))
+        });
+    let mut coffset: u64 = 0;
+    loop {
+        let batch = read_batch(&mut read, config.threads * BLOCKS_PER_THREAD)?;
+        if batch.is_empty() {
+            break;
+        }
+        let blocks = compress_batch(&batch, config.threads)?;
+        for (data, block) in batch.iter().zip(blocks.iter()) {
+            write.write_all(block)?;
+            let coffset_next = coffset + block.len() as u64;
+            if let Some(block_indexer) = &mut block_indexer_opt {
+                block_indexer.add_block(data, coffset, coffset_next)?;
+            }
+            coffset = coffset_next;
+        }
+    }
+    write.write_all(&bgzf::EOF_BLOCK)?;
+    write.flush()?;
+    if let (Some(block_indexer), Some(index_file)) =
+        (block_indexer_opt, &config.index_file_opt) {
+        block_indexer.finish(coffset)?.write(index_file)?;
+    }
+    Ok(())
+}
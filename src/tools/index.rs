@@ -0,0 +1,122 @@
+use fs_err::File;
+use bgzip::BGZFReader;
+use crate::util::error::Error;
+use crate::config::IndexConfig;
+use crate::tabix::index_builder::{IndexBuilder, IndexFormat};
+use crate::tabix::tabix_index::IndexType;
+use crate::tabix::bgzf;
+
+pub(crate) struct LineIndexer {
+    format: IndexFormat,
+    index_builder: IndexBuilder,
+    i_line: usize,
+}
+
+impl LineIndexer {
+    pub(crate) fn new(format: IndexFormat, index_type: IndexType) -> LineIndexer {
+        let index_builder = IndexBuilder::new(format, index_type);
+        LineIndexer { format, index_builder, i_line: 0 }
+    }
+    pub(crate) fn add_line(&mut self, line: &str, vpos_beg: u64, vpos_end: u64)
+                           -> Result<(), Error> {
+        self.i_line += 1;
+        if self.i_line <= (self.format.skip_lines as usize) ||
+            line.as_bytes().first() == Some(&self.format.meta_char) {
+            return Ok(());
+        }
+        let (seq, beg, end) =
+            self.format.parse_record(line).map_err(|error| {
+                Error::from(format!("Line {}: {}", self.i_line, error))
+            })?;
+        self.index_builder.add_record(seq, beg, end, vpos_beg, vpos_end)
+    }
+    pub(crate) fn write(self, index_file: &str) -> Result<(), Error> {
+        self.index_builder.write(index_file)
+    }
+}
+
+pub(crate) fn build_index(config: &IndexConfig) -> Result<(), Error> {
+    let mut bgzf = BGZFReader::new(File::open(&config.data_file)?);
+    let mut line_buf = Vec::<u8>::new();
+    let mut line_indexer = LineIndexer::new(config.index_format, config.index_type);
+    loop {
+        let vpos_beg = bgzf.bgzf_pos();
+        line_buf.clear();
+        if bgzf::read_line(&mut bgzf, &mut line_buf)? == 0 {
+            break;
+        }
+        line_indexer.add_line(std::str::from_utf8(&line_buf)?, vpos_beg, bgzf.bgzf_pos())?;
+    }
+    line_indexer.write(&config.index_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Write};
+    use bgzip::BGZFReader;
+    use flate2::Compression;
+    use fs_err::File;
+    use crate::config::IndexConfig;
+    use crate::tabix::bgzf;
+    use crate::tabix::data_index::DataIndex;
+    use crate::tabix::index_builder::IndexFormat;
+    use crate::tabix::tabix_index::{Binning, IndexType};
+    use crate::tools::index::build_index;
+    use crate::util::error::Error;
+
+    // one block per chromosome, and no newline at the end of the last line
+    fn write_data_file(file: &str) -> Result<(), Error> {
+        let mut write = File::create(file)?;
+        let blocks: [&[u8]; 2] =
+            [b"#chrom\tpos\n1\t100\n1\t20000\n", b"2\t300\n2\t300\n2\t40000"];
+        for data in blocks {
+            write.write_all(&bgzf::compress_block(data, Compression::default())?)?;
+        }
+        write.write_all(&bgzf::EOF_BLOCK)?;
+        Ok(())
+    }
+
+    fn lines_at(data_file: &str, data_index: &DataIndex, i_seq: usize, pos: u32)
+                -> Result<Vec<String>, Error> {
+        let mut bgzf = BGZFReader::new(File::open(data_file)?);
+        let mut lines = Vec::<String>::new();
+        for vpos in data_index.seek_vposes(&data_index.sequences[i_seq], pos, pos) {
+            bgzf.bgzf_seek(vpos)?;
+            let mut line = String::new();
+            bgzf.read_line(&mut line)?;
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+
+    fn build_and_read_index(index_type: IndexType, extension: &str) -> Result<(), Error> {
+        let data_file = format!("tmp/index_data_{}.tsv.gz", extension);
+        write_data_file(&data_file)?;
+        let index_file = format!("{}.{}", data_file, extension);
+        let index_format = IndexFormat::for_preset(None)?;
+        let config =
+            IndexConfig { data_file: data_file.clone(), index_file: index_file.clone(),
+                index_format, index_type };
+        build_index(&config)?;
+        let data_index = DataIndex::from_file(&index_file)?;
+        assert_eq!(data_index.names, vec!(String::from("1"), String::from("2")));
+        assert_eq!(data_index.sequences.len(), 2);
+        assert_eq!(data_index.format.col_seq, 1);
+        assert_eq!(data_index.format.col_begin, 2);
+        assert_eq!(lines_at(&data_file, &data_index, 0, 100)?, vec!("1\t100\n"));
+        assert_eq!(lines_at(&data_file, &data_index, 0, 20000)?, vec!("1\t20000\n"));
+        assert_eq!(lines_at(&data_file, &data_index, 1, 300)?, vec!("2\t300\n"));
+        assert_eq!(lines_at(&data_file, &data_index, 1, 40000)?, vec!("2\t40000"));
+        Ok(())
+    }
+
+    #[test]
+    fn write_and_read_tbi() -> Result<(), Error> {
+        build_and_read_index(IndexType::Tbi, "tbi")
+    }
+
+    #[test]
+    fn write_and_read_csi() -> Result<(), Error> {
+        build_and_read_index(IndexType::Csi(Binning::new(14, 5)?), "csi")
+    }
+}
@@ -0,0 +1,216 @@
+use std::cmp;
+use std::io::{BufWriter, Write};
+use fs_err::File;
+use bgzip::BGZFReader;
+use crate::util::error::Error;
+use crate::util::error_policy::ErrorPolicy;
+use crate::config::QueryConfig;
+use crate::cache::run::load_chrom_aliases;
+use crate::cache::regions;
+use crate::genomics::variant::Pos;
+use crate::tabix::bgzf;
+use crate::tabix::data_index::DataIndex;
+use crate::tabix::tsv;
+
+struct QueryRegion {
+    chrom: String,
+    begin: Pos,
+    end: Pos,
+}
+
+fn parse_pos(pos_str: &str) -> Result<Pos, Error> {
+    Ok(pos_str.replace(',', "").parse::<Pos>()?)
+}
+
+fn parse_range(range: &str) -> Result<(Pos, Pos), Error> {
+    match range.split_once('-') {
+        None => { Ok((parse_pos(range)?, Pos::MAX)) }
+        Some((begin, "")) => { Ok((parse_pos(begin)?, Pos::MAX)) }
+        Some((begin, end)) => { Ok((parse_pos(begin)?, parse_pos(end)?)) }
+    }
+}
+
+impl QueryRegion {
+    fn new(chrom: String, begin: Pos, end: Pos) -> Result<QueryRegion, Error> {
+        if begin == 0 || end < begin {
+            Err(Error::from(format!("Invalid region {}:{}-{}.", chrom, begin, end)))
+        } else {
+            Ok(QueryRegion { chrom, begin, end })
+        }
+    }
+    // chrom, chrom:begin or chrom:begin-end, with 1-based, inclusive positions
+    fn parse(region: &str) -> Result<QueryRegion, Error> {
+        match region.rsplit_once(':') {
+            Some((chrom, range)) if !range.is_empty() => {
+                let (begin, end) = parse_range(range).map_err(|_| {
+                    Error::from(format!("Cannot parse region '{}'.", region))
+                })?;
+                QueryRegion::new(String::from(chrom), begin, end)
+            }
+            _ => { QueryRegion::new(String::from(region), 1, Pos::MAX) }
+        }
+    }
+}
+
+fn write_region(bgzf: &mut BGZFReader<File>, data_index: &DataIndex, i_seq: usize,
+                region: &QueryRegion, write: &mut impl Write) -> Result<(), Error> {
+    let sequence = &data_index.sequences[i_seq];
+    let name = &data_index.names[i_seq];
+    let region_beg = (region.begin - 1) as u64;
+    let region_end = region.end as u64;
+    let mut line = Vec::<u8>::new();
+    let mut vpos_read_until: u64 = 0;
+    for vpos in data_index.seek_vposes(sequence, region.begin, region.end) {
+        if vpos < vpos_read_until {
+            continue;
+        }
+        bgzf.bgzf_seek(vpos)?;
+        loop {
+            line.clear();
+            if bgzf::read_line(bgzf, &mut line)? == 0 {
+                break;
+            }
+            if line.first() == Some(&data_index.format.meta_char) {
+                continue;
+            }
+            let (seq, beg, end) = data_index.format.parse_record(std::str::from_utf8(&line)?)?;
+            if seq != name || beg >= region_end {
+                break;
+            }
+            if cmp::max(end, beg + 1) > region_beg {
+                write.write_all(&line)?;
+                if line.last() != Some(&b'\n') {
+                    write.write_all(b"\n")?;
+                }
+            }
+        }
+        vpos_read_until = bgzf.bgzf_pos();
+    }
+    Ok(())
+}
+
+pub(crate) fn query(config: &QueryConfig) -> Result<(), Error> {
+    let chrom_aliases = load_chrom_aliases(&config.chroms_config)?;
+    let data_index = DataIndex::from_file(&config.index_file)?;
+    let mut regions = Vec::<QueryRegion>::new();
+    for region in &config.regions {
+        regions.push(QueryRegion::parse(region)?);
+    }
+    if let Some(regions_file) = &config.regions_file_opt {
+        for region in regions::read_regions(regions_file, config.regions_format_opt,
+                                                  &ErrorPolicy::strict())? {
+            let (begin, end) = (region.begin(), region.end() - 1);
+            regions.push(QueryRegion::new(region.chrom, begin, end)?);
+        }
+    }
+    let mut bgzf = BGZFReader::new(File::open(&config.data_file)?);
+    let header_line = tsv::get_header_line(&mut bgzf)?;
+    let mut write: BufWriter<Box<dyn Write>> =
+        match &config.output_file_opt {
+            None => { BufWriter::new(Box::new(std::io::stdout())) }
+            Some(output_file) => { BufWriter::new(Box::new(File::create(output_file)?)) }
+        };
+    if header_line.as_bytes().first() == Some(&data_index.format.meta_char) {
+        writeln!(write, "{}", header_line)?;
+    }
+    for region in &regions {
+        if let Some(i_seq) = data_index.i_sequence(&region.chrom, &chrom_aliases) {
+            write_region(&mut bgzf, &data_index, i_seq, region, &mut write)?;
+        }
+    }
+    write.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use bgzip::BGZFReader;
+    use fs_err::File;
+    use crate::config::BgzipConfig;
+    use crate::genomics::variant::Pos;
+    use crate::tabix::data_index::DataIndex;
+    use crate::tabix::index_builder::IndexFormat;
+    use crate::tabix::tabix_index::IndexType;
+    use crate::tools;
+    use crate::tools::query::{write_region, QueryRegion};
+    use crate::util::error::Error;
+
+    fn assert_region(region: &str, chrom: &str, begin: Pos, end: Pos) -> Result<(), Error> {
+        let parsed = QueryRegion::parse(region)?;
+        assert_eq!((parsed.chrom.as_str(), parsed.begin, parsed.end), (chrom, begin, end),
+                   "Wrong parse of region '{}'", region);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_regions() -> Result<(), Error> {
+        assert_region("chr1", "chr1", 1, Pos::MAX)?;
+        assert_region("1:100", "1", 100, Pos::MAX)?;
+        assert_region("1:100-", "1", 100, Pos::MAX)?;
+        assert_region("X:1,000-2,000", "X", 1000, 2000)?;
+        assert_region("HLA-A:5-5", "HLA-A", 5, 5)?;
+        for region in ["1:abc", "1:100-abc", "1:200-100", "1:0-100", "1:-100"] {
+            assert!(QueryRegion::parse(region).is_err(), "Region '{}' should not parse", region);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn write_overlapping_lines() -> Result<(), Error> {
+        let plain_file = "tmp/query_data.tsv";
+        File::create(plain_file)?
+            .write_all(b"#chrom\tpos\n1\t100\n1\t150\n1\t200\n1\t250\n2\t150\n")?;
+        let data_file = format!("{}.gz", plain_file);
+        let index_file = format!("{}.tbi", data_file);
+        let config = BgzipConfig {
+            input_file: String::from(plain_file),
+            output_file: data_file.clone(),
+            threads: 1,
+            index_file_opt: Some(index_file.clone()),
+            index_format: IndexFormat::for_preset(None)?,
+            index_type: IndexType::Tbi,
+        };
+        tools::bgzip::compress(&config)?;
+        let data_index = DataIndex::from_file(&index_file)?;
+        let mut bgzf = BGZFReader::new(File::open(&data_file)?);
+        let mut written = Vec::<u8>::new();
+        let region = QueryRegion::parse("1:120-200")?;
+        write_region(&mut bgzf, &data_index, 0, &region, &mut written)?;
+        let region = QueryRegion::parse("2")?;
+        write_region(&mut bgzf, &data_index, 1, &region, &mut written)?;
+        let region = QueryRegion::parse("1:300-400")?;
+        write_region(&mut bgzf, &data_index, 0, &region, &mut written)?;
+        assert_eq!(String::from_utf8(written)?, "1\t150\n1\t200\n2\t150\n");
+        Ok(())
+    }
+
+    #[test]
+    fn write_vcf_lines_overlapping_end() -> Result<(), Error> {
+        let plain_file = "tmp/query_sv.vcf";
+        File::create(plain_file)?
+            .write_all(b"#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            1\t100\t.\tA\t<DEL>\t.\t.\tSVTYPE=DEL;END=50000\n\
+            1\t200\t.\tC\tT\t.\t.\tMYEND=60000\n\
+            1\t60000\t.\tG\tA\t.\t.\t.\n")?;
+        let data_file = format!("{}.gz", plain_file);
+        let index_file = format!("{}.tbi", data_file);
+        let config = BgzipConfig {
+            input_file: String::from(plain_file),
+            output_file: data_file.clone(),
+            threads: 1,
+            index_file_opt: Some(index_file.clone()),
+            index_format: IndexFormat::for_preset(Some("vcf"))?,
+            index_type: IndexType::Tbi,
+        };
+        tools::bgzip::compress(&config)?;
+        let data_index = DataIndex::from_file(&index_file)?;
+        let mut bgzf = BGZFReader::new(File::open(&data_file)?);
+        let mut written = Vec::<u8>::new();
+        let region = QueryRegion::parse("1:40000-40010")?;
+        write_region(&mut bgzf, &data_index, 0, &region, &mut written)?;
+        assert_eq!(String::from_utf8(written)?,
+                   "1\t100\t.\tA\t<DEL>\t.\t.\tSVTYPE=DEL;END=50000\n");
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 pub(crate) mod error;
+pub(crate) mod error_policy;
 pub(crate) mod iter_util;
 pub(crate) mod path_util;
 pub(crate) mod sh_util;
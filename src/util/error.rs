@@ -28,16 +28,6 @@ pub(crate) trait Reporter {
     fn report(&self, error: Error);
 }
 
-pub(crate) fn handle_result<T>(result: Result<T, Error>) -> Option<T> {
-    match result {
-        Ok(value) => Some(value),
-        Err(error) => {
-            println!("Error: {}", error);
-            None
-        }
-    }
-}
-
 impl Error {
     pub fn is_clap_pseudo_error(&self) -> bool {
         if let Error::Clap(clap_error) = &self {
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fs_err::File;
+use crate::util::error::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorMode {
+    Strict,
+    Lenient { max_errors_opt: Option<usize> },
+    Quarantine,
+}
+
+// What to do with malformed lines: abort, report and skip, or write them to a rejects file.
+// Shared between threads, hence the atomic counter and the mutexes.
+pub(crate) struct ErrorPolicy {
+    mode: ErrorMode,
+    n_errors: AtomicUsize,
+    rejects_opt: Option<Mutex<BufWriter<File>>>,
+    keys_seen: Mutex<HashSet<String>>,
+}
+
+impl ErrorMode {
+    pub(crate) fn parse(mode: &str, max_errors_opt: Option<usize>) -> Result<ErrorMode, Error> {
+        match (mode, max_errors_opt) {
+            ("lenient", _) => { Ok(ErrorMode::Lenient { max_errors_opt }) }
+            ("strict" | "quarantine", Some(_)) => {
+                Err(Error::from(format!("A maximum number of errors only applies to error \
+                policy lenient, not {}.", mode)))
+            }
+            ("strict", None) => { Ok(ErrorMode::Strict) }
+            ("quarantine", None) => { Ok(ErrorMode::Quarantine) }
+            _ => {
+                Err(Error::from(format!("Unknown error policy '{}', need 'strict', 'lenient' \
+                or 'quarantine'.", mode)))
+            }
+        }
+    }
+}
+
+impl ErrorPolicy {
+    pub(crate) fn new(mode: ErrorMode, rejects_file_opt: &Option<String>)
+                      -> Result<ErrorPolicy, Error> {
+        let rejects_opt = match (mode, rejects_file_opt) {
+            (ErrorMode::Quarantine, None) => {
+                return Err(Error::from("Error policy quarantine needs a rejects file."));
+            }
+            (ErrorMode::Quarantine, Some(rejects_file)) => {
+                let mut write = BufWriter::new(File::create(rejects_file)?);
+                writeln!(write, "#Source\tError\tLine")?;
+                Some(Mutex::new(write))
+            }
+            (_, Some(_)) => {
+                return Err(Error::from("A rejects file is only used with error policy \
+                quarantine."));
+            }
+            (_, None) => { None }
+        };
+        let keys_seen = Mutex::new(HashSet::new());
+        Ok(ErrorPolicy { mode, n_errors: AtomicUsize::new(0), rejects_opt, keys_seen })
+    }
+    pub(crate) fn strict() -> ErrorPolicy {
+        let keys_seen = Mutex::new(HashSet::new());
+        ErrorPolicy {
+            mode: ErrorMode::Strict, n_errors: AtomicUsize::new(0), rejects_opt: None, keys_seen
+        }
+    }
+    pub(crate) fn n_errors(&self) -> usize { self.n_errors.load(Ordering::Relaxed) }
+    // Ok if the line is to be skipped, Err if processing is to be aborted
+    pub(crate) fn handle(&self, source: &str, line: &str, error: Error) -> Result<(), Error> {
+        let n_errors = self.n_errors.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.mode {
+            ErrorMode::Strict => { Err(Error::from(format!("{}: {}", source, error))) }
+            ErrorMode::Lenient { max_errors_opt } => {
+                eprintln!("Warning: skipping {}: {}", source, error);
+                match max_errors_opt {
+                    Some(max_errors) if n_errors > max_errors => {
+                        Err(Error::from(format!("Aborting after {} errors, more than the \
+                        maximum of {}.", n_errors, max_errors)))
+                    }
+                    _ => { Ok(()) }
+                }
+            }
+            ErrorMode::Quarantine => {
+                if let Some(rejects) = &self.rejects_opt {
+                    let mut write = rejects.lock().map_err(|_| {
+                        Error::from("Rejects file is unusable after a thread panicked.")
+                    })?;
+                    let line = line.trim_end_matches(&['\n', '\r'][..]);
+                    writeln!(write, "{}\t{}\t{}", source, error, line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+    // for lines that may be read more than once, like data file lines read again after a seek,
+    // which are counted and reported only the first time they come with a key
+    pub(crate) fn handle_once(&self, key: &str, source: &str, line: &str, error: Error)
+                              -> Result<(), Error> {
+        let is_new = self.keys_seen.lock().map_err(|_| {
+            Error::from("Error policy is unusable after a thread panicked.")
+        })?.insert(String::from(key));
+        if is_new { self.handle(source, line, error) } else { Ok(()) }
+    }
+    pub(crate) fn flush(&self) -> Result<(), Error> {
+        if let Some(rejects) = &self.rejects_opt {
+            let mut write = rejects.lock().map_err(|_| {
+                Error::from("Rejects file is unusable after a thread panicked.")
+            })?;
+            write.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::error::Error;
+    use crate::util::error_policy::{ErrorMode, ErrorPolicy};
+
+    #[test]
+    fn handle_errors_by_mode() -> Result<(), Error> {
+        let strict = ErrorPolicy::strict();
+        assert!(strict.handle("line 1", "x", Error::from("bad")).is_err());
+        let lenient = ErrorPolicy::new(ErrorMode::parse("lenient", Some(1))?, &None)?;
+        assert!(lenient.handle("line 1", "x", Error::from("bad")).is_ok());
+        assert!(lenient.handle("line 2", "y", Error::from("bad")).is_err());
+        let rejects_file = String::from("tmp/rejects.tsv");
+        let quarantine =
+            ErrorPolicy::new(ErrorMode::parse("quarantine", None)?, &Some(rejects_file.clone()))?;
+        quarantine.handle("line 3", "z\n", Error::from("bad"))?;
+        quarantine.flush()?;
+        assert_eq!(quarantine.n_errors(), 1);
+        assert_eq!(std::fs::read_to_string(rejects_file)?,
+                   "#Source\tError\tLine\nline 3\tbad\tz\n");
+        Ok(())
+    }
+
+    #[test]
+    fn handle_lines_read_again_once() -> Result<(), Error> {
+        let lenient = ErrorPolicy::new(ErrorMode::parse("lenient", Some(1))?, &None)?;
+        for _ in 0..3 {
+            lenient.handle_once("17", "line 1", "x", Error::from("bad"))?;
+        }
+        assert_eq!(lenient.n_errors(), 1);
+        assert!(lenient.handle_once("42", "line 1", "y", Error::from("bad")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn max_errors_only_for_lenient() {
+        assert!(ErrorMode::parse("lenient", Some(3)).is_ok());
+        assert!(ErrorMode::parse("strict", Some(3)).is_err());
+        assert!(ErrorMode::parse("quarantine", Some(3)).is_err());
+        assert!(ErrorMode::parse("quarantine", None).is_ok());
+    }
+}